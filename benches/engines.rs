@@ -0,0 +1,52 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use my_chip_8::cpu::{Cpu, Engine};
+use my_chip_8::debugger::Debugger;
+use my_chip_8::emulator::{run_frame, INSTRUCTIONS_PER_FRAME};
+
+const ROMS: [&str; 4] = [
+    "./roms/2-ibm-logo.ch8",
+    "./roms/invaders.ch8",
+    "./roms/pong.ch8",
+    "./roms/tetris.ch8",
+];
+const FRAMES: u32 = 60;
+
+fn boot(rom: &str, engine: Engine) -> Cpu {
+    let mut cpu = my_chip_8::cpu::new();
+    cpu.set_engine(engine);
+    cpu.load_fonts("./roms/fonts.ch8").unwrap();
+    cpu.load_rom(rom).unwrap();
+    cpu
+}
+
+/// One second of emulation per iteration, from a freshly booted ROM.
+fn engines(c: &mut Criterion) {
+    let mut group = c.benchmark_group("engines");
+    group.throughput(Throughput::Elements(
+        (FRAMES * INSTRUCTIONS_PER_FRAME) as u64,
+    ));
+
+    for rom in ROMS {
+        let name = rom.trim_start_matches("./roms/");
+        for engine in [Engine::Interpreter, Engine::Cached, Engine::Blocks] {
+            let id = BenchmarkId::new(format!("{engine:?}").to_lowercase(), name);
+            group.bench_with_input(id, &engine, |b, &engine| {
+                b.iter_batched(
+                    || boot(rom, engine),
+                    |mut cpu| {
+                        let mut debugger = Debugger::default();
+                        for _ in 0..FRAMES {
+                            run_frame(&mut cpu, &mut debugger, INSTRUCTIONS_PER_FRAME).unwrap();
+                        }
+                        cpu
+                    },
+                    criterion::BatchSize::SmallInput,
+                )
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, engines);
+criterion_main!(benches);
@@ -0,0 +1,10 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use my_chip_8::decoder::decode_instruction;
+
+fuzz_target!(|word: u16| {
+    let op_code = decode_instruction(word);
+    let _ = op_code.to_string();
+    assert_eq!(op_code.encode(), word, "{op_code}");
+});
@@ -0,0 +1,68 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use my_chip_8::cpu::{Cpu, Engine, MachineConfig};
+use my_chip_8::emulator::INSTRUCTIONS_PER_FRAME;
+use my_chip_8::lockstep::{compare, Outcome};
+use my_chip_8::memory::{MemorySize, OutOfBounds};
+use my_chip_8::mnemonic::Syntax;
+use my_chip_8::movie::Movie;
+use my_chip_8::platform::Platform;
+
+/// Frames of [INSTRUCTIONS_PER_FRAME] instructions run per input.
+const FRAMES: u32 = 20;
+
+/// The first byte picks the configuration, the next 16 the registers and the
+/// rest is loaded as the ROM.
+fn machine(data: &[u8], engine: Engine) -> Option<Cpu> {
+    let (&flags, rest) = data.split_first()?;
+    let registers: [u8; 16] = rest.get(..16)?.try_into().ok()?;
+    let rom = &rest[16..];
+
+    let memory_size = if flags & 1 == 0 {
+        MemorySize::Standard
+    } else {
+        MemorySize::Extended
+    };
+    let out_of_bounds = if flags & 2 == 0 {
+        OutOfBounds::Fault
+    } else {
+        OutOfBounds::Wrap
+    };
+    let platform = [
+        Platform::Chip8,
+        Platform::CosmacVip,
+        Platform::SuperChip,
+        Platform::XoChip,
+    ][(flags >> 2 & 3) as usize];
+
+    MachineConfig {
+        memory_size,
+        out_of_bounds,
+        platform,
+        engine,
+    }
+    .boot(rom, registers)
+    .ok()
+}
+
+fuzz_target!(|data: &[u8]| {
+    let (Some(mut interpreter), Some(mut blocks)) = (
+        machine(data, Engine::Interpreter),
+        machine(data, Engine::Blocks),
+    ) else {
+        return;
+    };
+    let outcome = compare(
+        &mut interpreter,
+        &mut blocks,
+        &Movie::default(),
+        FRAMES,
+        INSTRUCTIONS_PER_FRAME,
+        Syntax::Cowgod,
+    );
+    if let Outcome::Diverged(divergence) = outcome {
+        panic!("the engines disagree\n{divergence}");
+    }
+    assert!(interpreter.stack().len() <= interpreter.stack().depth());
+});
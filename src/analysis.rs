@@ -0,0 +1,604 @@
+use crate::decoder::decode_instruction;
+use crate::mnemonic::{Mnemonic, Syntax};
+use crate::opcode::OpCode;
+use crate::platform::Platform;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+use std::ops::RangeInclusive;
+
+/// `BNNN` table entries followed before giving up on finding the end of the table.
+const MAX_TABLE_ENTRIES: u16 = 64;
+
+/// How control gets from one instruction to another.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum EdgeKind {
+    /// The following instruction, including the return address of a call.
+    Next,
+    /// The instruction after the following one, when a skip is taken.
+    Skip,
+    Jump,
+    Call,
+    /// A `JP` in the table a `BNNN` indexes, see [`analyze`].
+    Table,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Edge {
+    pub target: u16,
+    pub kind: EdgeKind,
+}
+
+/// Straight-line code: only the first instruction is a branch target and only
+/// the last one branches.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Block {
+    pub start: u16,
+    pub instructions: Vec<(u16, OpCode)>,
+    pub edges: Vec<Edge>,
+}
+
+/// An instruction whose result depends on one of the [`Quirks`](crate::platform::Quirks).
+#[derive(Clone, Debug, PartialEq)]
+pub struct QuirkHint {
+    pub address: u16,
+    pub op_code: OpCode,
+    /// Name of the `Quirks` field.
+    pub quirk: &'static str,
+}
+
+/// A store through an `I` set by a constant `ANNN` that overwrites reachable code.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CodeWrite {
+    pub address: u16,
+    pub target: RangeInclusive<u16>,
+}
+
+/// What can be learnt from a ROM without running it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Analysis {
+    /// The ROM at its load address, preceded by zeroes.
+    pub memory: Vec<u8>,
+    pub blocks: Vec<Block>,
+    /// Number of reachable instructions per opcode family, e.g. `8XY6`.
+    pub families: BTreeMap<&'static str, usize>,
+    /// The least capable platform that runs every reachable instruction.
+    pub platform: Platform,
+    /// Reachable instructions that need `platform`, e.g. SCHIP's `00FF`.
+    pub extensions: Vec<(u16, OpCode)>,
+    pub quirks: Vec<QuirkHint>,
+    pub code_writes: Vec<CodeWrite>,
+}
+
+/// Builds the control-flow graph from `origin`, following jumps, calls and both
+/// sides of skips. A `BNNN` is assumed to index a table of `JP`s starting at NNN.
+/// Returns, `00FD`, unknown instructions and running off the ROM end a path.
+pub fn analyze(rom: &[u8], origin: u16) -> Analysis {
+    let mut memory = vec![0; origin as usize];
+    memory.extend_from_slice(rom);
+    let fetch = |address: u16| {
+        let start = address as usize;
+        memory
+            .get(start..start + 2)
+            .filter(|_| address >= origin)
+            .map(|bytes| decode_instruction(u16::from_be_bytes([bytes[0], bytes[1]])))
+    };
+
+    let mut instructions: BTreeMap<u16, (OpCode, Vec<Edge>)> = BTreeMap::new();
+    let mut pending = vec![origin];
+    while let Some(address) = pending.pop() {
+        if instructions.contains_key(&address) {
+            continue;
+        }
+        let Some(op_code) = fetch(address) else {
+            continue;
+        };
+        let next = address.wrapping_add(op_code.size());
+        let edge = |target: u16, kind: EdgeKind| Edge { target, kind };
+        let edges = match op_code {
+            OpCode::Jump(target) => vec![edge(target, EdgeKind::Jump)],
+            OpCode::CallSubroutine(target) => {
+                vec![edge(target, EdgeKind::Call), edge(next, EdgeKind::Next)]
+            }
+            OpCode::JumpWithV0Offset(table) => (0..MAX_TABLE_ENTRIES)
+                .map(|entry| table.wrapping_add(entry * 2))
+                .take_while(|&entry| matches!(fetch(entry), Some(OpCode::Jump(_))))
+                .map(|entry| edge(entry, EdgeKind::Table))
+                .collect(),
+            _ if is_skip(op_code) => {
+                let skipped = fetch(next).map_or(2, OpCode::size);
+                vec![
+                    edge(next, EdgeKind::Next),
+                    edge(next.wrapping_add(skipped), EdgeKind::Skip),
+                ]
+            }
+            OpCode::RetFromSubroutine | OpCode::Unknown(0x00FD) => vec![],
+            OpCode::Unknown(word) if extension(word).is_none() => vec![],
+            _ => vec![edge(next, EdgeKind::Next)],
+        };
+        pending.extend(edges.iter().map(|edge| edge.target));
+        instructions.insert(address, (op_code, edges));
+    }
+
+    let mut analysis = Analysis {
+        blocks: blocks(&instructions, origin),
+        memory,
+        families: BTreeMap::new(),
+        platform: Platform::Chip8,
+        extensions: vec![],
+        quirks: vec![],
+        code_writes: vec![],
+    };
+    for (&address, &(op_code, _)) in &instructions {
+        *analysis.families.entry(family(op_code)).or_default() += 1;
+        let required = match op_code {
+            OpCode::LoadIndexLong => Some(Platform::XoChip),
+            OpCode::Unknown(word) => extension(word),
+            _ => None,
+        };
+        if let Some(required) = required {
+            analysis.extensions.push((address, op_code));
+            if analysis.platform != Platform::XoChip {
+                analysis.platform = required;
+            }
+        }
+    }
+    for block in &analysis.blocks {
+        analysis.quirks.extend(quirk_hints(block));
+        analysis
+            .code_writes
+            .extend(code_writes(block, &instructions));
+    }
+    analysis
+}
+
+impl Analysis {
+    pub fn instruction_count(&self) -> usize {
+        self.blocks
+            .iter()
+            .map(|block| block.instructions.len())
+            .sum()
+    }
+
+    /// Plain-text summary of the ROM's platform, opcode usage, quirk
+    /// sensitivity and self-modifying code.
+    pub fn report(&self, syntax: Syntax) -> String {
+        let mut report = format!(
+            "{} reachable instructions in {} blocks\nplatform: {:?}\n",
+            self.instruction_count(),
+            self.blocks.len(),
+            self.platform
+        );
+        for &(address, op_code) in &self.extensions {
+            let _ = writeln!(
+                report,
+                "  {address:#06X}  {}",
+                self.mnemonic(address, op_code, syntax)
+            );
+        }
+
+        report.push_str("\nopcode families\n");
+        for (family, count) in &self.families {
+            let _ = writeln!(report, "  {family:<10} {count:>6}");
+        }
+
+        report.push_str("\nquirk sensitivity\n");
+        for hint in &self.quirks {
+            let _ = writeln!(
+                report,
+                "  {:#06X}  {:<28} {}",
+                hint.address,
+                hint.op_code.mnemonic(syntax).to_string(),
+                hint.quirk
+            );
+        }
+
+        report.push_str("\nself-modifying code\n");
+        for write in &self.code_writes {
+            let _ = writeln!(
+                report,
+                "  {:#06X}  writes {:#06X}..={:#06X}",
+                write.address,
+                write.target.start(),
+                write.target.end()
+            );
+        }
+        report
+    }
+
+    /// The control-flow graph in Graphviz DOT, one box per block.
+    pub fn to_dot(&self, syntax: Syntax) -> String {
+        let mut dot = String::from("digraph cfg {\n  node [shape=box, fontname=monospace];\n");
+        for block in &self.blocks {
+            let mut label = String::new();
+            for &(address, op_code) in &block.instructions {
+                let mnemonic = self.mnemonic(address, op_code, syntax).to_string();
+                let _ = write!(
+                    label,
+                    "{address:#06X}  {}\\l",
+                    mnemonic.replace('"', "\\\"")
+                );
+            }
+            let _ = writeln!(dot, "  \"{:#06X}\" [label=\"{label}\"];", block.start);
+            for edge in &block.edges {
+                let _ = writeln!(
+                    dot,
+                    "  \"{:#06X}\" -> \"{:#06X}\" [label=\"{:?}\"];",
+                    block.start, edge.target, edge.kind
+                );
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    fn mnemonic(&self, address: u16, op_code: OpCode, syntax: Syntax) -> Mnemonic {
+        let mnemonic = op_code.mnemonic(syntax);
+        let start = address as usize + 2;
+        match (op_code, self.memory.get(start..start + 2)) {
+            (OpCode::LoadIndexLong, Some(bytes)) => {
+                mnemonic.with_long_address(u16::from_be_bytes([bytes[0], bytes[1]]))
+            }
+            _ => mnemonic,
+        }
+    }
+}
+
+/// Splits the reachable instructions at branch targets and after branches.
+fn blocks(instructions: &BTreeMap<u16, (OpCode, Vec<Edge>)>, origin: u16) -> Vec<Block> {
+    let mut leaders = BTreeSet::from([origin]);
+    for (&address, (op_code, edges)) in instructions {
+        let falls_through = matches!(
+            edges.as_slice(),
+            [Edge {
+                kind: EdgeKind::Next,
+                ..
+            }]
+        );
+        if !falls_through {
+            leaders.extend(edges.iter().map(|edge| edge.target));
+            leaders.insert(address.wrapping_add(op_code.size()));
+        }
+    }
+
+    let mut blocks: Vec<Block> = vec![];
+    let mut expected = None;
+    for (&address, (op_code, edges)) in instructions {
+        match blocks.last_mut() {
+            Some(block) if expected == Some(address) && !leaders.contains(&address) => {
+                block.instructions.push((address, *op_code));
+                block.edges.clone_from(edges);
+            }
+            _ => blocks.push(Block {
+                start: address,
+                instructions: vec![(address, *op_code)],
+                edges: edges.clone(),
+            }),
+        }
+        let falls_through = matches!(
+            edges.as_slice(),
+            [Edge {
+                kind: EdgeKind::Next,
+                ..
+            }]
+        );
+        expected = falls_through.then(|| address.wrapping_add(op_code.size()));
+    }
+    blocks
+}
+
+/// `shift_uses_vy` for shifts between two registers, `load_store_increments_index`
+/// when `I` is used again after `FX55`/`FX65` before being set, `jump_uses_vx` for
+/// `BNNN` with a non-zero X and `logic_resets_vf` when VF is read after a logic
+/// instruction before being written.
+fn quirk_hints(block: &Block) -> Vec<QuirkHint> {
+    let mut hints = vec![];
+    for (position, &(address, op_code)) in block.instructions.iter().enumerate() {
+        let rest = || {
+            block.instructions[position + 1..]
+                .iter()
+                .map(|&(_, op_code)| op_code)
+        };
+        let quirk = match op_code {
+            OpCode::ShiftRightRegisterFromRegister(x, y)
+            | OpCode::ShiftLeftRegisterFromRegister(x, y)
+                if x != y =>
+            {
+                Some("shift_uses_vy")
+            }
+            OpCode::LoadFromRegistersToMemory(_) | OpCode::LoadFromMemoryToRegisters(_)
+                if rest()
+                    .find(|&op_code| uses_index(op_code) || sets_index(op_code))
+                    .is_some_and(uses_index) =>
+            {
+                Some("load_store_increments_index")
+            }
+            OpCode::JumpWithV0Offset(nnn) if nnn >> 8 != 0 => Some("jump_uses_vx"),
+            OpCode::SetRegisterToRegisterValueUsingOR(..)
+            | OpCode::SetRegisterToRegisterValueUsingAND(..)
+            | OpCode::SetRegisterToRegisterValueUsingXOR(..)
+                if rest()
+                    .find(|&op_code| reads_vf(op_code) || writes_vf(op_code))
+                    .is_some_and(reads_vf) =>
+            {
+                Some("logic_resets_vf")
+            }
+            _ => None,
+        };
+        if let Some(quirk) = quirk {
+            hints.push(QuirkHint {
+                address,
+                op_code,
+                quirk,
+            });
+        }
+    }
+    hints
+}
+
+/// Follows `I` through the block from `ANNN` to the stores that use it.
+fn code_writes(block: &Block, instructions: &BTreeMap<u16, (OpCode, Vec<Edge>)>) -> Vec<CodeWrite> {
+    let is_code = |target: &RangeInclusive<u16>| {
+        instructions
+            .range(..=*target.end())
+            .next_back()
+            .is_some_and(|(&address, (op_code, _))| {
+                address.saturating_add(op_code.size() - 1) >= *target.start()
+            })
+    };
+    let mut writes = vec![];
+    let mut index: Option<u16> = None;
+    for &(address, op_code) in &block.instructions {
+        let length = match op_code {
+            OpCode::LoadFromRegistersToMemory(x) => Some(x as u16 + 1),
+            OpCode::StoreBCDRepresentationOfRegister(_) => Some(3),
+            _ => None,
+        };
+        if let (Some(start), Some(length)) = (index, length) {
+            let target = start..=start.saturating_add(length - 1);
+            if is_code(&target) {
+                writes.push(CodeWrite { address, target });
+            }
+        }
+        index = match op_code {
+            OpCode::SetIndex(nnn) => Some(nnn),
+            _ if sets_index(op_code) => None,
+            OpCode::LoadFromRegistersToMemory(_) | OpCode::LoadFromMemoryToRegisters(_) => None,
+            _ => index,
+        };
+    }
+    writes
+}
+
+fn is_skip(op_code: OpCode) -> bool {
+    matches!(
+        op_code,
+        OpCode::SkipIfRegisterEquals(..)
+            | OpCode::SkipIfRegisterNotEquals(..)
+            | OpCode::SkipIfBothRegistersEqual(..)
+            | OpCode::SkipIfBothRegistersNotEqual(..)
+            | OpCode::SkipIfKey(_)
+            | OpCode::SkipIfNotKey(_)
+    )
+}
+
+fn uses_index(op_code: OpCode) -> bool {
+    matches!(
+        op_code,
+        OpCode::Draw(..)
+            | OpCode::AddRegisterValueToIndex(_)
+            | OpCode::StoreBCDRepresentationOfRegister(_)
+            | OpCode::LoadFromRegistersToMemory(_)
+            | OpCode::LoadFromMemoryToRegisters(_)
+    )
+}
+
+fn sets_index(op_code: OpCode) -> bool {
+    matches!(
+        op_code,
+        OpCode::SetIndex(_) | OpCode::LoadIndexLong | OpCode::LoadSpriteRepresentationInMemory(_)
+    )
+}
+
+fn reads_vf(op_code: OpCode) -> bool {
+    match op_code {
+        OpCode::SkipIfRegisterEquals(x, _) | OpCode::SkipIfRegisterNotEquals(x, _) => x == 0xF,
+        OpCode::SkipIfBothRegistersEqual(x, y)
+        | OpCode::SkipIfBothRegistersNotEqual(x, y)
+        | OpCode::SetRegisterToRegisterValueUsingOR(x, y)
+        | OpCode::SetRegisterToRegisterValueUsingAND(x, y)
+        | OpCode::SetRegisterToRegisterValueUsingXOR(x, y)
+        | OpCode::AddRegisterToRegister(x, y)
+        | OpCode::SubRegisterToRegister(x, y)
+        | OpCode::SubRegisterToRegisterReverse(x, y)
+        | OpCode::ShiftRightRegisterFromRegister(x, y)
+        | OpCode::ShiftLeftRegisterFromRegister(x, y) => x == 0xF || y == 0xF,
+        OpCode::SetRegisterToRegisterValue(_, y) => y == 0xF,
+        OpCode::AddRegister { register, .. } => register == 0xF,
+        OpCode::Draw(x, y, _) => x == 0xF || y == 0xF,
+        OpCode::SkipIfKey(x) | OpCode::SkipIfNotKey(x) => x == 0xF,
+        OpCode::SetDelayTimerFromRegister(x)
+        | OpCode::SetSoundTimerFromRegister(x)
+        | OpCode::AddRegisterValueToIndex(x)
+        | OpCode::LoadSpriteRepresentationInMemory(x)
+        | OpCode::StoreBCDRepresentationOfRegister(x)
+        | OpCode::LoadFromRegistersToMemory(x) => x == 0xF,
+        _ => false,
+    }
+}
+
+fn writes_vf(op_code: OpCode) -> bool {
+    match op_code {
+        OpCode::AddRegisterToRegister(..)
+        | OpCode::SubRegisterToRegister(..)
+        | OpCode::SubRegisterToRegisterReverse(..)
+        | OpCode::ShiftRightRegisterFromRegister(..)
+        | OpCode::ShiftLeftRegisterFromRegister(..)
+        | OpCode::Draw(..) => true,
+        OpCode::SetRegister { register, .. } | OpCode::SetRegisterWithRandom(register, _) => {
+            register == 0xF
+        }
+        OpCode::SetRegisterToRegisterValue(x, _) | OpCode::SetRegisterFromDelayTimer(x) => x == 0xF,
+        OpCode::GetKey(x) => x == 0xF,
+        OpCode::LoadFromMemoryToRegisters(x) => x == 0xF,
+        _ => false,
+    }
+}
+
+/// The platform introducing an instruction the decoder doesn't know: SCHIP's
+/// `00CN`, `00FB` to `00FF`, `FX30`, `FX75` and `FX85`, or XO-CHIP's `00DN`,
+/// `5XY2`, `5XY3`, `F002` and `FN01`.
+pub fn extension(word: u16) -> Option<Platform> {
+    if word & 0xFFF0 == 0x00D0
+        || matches!(word & 0xF00F, 0x5002 | 0x5003)
+        || word == 0xF002
+        || word & 0xF0FF == 0xF001
+    {
+        Some(Platform::XoChip)
+    } else if word & 0xFFF0 == 0x00C0
+        || matches!(word, 0x00FB..=0x00FF)
+        || matches!(word & 0xF0FF, 0xF030 | 0xF075 | 0xF085)
+    {
+        Some(Platform::SuperChip)
+    } else {
+        None
+    }
+}
+
+/// The instruction pattern, with X, Y, N and NNN as placeholders.
+pub fn family(op_code: OpCode) -> &'static str {
+    match op_code {
+        OpCode::ClearScreen => "00E0",
+        OpCode::RetFromSubroutine => "00EE",
+        OpCode::Jump(_) => "1NNN",
+        OpCode::CallSubroutine(_) => "2NNN",
+        OpCode::SkipIfRegisterEquals(..) => "3XNN",
+        OpCode::SkipIfRegisterNotEquals(..) => "4XNN",
+        OpCode::SkipIfBothRegistersEqual(..) => "5XY0",
+        OpCode::SetRegister { .. } => "6XNN",
+        OpCode::AddRegister { .. } => "7XNN",
+        OpCode::SetRegisterToRegisterValue(..) => "8XY0",
+        OpCode::SetRegisterToRegisterValueUsingOR(..) => "8XY1",
+        OpCode::SetRegisterToRegisterValueUsingAND(..) => "8XY2",
+        OpCode::SetRegisterToRegisterValueUsingXOR(..) => "8XY3",
+        OpCode::AddRegisterToRegister(..) => "8XY4",
+        OpCode::SubRegisterToRegister(..) => "8XY5",
+        OpCode::ShiftRightRegisterFromRegister(..) => "8XY6",
+        OpCode::SubRegisterToRegisterReverse(..) => "8XY7",
+        OpCode::ShiftLeftRegisterFromRegister(..) => "8XYE",
+        OpCode::SkipIfBothRegistersNotEqual(..) => "9XY0",
+        OpCode::SetIndex(_) => "ANNN",
+        OpCode::JumpWithV0Offset(_) => "BNNN",
+        OpCode::SetRegisterWithRandom(..) => "CXNN",
+        OpCode::Draw(..) => "DXYN",
+        OpCode::SkipIfKey(_) => "EX9E",
+        OpCode::SkipIfNotKey(_) => "EXA1",
+        OpCode::SetRegisterFromDelayTimer(_) => "FX07",
+        OpCode::GetKey(_) => "FX0A",
+        OpCode::SetDelayTimerFromRegister(_) => "FX15",
+        OpCode::SetSoundTimerFromRegister(_) => "FX18",
+        OpCode::AddRegisterValueToIndex(_) => "FX1E",
+        OpCode::LoadSpriteRepresentationInMemory(_) => "FX29",
+        OpCode::StoreBCDRepresentationOfRegister(_) => "FX33",
+        OpCode::LoadFromRegistersToMemory(_) => "FX55",
+        OpCode::LoadFromMemoryToRegisters(_) => "FX65",
+        OpCode::LoadIndexLong => "F000",
+        OpCode::Unknown(word) => match word {
+            _ if word & 0xFFF0 == 0x00C0 => "00CN",
+            _ if word & 0xFFF0 == 0x00D0 => "00DN",
+            0x00FB => "00FB",
+            0x00FC => "00FC",
+            0x00FD => "00FD",
+            0x00FE => "00FE",
+            0x00FF => "00FF",
+            0xF002 => "F002",
+            _ if word & 0xF00F == 0x5002 => "5XY2",
+            _ if word & 0xF00F == 0x5003 => "5XY3",
+            _ if word & 0xF0FF == 0xF001 => "FN01",
+            _ if word & 0xF0FF == 0xF030 => "FX30",
+            _ if word & 0xF0FF == 0xF075 => "FX75",
+            _ if word & 0xF0FF == 0xF085 => "FX85",
+            _ => "unknown",
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::analysis::{analyze, CodeWrite, Edge, EdgeKind};
+    use crate::mnemonic::Syntax;
+    use crate::platform::Platform;
+
+    #[test]
+    fn splits_blocks_at_skips_calls_and_jump_tables() {
+        let rom = [
+            0x30, 0x01, // 0x200: SE V0, 0x01
+            0x22, 0x0A, // 0x202: CALL 0x20A
+            0xB2, 0x06, // 0x204: JP V0, 0x206
+            0x12, 0x04, // 0x206: JP 0x204
+            0x12, 0x0C, // 0x208: JP 0x20C
+            0x00, 0xEE, // 0x20A: RET
+            0x00, 0xFF, // 0x20C: HIGH
+            0x12, 0x0C, // 0x20E: JP 0x20C
+        ];
+
+        let analysis = analyze(&rom, 0x200);
+
+        let starts: Vec<u16> = analysis.blocks.iter().map(|block| block.start).collect();
+        assert_eq!(starts, [0x200, 0x202, 0x204, 0x206, 0x208, 0x20A, 0x20C]);
+        assert_eq!(
+            analysis.blocks[2].edges,
+            [
+                Edge {
+                    target: 0x206,
+                    kind: EdgeKind::Table
+                },
+                Edge {
+                    target: 0x208,
+                    kind: EdgeKind::Table
+                }
+            ]
+        );
+        assert_eq!(analysis.blocks[6].instructions.len(), 2);
+        assert_eq!(analysis.platform, Platform::SuperChip);
+        assert_eq!(analysis.families["1NNN"], 3);
+        assert!(analysis
+            .to_dot(Syntax::Cowgod)
+            .contains("\"0x0200\" -> \"0x0204\" [label=\"Skip\"];"));
+    }
+
+    #[test]
+    fn flags_quirk_sensitive_and_self_modifying_code() {
+        let rom = [
+            0x81, 0x26, // 0x200: SHR V1, V2
+            0xA2, 0x00, // 0x202: LD I, 0x200
+            0xF1, 0x55, // 0x204: LD [I], V1
+            0xD0, 0x15, // 0x206: DRW V0, V1, 5
+            0x81, 0x21, // 0x208: OR V1, V2
+            0x3F, 0x00, // 0x20A: SE VF, 0x00
+            0x12, 0x00, // 0x20C: JP 0x200
+        ];
+
+        let analysis = analyze(&rom, 0x200);
+
+        let quirks: Vec<(u16, &str)> = analysis
+            .quirks
+            .iter()
+            .map(|hint| (hint.address, hint.quirk))
+            .collect();
+        assert_eq!(
+            quirks,
+            [
+                (0x200, "shift_uses_vy"),
+                (0x204, "load_store_increments_index"),
+                (0x208, "logic_resets_vf")
+            ]
+        );
+        assert_eq!(
+            analysis.code_writes,
+            [CodeWrite {
+                address: 0x204,
+                target: 0x200..=0x201
+            }]
+        );
+    }
+}
@@ -0,0 +1,52 @@
+use clap::Parser;
+use my_chip_8::analysis::analyze;
+use my_chip_8::container;
+use my_chip_8::database::Database;
+use my_chip_8::debugger::parse_address;
+use my_chip_8::mnemonic::Syntax;
+use std::fs;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+/// Statically analyses a ROM: reachable code, opcode usage, the platform it
+/// needs, quirk-sensitive instructions and self-modifying code.
+#[derive(Parser, Debug)]
+#[command(version)]
+struct Args {
+    rom: PathBuf,
+
+    /// Where the ROM is loaded, from the ROM database or its guessed platform by default
+    #[arg(long, value_name = "ADDR", value_parser = parse_address)]
+    load_address: Option<u16>,
+
+    /// Write the control-flow graph in Graphviz DOT format
+    #[arg(long, value_name = "FILE")]
+    dot: Option<PathBuf>,
+
+    /// Assembly syntax of the report and the graph
+    #[arg(long, value_enum, default_value_t = Syntax::Cowgod)]
+    syntax: Syntax,
+}
+
+fn run(args: &Args) -> Result<(), String> {
+    let rom = container::load(&args.rom)?.rom;
+    let profile = Database::bundled().identify(&rom);
+    let load_address = args.load_address.unwrap_or(profile.platform.load_address());
+    let analysis = analyze(&rom, load_address);
+    print!("{}", analysis.report(args.syntax));
+    if let Some(path) = &args.dot {
+        fs::write(path, analysis.to_dot(args.syntax))
+            .map_err(|e| format!("can't write {}: {e}", path.display()))?;
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    match run(&Args::parse()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{e}");
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,113 @@
+use clap::Parser;
+use my_chip_8::container;
+use my_chip_8::cpu::{Cpu, Engine};
+use my_chip_8::database::Database;
+use my_chip_8::lockstep::{compare, Outcome};
+use my_chip_8::memory::Memory;
+use my_chip_8::mnemonic::Syntax;
+use my_chip_8::movie::Movie;
+use my_chip_8::platform::Platform;
+use my_chip_8::stack::{Stack, StackStorage};
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+/// Runs a ROM on two emulator configurations in lockstep and reports the first
+/// instruction after which their registers, memory or screen differ.
+#[derive(Parser, Debug)]
+#[command(version)]
+struct Args {
+    rom: PathBuf,
+
+    /// Keys to hold on each frame, see `Movie` for the format
+    #[arg(long, value_name = "FILE")]
+    movie: Option<PathBuf>,
+
+    /// Number of frames to compare
+    #[arg(long, default_value_t = 600)]
+    frames: u32,
+
+    /// Instructions run per frame, from the ROM database or 50 by default
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    instructions_per_frame: Option<u32>,
+
+    /// Seed for `CXNN`, shared by both machines
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+
+    #[arg(long, value_enum, default_value_t = Engine::Interpreter)]
+    left_engine: Engine,
+
+    /// Platform whose call stack depth and quirks the first machine uses
+    #[arg(long, value_enum, default_value_t = Platform::Chip8)]
+    left_platform: Platform,
+
+    #[arg(long, value_enum, default_value_t = Engine::Blocks)]
+    right_engine: Engine,
+
+    /// Platform whose call stack depth and quirks the second machine uses
+    #[arg(long, value_enum, default_value_t = Platform::Chip8)]
+    right_platform: Platform,
+
+    /// Assembly syntax of the disassembly around the divergence
+    #[arg(long, value_enum, default_value_t = Syntax::Cowgod)]
+    syntax: Syntax,
+}
+
+fn boot(args: &Args, rom: &[u8], engine: Engine, platform: Platform) -> Result<Cpu, String> {
+    let mut cpu = my_chip_8::cpu::from_parts(
+        Memory::default(),
+        Stack::new(platform.stack_depth(), StackStorage::Internal),
+    );
+    cpu.set_engine(engine);
+    cpu.set_quirks(platform.quirks());
+    cpu.seed_random(args.seed);
+    cpu.load_fonts("./roms/fonts.ch8")?;
+    cpu.load_rom_at(rom, platform.load_address(), platform.load_address())?;
+    Ok(cpu)
+}
+
+fn run(args: &Args) -> Result<Outcome, String> {
+    let movie = match &args.movie {
+        Some(path) => Movie::load(path)?,
+        None => Movie::default(),
+    };
+    let rom = container::load(&args.rom)?.rom;
+    let instructions_per_frame = args
+        .instructions_per_frame
+        .unwrap_or_else(|| Database::bundled().identify(&rom).instructions_per_frame);
+    let mut left = boot(args, &rom, args.left_engine, args.left_platform)?;
+    let mut right = boot(args, &rom, args.right_engine, args.right_platform)?;
+    Ok(compare(
+        &mut left,
+        &mut right,
+        &movie,
+        args.frames,
+        instructions_per_frame,
+        args.syntax,
+    ))
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    match run(&args) {
+        Ok(Outcome::Identical { cycles }) => {
+            println!(
+                "identical for {} frames, {cycles} instructions",
+                args.frames
+            );
+            ExitCode::SUCCESS
+        }
+        Ok(Outcome::Faulted { cycle, fault }) => {
+            println!("identical until both faulted at cycle {cycle}: {fault}");
+            ExitCode::SUCCESS
+        }
+        Ok(Outcome::Diverged(divergence)) => {
+            print!("{divergence}");
+            ExitCode::FAILURE
+        }
+        Err(e) => {
+            eprintln!("{e}");
+            ExitCode::from(2)
+        }
+    }
+}
@@ -0,0 +1,43 @@
+use clap::Parser;
+use my_chip_8::octo;
+use std::fs;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+/// Compiles an Octo program into a ROM.
+#[derive(Parser, Debug)]
+#[command(version)]
+struct Args {
+    /// Octo source file
+    source: PathBuf,
+
+    /// Where to write the ROM, the source path with a .ch8 extension by default
+    #[arg(short, long, value_name = "FILE")]
+    output: Option<PathBuf>,
+}
+
+fn run(args: &Args) -> Result<PathBuf, String> {
+    let source = fs::read_to_string(&args.source)
+        .map_err(|e| format!("can't read {}: {e}", args.source.display()))?;
+    let rom = octo::compile(&source).map_err(|e| format!("{}: {e}", args.source.display()))?;
+    let output = args
+        .output
+        .clone()
+        .unwrap_or_else(|| args.source.with_extension("ch8"));
+    fs::write(&output, rom).map_err(|e| format!("can't write {}: {e}", output.display()))?;
+    Ok(output)
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    match run(&args) {
+        Ok(output) => {
+            println!("wrote {}", output.display());
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("{e}");
+            ExitCode::FAILURE
+        }
+    }
+}
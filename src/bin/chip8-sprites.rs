@@ -0,0 +1,113 @@
+use clap::Parser;
+use my_chip_8::analysis::analyze;
+use my_chip_8::container;
+use my_chip_8::database::Database;
+use my_chip_8::debugger::parse_address;
+use my_chip_8::memory::Memory;
+use my_chip_8::movie::Movie;
+use my_chip_8::palette::Palette;
+use my_chip_8::screenshot::save_rgb_png;
+use my_chip_8::sprites::{find_static, merge, to_octo, to_sheet, trace};
+use my_chip_8::stack::{Stack, StackStorage};
+use std::fs;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+/// Extracts the sprites a ROM draws, found from its `ANNN`/`DXYN` pairs and by
+/// running it, as Octo literals or a PNG sprite sheet.
+#[derive(Parser, Debug)]
+#[command(version)]
+struct Args {
+    rom: PathBuf,
+
+    /// Where the ROM is loaded, from the ROM database or its guessed platform by default
+    #[arg(long, value_name = "ADDR", value_parser = parse_address)]
+    load_address: Option<u16>,
+
+    /// Number of frames to run the ROM for, 0 to only look at the code
+    #[arg(long, default_value_t = 600)]
+    frames: u32,
+
+    /// Keys to hold on each frame while running, see `Movie` for the format
+    #[arg(long, value_name = "FILE")]
+    movie: Option<PathBuf>,
+
+    /// Write the sprites as Octo source, printed when no output is given
+    #[arg(long, value_name = "FILE")]
+    octo: Option<PathBuf>,
+
+    /// Write a sprite sheet
+    #[arg(long, value_name = "FILE")]
+    png: Option<PathBuf>,
+
+    /// Pixels per dot in the sprite sheet
+    #[arg(long, default_value_t = 4)]
+    scale: u32,
+}
+
+fn run(args: &Args) -> Result<(), String> {
+    let rom = container::load(&args.rom)?.rom;
+    let profile = Database::bundled().identify(&rom);
+    let load_address = args.load_address.unwrap_or(profile.platform.load_address());
+    let analysis = analyze(&rom, load_address);
+    let mut sprites = find_static(&analysis);
+    // the ROM as loaded, or the memory the traced program left behind, whose
+    // sprites may have been built at runtime
+    let mut memory = analysis.memory.clone();
+
+    if args.frames > 0 {
+        let movie = match &args.movie {
+            Some(path) => Movie::load(path)?,
+            None => Movie::default(),
+        };
+        let mut cpu = my_chip_8::cpu::from_parts(
+            Memory::default(),
+            Stack::new(profile.platform.stack_depth(), StackStorage::Internal),
+        );
+        cpu.set_quirks(profile.quirks);
+        cpu.load_fonts("./roms/fonts.ch8")?;
+        cpu.load_rom_at(&rom, load_address, load_address)?;
+        let (traced, fault) = trace(
+            &mut cpu,
+            &movie,
+            args.frames,
+            profile.instructions_per_frame,
+        );
+        if let Some(fault) = fault {
+            eprintln!("stopped tracing at {:#05X}: {fault}", cpu.pc());
+        }
+        // the fonts aren't part of the ROM
+        sprites.extend(
+            traced
+                .into_iter()
+                .filter(|sprite| sprite.address >= load_address),
+        );
+        sprites = merge(sprites);
+        memory = cpu.memory().as_slice().to_vec();
+    }
+
+    if let Some(path) = &args.png {
+        let (width, height, pixels) = to_sheet(&memory, &sprites, &Palette::default(), args.scale);
+        save_rgb_png(path, width, height, &pixels)?;
+    }
+    let source = to_octo(&memory, &sprites);
+    match &args.octo {
+        Some(path) => {
+            fs::write(path, source).map_err(|e| format!("can't write {}: {e}", path.display()))?
+        }
+        None if args.png.is_none() => print!("{source}"),
+        None => {}
+    }
+    eprintln!("{} sprites", sprites.len());
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    match run(&Args::parse()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{e}");
+            ExitCode::FAILURE
+        }
+    }
+}
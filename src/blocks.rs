@@ -0,0 +1,319 @@
+use crate::cpu::Cpu;
+use crate::decoder::decode_instruction;
+use crate::fault::Fault;
+use crate::memory::Memory;
+use crate::opcode::OpCode;
+use std::rc::Rc;
+
+/// Longest run of instructions compiled into a single block.
+pub const MAX_BLOCK_LENGTH: usize = 32;
+
+/// One compiled instruction, called with the program counter already past it.
+pub type Op = Box<dyn Fn(&mut Cpu) -> Result<(), Fault>>;
+
+/// Straight-line instructions ending with the first one that can change the
+/// control flow, so that only the last one may move the program counter.
+pub struct Block {
+    pub start: usize,
+    pub ops: Vec<Op>,
+}
+
+impl Block {
+    fn covers(&self, address: usize) -> bool {
+        (self.start..self.start + 2 * self.ops.len()).contains(&address)
+    }
+}
+
+/// Compiled blocks keyed by the address they start at.
+pub struct BlockCache {
+    blocks: Vec<Option<Rc<Block>>>,
+    /// Bytes that belong or belonged to a block, writes elsewhere need no lookup.
+    code: Vec<bool>,
+}
+
+impl std::fmt::Debug for BlockCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let compiled = self.blocks.iter().flatten().count();
+        write!(f, "BlockCache {{ {compiled} blocks }}")
+    }
+}
+
+impl BlockCache {
+    pub fn new(memory_size: usize) -> BlockCache {
+        BlockCache {
+            blocks: vec![None; memory_size],
+            code: vec![false; memory_size],
+        }
+    }
+
+    pub fn get(&self, address: usize) -> Option<Rc<Block>> {
+        self.blocks.get(address)?.clone()
+    }
+
+    /// Compiles the block starting at `address`, or returns `None` when its first
+    /// instruction straddles the end of memory and must go through the interpreter.
+    pub fn compile(&mut self, memory: &Memory, address: usize) -> Option<Rc<Block>> {
+        let bytes = memory.as_slice();
+        let mut ops = vec![];
+        let mut pc = address;
+        while ops.len() < MAX_BLOCK_LENGTH && pc + 1 < bytes.len() {
+            let op_code = decode_instruction(u16::from_be_bytes([bytes[pc], bytes[pc + 1]]));
+            ops.push(compile(op_code));
+            pc += 2;
+            if ends_block(op_code) {
+                break;
+            }
+        }
+        if ops.is_empty() {
+            return None;
+        }
+
+        self.code[address..pc].fill(true);
+        let block = Rc::new(Block {
+            start: address,
+            ops,
+        });
+        self.blocks[address] = Some(block.clone());
+        Some(block)
+    }
+
+    /// Drops the blocks covering the byte at `address`.
+    pub fn invalidate(&mut self, address: usize) {
+        if !self.code.get(address).copied().unwrap_or(false) {
+            return;
+        }
+        let first = address.saturating_sub(2 * MAX_BLOCK_LENGTH - 1);
+        for start in first..=address {
+            if self.blocks[start]
+                .as_ref()
+                .is_some_and(|block| block.covers(address))
+            {
+                self.blocks[start] = None;
+            }
+        }
+    }
+}
+
+/// Jumps, calls, returns, skips, `FX0A` and `F000 NNNN` may not fall through to the next instruction.
+fn ends_block(op_code: OpCode) -> bool {
+    matches!(
+        op_code,
+        OpCode::Jump(_)
+            | OpCode::JumpWithV0Offset(_)
+            | OpCode::CallSubroutine(_)
+            | OpCode::RetFromSubroutine
+            | OpCode::SkipIfRegisterEquals(..)
+            | OpCode::SkipIfRegisterNotEquals(..)
+            | OpCode::SkipIfBothRegistersEqual(..)
+            | OpCode::SkipIfBothRegistersNotEqual(..)
+            | OpCode::SkipIfKey(_)
+            | OpCode::SkipIfNotKey(_)
+            | OpCode::GetKey(_)
+            | OpCode::LoadIndexLong
+            | OpCode::Unknown(_)
+    )
+}
+
+/// Register, index, ALU, skip and draw operations get a dedicated closure, the
+/// rarer ones go through the interpreter's dispatch. Quirks are read when the
+/// closure runs, as they may change after the block is compiled.
+fn compile(op_code: OpCode) -> Op {
+    match op_code {
+        OpCode::SetRegister { register, value } => Box::new(move |cpu| {
+            cpu.registers_mut()[register] = value;
+            Ok(())
+        }),
+        OpCode::AddRegister { register, value } => Box::new(move |cpu| {
+            let registers = cpu.registers_mut();
+            registers[register] = registers[register].wrapping_add(value);
+            Ok(())
+        }),
+        OpCode::SetRegisterToRegisterValue(x, y) => Box::new(move |cpu| {
+            let registers = cpu.registers_mut();
+            registers[x as usize] = registers[y as usize];
+            Ok(())
+        }),
+        OpCode::SetIndex(address) => Box::new(move |cpu| {
+            cpu.set_index(address);
+            Ok(())
+        }),
+        OpCode::AddRegisterValueToIndex(x) => Box::new(move |cpu| {
+            let index = cpu.index().wrapping_add(cpu.registers()[x as usize] as u16);
+            cpu.set_index(index);
+            Ok(())
+        }),
+        OpCode::SetRegisterToRegisterValueUsingOR(x, y) => logic(x, y, |vx, vy| vx | vy),
+        OpCode::SetRegisterToRegisterValueUsingAND(x, y) => logic(x, y, |vx, vy| vx & vy),
+        OpCode::SetRegisterToRegisterValueUsingXOR(x, y) => logic(x, y, |vx, vy| vx ^ vy),
+        OpCode::AddRegisterToRegister(x, y) => with_flag(x, y, |vx, vy| vx.overflowing_add(vy)),
+        OpCode::SubRegisterToRegister(x, y) => with_flag(x, y, |vx, vy| {
+            let (value, borrow) = vx.overflowing_sub(vy);
+            (value, !borrow)
+        }),
+        OpCode::SubRegisterToRegisterReverse(x, y) => with_flag(x, y, |vx, vy| {
+            let (value, borrow) = vy.overflowing_sub(vx);
+            (value, !borrow)
+        }),
+        OpCode::ShiftRightRegisterFromRegister(x, y) => {
+            shift(x, y, |value| (value >> 1, value & 1))
+        }
+        OpCode::ShiftLeftRegisterFromRegister(x, y) => {
+            shift(x, y, |value| (value << 1, value >> 7))
+        }
+        OpCode::SkipIfRegisterEquals(x, value) => skip_if(move |v| v[x as usize] == value),
+        OpCode::SkipIfRegisterNotEquals(x, value) => skip_if(move |v| v[x as usize] != value),
+        OpCode::SkipIfBothRegistersEqual(x, y) => skip_if(move |v| v[x as usize] == v[y as usize]),
+        OpCode::SkipIfBothRegistersNotEqual(x, y) => {
+            skip_if(move |v| v[x as usize] != v[y as usize])
+        }
+        OpCode::Draw(x, y, nibble) => Box::new(move |cpu| cpu.display(x, y, nibble)),
+        _ => Box::new(move |cpu| cpu.execute(op_code)),
+    }
+}
+
+/// `8XY1`, `8XY2` and `8XY3`.
+fn logic(x: u8, y: u8, operation: impl Fn(u8, u8) -> u8 + 'static) -> Op {
+    let (x, y) = (x as usize, y as usize);
+    Box::new(move |cpu| {
+        let resets_flag = cpu.quirks().logic_resets_vf;
+        let registers = cpu.registers_mut();
+        registers[x] = operation(registers[x], registers[y]);
+        if resets_flag {
+            registers[0xF] = 0;
+        }
+        Ok(())
+    })
+}
+
+/// `8XY4`, `8XY5` and `8XY7`, VF being set after VX in case X is F.
+fn with_flag(x: u8, y: u8, operation: impl Fn(u8, u8) -> (u8, bool) + 'static) -> Op {
+    let (x, y) = (x as usize, y as usize);
+    Box::new(move |cpu| {
+        let registers = cpu.registers_mut();
+        let (value, flag) = operation(registers[x], registers[y]);
+        registers[x] = value;
+        registers[0xF] = flag as u8;
+        Ok(())
+    })
+}
+
+/// `8XY6` and `8XYE`, `operation` returning the result and the bit shifted out.
+fn shift(x: u8, y: u8, operation: impl Fn(u8) -> (u8, u8) + 'static) -> Op {
+    let (x, y) = (x as usize, y as usize);
+    Box::new(move |cpu| {
+        let source = if cpu.quirks().shift_uses_vy { y } else { x };
+        let registers = cpu.registers_mut();
+        let (value, flag) = operation(registers[source]);
+        registers[x] = value;
+        registers[0xF] = flag;
+        Ok(())
+    })
+}
+
+/// `3XNN`, `4XNN`, `5XY0` and `9XY0`.
+fn skip_if(condition: impl Fn(&[u8; 16]) -> bool + 'static) -> Op {
+    Box::new(move |cpu| {
+        if condition(cpu.registers()) {
+            cpu.skip();
+        }
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cpu::{new, Cpu, Engine};
+    use crate::emulator::INSTRUCTIONS_PER_FRAME;
+    use std::collections::HashSet;
+    use std::fs;
+
+    fn boot(rom: &str, engine: Engine) -> Cpu {
+        let mut cpu = new();
+        cpu.set_engine(engine);
+        cpu.seed_random(8);
+        cpu.load_fonts("./roms/fonts.ch8").unwrap();
+        cpu.load_rom(rom).unwrap();
+        cpu
+    }
+
+    fn assert_same_state(expected: &Cpu, actual: &Cpu, context: &str) {
+        assert_eq!(expected.pc(), actual.pc(), "pc {context}");
+        assert_eq!(
+            expected.registers(),
+            actual.registers(),
+            "registers {context}"
+        );
+        assert_eq!(expected.index(), actual.index(), "index {context}");
+        assert_eq!(
+            expected.stack().len(),
+            actual.stack().len(),
+            "stack {context}"
+        );
+        assert_eq!(
+            expected.delay_timer(),
+            actual.delay_timer(),
+            "delay {context}"
+        );
+        assert_eq!(
+            expected.sound_timer(),
+            actual.sound_timer(),
+            "sound {context}"
+        );
+        assert!(
+            expected.memory().as_slice() == actual.memory().as_slice(),
+            "memory {context}"
+        );
+        assert!(expected.screen == actual.screen, "screen {context}");
+    }
+
+    #[test]
+    fn runs_the_bundled_roms_like_the_interpreter() {
+        for entry in fs::read_dir("./roms").unwrap() {
+            let path = entry.unwrap().path();
+            let rom = path.to_str().unwrap();
+            if rom.ends_with("fonts.ch8") {
+                continue;
+            }
+            let mut interpreter = boot(rom, Engine::Interpreter);
+            let mut blocks = boot(rom, Engine::Blocks);
+
+            for frame in 0..300 {
+                // hold a different key every few frames to go through menus and game loops
+                let keys = HashSet::from([(frame / 20 % 16) as u8]);
+                let results = [&mut interpreter, &mut blocks].map(|cpu| {
+                    cpu.set_keys_pressed(keys.clone());
+                    let result = cpu.run(INSTRUCTIONS_PER_FRAME);
+                    cpu.decrement_timers();
+                    result
+                });
+                assert_eq!(results[0], results[1], "{rom} frame {frame}");
+                assert_same_state(&interpreter, &blocks, &format!("{rom} frame {frame}"));
+                if results[0].is_err() {
+                    break;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn recompiles_blocks_after_they_are_overwritten() {
+        let mut cpu = boot("./roms/test.ch8", Engine::Blocks);
+        // 0x200: LD V3, 0x01, overwritten with LD V3, 0x02; JP 0x204
+        // 0x204: LD I, 0x200; LD V0, 0x63; LD V1, 0x02; LD [I], V1; JP 0x200
+        cpu.memory_mut()
+            .load(
+                0x200,
+                &[
+                    0x63, 0x01, 0x12, 0x04, 0xA2, 0x00, 0x60, 0x63, 0x61, 0x02, 0xF1, 0x55, 0x12,
+                    0x00,
+                ],
+            )
+            .unwrap();
+
+        cpu.run(2).unwrap();
+        assert_eq!(cpu.registers()[3], 0x01);
+        cpu.run(7).unwrap();
+        assert_eq!(cpu.registers()[3], 0x02);
+        assert_eq!(cpu.pc(), 0x204);
+    }
+}
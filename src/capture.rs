@@ -0,0 +1,98 @@
+use crate::cli::Args;
+use log::{error, info};
+use my_chip_8::cpu::Cpu;
+use my_chip_8::emulator::Hooks;
+use my_chip_8::frontend::Command;
+use my_chip_8::palette::Palette;
+use my_chip_8::recorder::Recorder;
+use my_chip_8::screen::DOT_SIZE_IN_PXS;
+use my_chip_8::screenshot;
+use std::path::PathBuf;
+use time::OffsetDateTime;
+
+/// Screenshots and gameplay recordings of the running ROM, named after it.
+pub struct Capture<'a> {
+    args: &'a Args,
+    rom_path: PathBuf,
+    recorder: Option<Recorder>,
+}
+
+impl<'a> Capture<'a> {
+    pub fn new(args: &'a Args, rom_path: PathBuf) -> Capture<'a> {
+        Capture {
+            args,
+            rom_path,
+            recorder: None,
+        }
+    }
+
+    pub fn take_screenshot(&self, cpu: &Cpu, palette: &Palette) {
+        match screenshot::capture(
+            &self.args.screenshot_dir,
+            &self.rom_path,
+            &cpu.screen,
+            palette,
+            DOT_SIZE_IN_PXS,
+        ) {
+            Ok(paths) => paths
+                .iter()
+                .for_each(|path| info!("screenshot saved to {}", path.display())),
+            Err(e) => error!("can't save screenshot: {e}"),
+        }
+    }
+
+    pub fn start_recording(&mut self, palette: Palette) {
+        let base_name = screenshot::base_name(&self.rom_path, OffsetDateTime::now_utc());
+        match Recorder::start(
+            self.args.capture_format,
+            &self.args.capture_dir.join(base_name),
+            palette,
+            DOT_SIZE_IN_PXS,
+            self.args.capture_audio,
+        ) {
+            Ok(recorder) => {
+                info!("recording started");
+                self.recorder = Some(recorder);
+            }
+            Err(e) => error!("can't start recording: {e}"),
+        }
+    }
+
+    pub fn stop_recording(&mut self) {
+        let Some(recorder) = self.recorder.take() else {
+            return;
+        };
+        match recorder.finish() {
+            Ok(paths) => paths
+                .iter()
+                .for_each(|path| info!("recording saved to {}", path.display())),
+            Err(e) => error!("can't save recording: {e}"),
+        }
+    }
+}
+
+impl Hooks for Capture<'_> {
+    fn on_frame(&mut self, cpu: &Cpu) {
+        if let Some(recorder) = &mut self.recorder {
+            if let Err(e) = recorder.capture(&cpu.screen, cpu.is_sound_playing()) {
+                error!("recording stopped: {e}");
+                self.recorder = None;
+            }
+        }
+    }
+
+    fn on_command(&mut self, command: Command, cpu: &Cpu, palette: Palette) {
+        match command {
+            Command::Screenshot => self.take_screenshot(cpu, &palette),
+            Command::ToggleRecording if self.recorder.is_some() => self.stop_recording(),
+            Command::ToggleRecording => self.start_recording(palette),
+            _ => {}
+        }
+    }
+}
+
+impl Drop for Capture<'_> {
+    fn drop(&mut self) {
+        self.stop_recording();
+    }
+}
@@ -0,0 +1,176 @@
+use clap::{Parser, ValueEnum};
+use my_chip_8::cpu::Engine;
+use my_chip_8::debugger::{parse_address, parse_address_range, Breakpoint, Watchpoint};
+use my_chip_8::expression::Expression;
+use my_chip_8::filters::Preset;
+use my_chip_8::memory::{MemorySize, OutOfBounds};
+use my_chip_8::mnemonic::Syntax;
+use my_chip_8::platform::Platform;
+use my_chip_8::recorder::CaptureFormat;
+use my_chip_8::trace::TraceFormat;
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum Frontend {
+    /// SDL window, requires the `sdl` feature
+    #[default]
+    Sdl,
+    /// ANSI colours and half-block characters in the current terminal
+    Terminal,
+}
+
+#[derive(Parser, Debug)]
+#[command(version, about = "A CHIP-8 emulator")]
+pub struct Args {
+    /// ROM, Octo source (.8o), Octo cartridge (.gif) or a zip/gzip archive of one
+    /// to run, a file dialog is opened when omitted
+    pub rom: Option<PathBuf>,
+
+    /// Where the emulator is displayed and takes its input from
+    #[arg(long, value_enum, default_value_t = Frontend::Sdl)]
+    pub frontend: Frontend,
+
+    /// Machine the ROM targets, decides the call stack depth and instruction quirks.
+    /// Looked up in the ROM database or guessed from the ROM's instructions when omitted
+    #[arg(long, value_enum)]
+    pub platform: Option<Platform>,
+
+    /// Directory with the programs.json, sha1-hashes.json and platforms.json of a
+    /// chip-8-database checkout, replacing the database bundled with the emulator
+    #[arg(long, value_name = "DIR")]
+    pub database: Option<PathBuf>,
+
+    /// Where the ROM is loaded, 0x200 or 0x600 for the ETI-660 by default
+    #[arg(long, value_name = "ADDR", value_parser = parse_address)]
+    pub load_address: Option<u16>,
+
+    /// Where execution starts, the load address by default
+    #[arg(long, value_name = "ADDR", value_parser = parse_address)]
+    pub entry_point: Option<u16>,
+
+    /// Instructions run per 60 Hz frame, from the ROM database or 50 by default.
+    /// Changed at runtime with - and =
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    pub instructions_per_frame: Option<u32>,
+
+    /// Emulated seconds per real second from 0.01 to 100, e.g. 0.25 for slow motion. Tab toggles an
+    /// uncapped turbo, M cycles slow motion, P pauses and N advances a paused frame
+    #[arg(long, default_value_t = 1.0, value_parser = parse_speed)]
+    pub speed: f64,
+
+    /// Keep the call stack in emulated memory at 0xEA0, like the COSMAC VIP interpreter
+    #[arg(long)]
+    pub vip_stack: bool,
+
+    /// How instructions are executed, every engine gives the same results
+    #[arg(long, value_enum, default_value_t = Engine::Cached)]
+    pub engine: Engine,
+
+    /// Seed of the random number generator used by CXNN, for reproducible runs
+    #[arg(long)]
+    pub seed: Option<u64>,
+
+    /// Amount of addressable memory
+    #[arg(long, value_enum, default_value_t = MemorySize::Standard)]
+    pub memory_size: MemorySize,
+
+    /// Behaviour of memory accesses past the end of memory
+    #[arg(long, value_enum, default_value_t = OutOfBounds::Wrap)]
+    pub out_of_bounds: OutOfBounds,
+
+    /// Software post-processing applied to the rendered frame
+    #[arg(long, value_enum, default_value_t = Preset::None)]
+    pub filter: Preset,
+
+    /// Run without a window for a fixed number of frames
+    #[arg(long)]
+    pub headless: bool,
+
+    /// Number of 60 Hz frames to emulate in headless mode
+    #[arg(long, default_value_t = 600)]
+    pub frames: u32,
+
+    /// Write a screenshot when the headless run is over
+    #[arg(long)]
+    pub screenshot: bool,
+
+    /// Directory where screenshots are written
+    #[arg(long, default_value = ".")]
+    pub screenshot_dir: PathBuf,
+
+    /// Record gameplay from the first frame, F11 toggles recording in a window
+    #[arg(long)]
+    pub record: bool,
+
+    /// Format of the recorded frames
+    #[arg(long, value_enum, default_value_t = CaptureFormat::Gif)]
+    pub capture_format: CaptureFormat,
+
+    /// Also record the beeper as a WAV track
+    #[arg(long)]
+    pub capture_audio: bool,
+
+    /// Directory where recordings are written
+    #[arg(long, default_value = ".")]
+    pub capture_dir: PathBuf,
+
+    /// Pause before the instruction at ADDR runs, or only when the condition holds with ADDR:CONDITION
+    #[arg(long = "break", value_name = "ADDR[:CONDITION]")]
+    pub breakpoints: Vec<Breakpoint>,
+
+    /// Pause as soon as the expression becomes true, e.g. "V3 == 0x10 && [I] != 0"
+    #[arg(long, value_name = "EXPRESSION")]
+    pub break_if: Vec<Expression>,
+
+    /// Pause when an instruction reads or writes ADDR or the START-END range
+    #[arg(long, value_name = "ADDR[-END]")]
+    pub watch: Vec<Watchpoint>,
+
+    /// Pause when an instruction reads ADDR or the START-END range
+    #[arg(long, value_name = "ADDR[-END]")]
+    pub watch_read: Vec<Watchpoint>,
+
+    /// Pause when an instruction writes ADDR or the START-END range
+    #[arg(long, value_name = "ADDR[-END]")]
+    pub watch_write: Vec<Watchpoint>,
+
+    /// Record executed instructions to this file
+    #[arg(long, value_name = "PATH")]
+    pub trace: Option<PathBuf>,
+
+    /// Format of the trace records
+    #[arg(long, value_enum, default_value_t = TraceFormat::JsonLines)]
+    pub trace_format: TraceFormat,
+
+    /// Only trace instructions at ADDR or in the START-END range
+    #[arg(long, value_name = "ADDR[-END]", value_parser = parse_address_range)]
+    pub trace_range: Option<RangeInclusive<usize>>,
+
+    /// Keep only the last N instructions and write them to the trace file when a fault occurs
+    #[arg(long, value_name = "N")]
+    pub trace_ring: Option<usize>,
+
+    /// Count executions per address, opcode and subroutine, and write the report to this file on exit
+    #[arg(long, value_name = "PATH")]
+    pub profile: Option<PathBuf>,
+
+    /// Write the memory heatmap of the profiled run to this PNG file on exit, F9 shows it in a window
+    #[arg(long, value_name = "PATH")]
+    pub heatmap: Option<PathBuf>,
+
+    /// Wait for a GDB remote protocol debugger on this address instead of running, e.g. 127.0.0.1:1234
+    #[arg(long, value_name = "HOST:PORT")]
+    pub gdb: Option<String>,
+
+    /// Assembly syntax of the instructions shown by the debugger and the profile
+    #[arg(long, value_enum, default_value_t = Syntax::Cowgod)]
+    pub syntax: Syntax,
+}
+
+fn parse_speed(text: &str) -> Result<f64, String> {
+    match text.parse::<f64>() {
+        Ok(speed) if (0.01..=100.0).contains(&speed) => Ok(speed),
+        _ => Err(format!("expected a number from 0.01 to 100, got {text}")),
+    }
+}
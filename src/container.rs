@@ -0,0 +1,256 @@
+use crate::analysis::analyze;
+use crate::database::Profile;
+use crate::emulator::INSTRUCTIONS_PER_FRAME;
+use crate::frontend::Keymap;
+use crate::octo;
+use crate::palette::{parse_color, Palette};
+use crate::platform::{Platform, Quirks, PROGRAM_START};
+use flate2::read::GzDecoder;
+use serde::Deserialize;
+use std::fs;
+use std::io::{Cursor, Read};
+use std::path::Path;
+
+/// Extensions of the files an archive is searched for, in order of preference.
+const ROM_EXTENSIONS: [&str; 5] = ["ch8", "c8x", "sc8", "xo8", "8o"];
+
+/// A program read from disk, with what its file says about how to run it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cartridge {
+    pub rom: Vec<u8>,
+    /// Implied by the extension, e.g. `.sc8` for SCHIP programs.
+    pub platform: Option<Platform>,
+    /// Settings saved with the program, only Octo cartridges have them.
+    pub profile: Option<Profile>,
+}
+
+/// Octo's settings, of which only those with an equivalent here are kept.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OctoOptions {
+    tickrate: Option<u32>,
+    background_color: Option<String>,
+    fill_color: Option<String>,
+    #[serde(default)]
+    shift_quirks: bool,
+    #[serde(default)]
+    load_store_quirks: bool,
+    #[serde(default)]
+    clip_quirks: bool,
+    #[serde(default)]
+    jump_quirks: bool,
+    #[serde(default)]
+    logic_quirks: bool,
+    #[serde(default)]
+    v_blank_quirks: bool,
+}
+
+#[derive(Deserialize)]
+struct OctoCartridge {
+    program: String,
+    options: OctoOptions,
+}
+
+/// Reads a raw ROM (`.ch8`, `.c8x`, `.sc8`, `.xo8`), Octo source (`.8o`), an
+/// Octo cartridge (`.gif`) or the first of those in a `.zip` or `.gz` archive.
+pub fn load(path: &Path) -> Result<Cartridge, String> {
+    let bytes = fs::read(path).map_err(|e| format!("can't read {}: {e}", path.display()))?;
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    from_bytes(&name, bytes).map_err(|e| format!("{}: {e}", path.display()))
+}
+
+/// Decodes `bytes` according to the extension of the file `name`, unknown
+/// extensions being raw ROMs.
+pub fn from_bytes(name: &str, bytes: Vec<u8>) -> Result<Cartridge, String> {
+    match extension(name).as_str() {
+        "8o" => {
+            let source = String::from_utf8(bytes).map_err(|_| "the source isn't UTF-8")?;
+            Ok(Cartridge {
+                rom: octo::compile(&source)?,
+                platform: None,
+                profile: None,
+            })
+        }
+        "gif" => from_octo_cartridge(&bytes),
+        "zip" => from_zip(bytes),
+        "gz" => {
+            let mut decompressed = vec![];
+            GzDecoder::new(bytes.as_slice())
+                .read_to_end(&mut decompressed)
+                .map_err(|e| format!("invalid gzip data: {e}"))?;
+            let inner = name.rsplit_once('.').map_or(name, |(stem, _)| stem);
+            from_bytes(inner, decompressed)
+        }
+        extension => Ok(Cartridge {
+            rom: bytes,
+            platform: match extension {
+                "c8x" => Some(Platform::CosmacVip),
+                "sc8" => Some(Platform::SuperChip),
+                "xo8" => Some(Platform::XoChip),
+                _ => None,
+            },
+            profile: None,
+        }),
+    }
+}
+
+fn extension(name: &str) -> String {
+    Path::new(name)
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_ascii_lowercase())
+        .unwrap_or_default()
+}
+
+fn from_zip(bytes: Vec<u8>) -> Result<Cartridge, String> {
+    let mut archive =
+        zip::ZipArchive::new(Cursor::new(bytes)).map_err(|e| format!("invalid zip: {e}"))?;
+    let rank = |name: &str| {
+        ROM_EXTENSIONS
+            .iter()
+            .position(|candidate| extension(name) == *candidate)
+    };
+    let (index, name) = (0..archive.len())
+        .filter_map(|index| {
+            let name = archive.name_for_index(index)?.to_string();
+            Some((rank(&name)?, index, name))
+        })
+        .min()
+        .map(|(_, index, name)| (index, name))
+        .ok_or("the zip contains no ROM")?;
+
+    let mut bytes = vec![];
+    archive
+        .by_index(index)
+        .and_then(|mut file| Ok(file.read_to_end(&mut bytes)?))
+        .map_err(|e| format!("can't extract {name}: {e}"))?;
+    from_bytes(&name, bytes)
+}
+
+/// Octo hides a JSON payload in the colour indices of a GIF's pixels: two bits
+/// per pixel, most significant first, a 32-bit big-endian length and then the
+/// JSON with the program's source and options.
+fn from_octo_cartridge(bytes: &[u8]) -> Result<Cartridge, String> {
+    let mut options = gif::DecodeOptions::new();
+    options.set_color_output(gif::ColorOutput::Indexed);
+    let mut decoder = options
+        .read_info(bytes)
+        .map_err(|e| format!("invalid GIF: {e}"))?;
+    let mut pixels = vec![];
+    while let Some(frame) = decoder
+        .read_next_frame()
+        .map_err(|e| format!("invalid GIF: {e}"))?
+    {
+        pixels.extend_from_slice(&frame.buffer);
+    }
+
+    let payload: Vec<u8> = pixels
+        .chunks_exact(4)
+        .map(|bits| bits.iter().fold(0, |byte, pixel| byte << 2 | pixel & 3))
+        .collect();
+    let (length, json) = payload
+        .split_first_chunk::<4>()
+        .ok_or("the GIF isn't an Octo cartridge")?;
+    let json = json
+        .get(..u32::from_be_bytes(*length) as usize)
+        .ok_or("the GIF isn't an Octo cartridge")?;
+    let cartridge: OctoCartridge =
+        serde_json::from_slice(json).map_err(|e| format!("invalid Octo cartridge: {e}"))?;
+
+    let rom = octo::compile(&cartridge.program)?;
+    let options = cartridge.options;
+    let color = |color: &Option<String>| color.as_deref().and_then(parse_color);
+    let platform = analyze(&rom, PROGRAM_START).platform;
+    let profile = Profile {
+        title: None,
+        platform,
+        quirks: Quirks {
+            shift_uses_vy: !options.shift_quirks,
+            load_store_increments_index: !options.load_store_quirks,
+            jump_uses_vx: options.jump_quirks,
+            logic_resets_vf: options.logic_quirks,
+            sprite_wrapping: !options.clip_quirks,
+            display_wait: options.v_blank_quirks,
+        },
+        instructions_per_frame: options.tickrate.unwrap_or(INSTRUCTIONS_PER_FRAME),
+        keymap: Keymap::default(),
+        palette: color(&options.background_color)
+            .zip(color(&options.fill_color))
+            .map(|(background, foreground)| Palette {
+                background,
+                foreground,
+            }),
+    };
+    Ok(Cartridge {
+        rom,
+        platform: Some(platform),
+        profile: Some(profile),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::container::from_bytes;
+    use crate::platform::Platform;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::io::{Cursor, Write};
+
+    #[test]
+    fn unpacks_archives_and_uses_the_extension_as_a_platform_hint() {
+        let rom = vec![0x00, 0xFF, 0x12, 0x02];
+
+        let mut zip = zip::ZipWriter::new(Cursor::new(vec![]));
+        let options = zip::write::SimpleFileOptions::default();
+        zip.start_file("README.txt", options).unwrap();
+        zip.write_all(b"not a rom").unwrap();
+        zip.start_file("game/GAME.SC8", options).unwrap();
+        zip.write_all(&rom).unwrap();
+        let zip = zip.finish().unwrap().into_inner();
+
+        let mut gzip = GzEncoder::new(vec![], Compression::default());
+        gzip.write_all(&zip).unwrap();
+        let cartridge = from_bytes("games.zip.gz", gzip.finish().unwrap()).unwrap();
+
+        assert_eq!(cartridge.rom, rom);
+        assert_eq!(cartridge.platform, Some(Platform::SuperChip));
+        assert!(from_bytes(
+            "empty.zip",
+            zip::ZipWriter::new(Cursor::new(vec![]))
+                .finish()
+                .unwrap()
+                .into_inner()
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn reads_the_program_and_options_of_octo_cartridges() {
+        let json = br##"{"program": ": main v0 := 7", "options": {"tickrate": 20,
+            "fillColor": "#FFFFFF", "backgroundColor": "#000000", "shiftQuirks": true}}"##;
+        let mut payload = (json.len() as u32).to_be_bytes().to_vec();
+        payload.extend_from_slice(json);
+        let mut pixels: Vec<u8> = payload
+            .iter()
+            .flat_map(|byte| [6, 4, 2, 0].map(|shift| 4 | byte >> shift & 3))
+            .collect();
+        pixels.resize(pixels.len().div_ceil(64) * 64, 0);
+
+        let mut gif = vec![];
+        {
+            let palette: Vec<u8> = (0..=255).flat_map(|index| [index, index, index]).collect();
+            let mut encoder =
+                gif::Encoder::new(&mut gif, 64, (pixels.len() / 64) as u16, &palette).unwrap();
+            let frame =
+                gif::Frame::from_indexed_pixels(64, (pixels.len() / 64) as u16, pixels, None);
+            encoder.write_frame(&frame).unwrap();
+        }
+
+        let cartridge = from_bytes("label.gif", gif).unwrap();
+        assert_eq!(cartridge.rom, [0x12, 0x02, 0x60, 0x07]);
+        let profile = cartridge.profile.unwrap();
+        assert_eq!(profile.instructions_per_frame, 20);
+        assert!(!profile.quirks.shift_uses_vy);
+        assert!(profile.quirks.load_store_increments_index);
+        assert_eq!(profile.palette.unwrap().foreground, (0xFF, 0xFF, 0xFF));
+    }
+}
@@ -1,48 +1,109 @@
+use crate::blocks::{Block, BlockCache};
+use crate::decode_cache::DecodeCache;
 use crate::decoder::decode_instruction;
+use crate::fault::Fault;
+use crate::memory::{Memory, MemorySize, OutOfBounds};
 use crate::opcode::OpCode;
-use crate::renderer::{GRID_X_SIZE, GRID_Y_SIZE};
+use crate::platform::{Platform, Quirks, PROGRAM_START};
+use crate::screen::{GRID_X_SIZE, GRID_Y_SIZE};
+use crate::stack::{Stack, StackStorage};
+use clap::ValueEnum;
 use log::warn;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 use std::collections::HashSet;
 use std::fs;
 use std::ops::BitXor;
-use std::time::Instant;
+use std::rc::Rc;
+
+/// How instructions are fetched and dispatched, all of them behave identically.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum Engine {
+    /// Fetches and decodes every instruction
+    Interpreter,
+    /// Reuses decoded instructions until their bytes are written
+    #[default]
+    Cached,
+    /// Compiles straight-line runs of instructions into closures, the fastest
+    Blocks,
+}
 
 #[derive(Debug)]
 pub struct Cpu {
-    memory: [u16; 4096],
+    memory: Memory,
     pc: u16,     //program counter
     v: [u8; 16], // registers
     i: u16,      // index
-    stack: Vec<u16>,
+    stack: Stack,
 
     pub screen: [[bool; GRID_X_SIZE]; GRID_Y_SIZE],
     pub should_render: bool,
     delay_timer: u8,
     sound_timer: u8,
-    is_key_pressed: bool,
-    key_pressed: Option<u16>,
     keys_pressed: HashSet<u8>,
-    last_ticked_at: Instant,
+    rng: StdRng,
+    engine: Engine,
+    quirks: Quirks,
+    /// Set by `DXYN` with the display wait quirk until the end of the frame.
+    waiting_for_vblank: bool,
+    decode_cache: Option<DecodeCache>,
+    blocks: Option<BlockCache>,
 }
 
 pub fn new() -> Cpu {
+    from_parts(Memory::default(), Stack::default())
+}
+
+pub fn from_parts(mut memory: Memory, stack: Stack) -> Cpu {
+    memory.set_write_tracking(true);
     Cpu {
-        memory: [0; 4096],
+        rng: StdRng::from_entropy(),
+        engine: Engine::Cached,
+        quirks: Quirks::default(),
+        waiting_for_vblank: false,
+        decode_cache: Some(DecodeCache::new(memory.size())),
+        blocks: None,
+        memory,
         pc: 0,
         v: [0; 16],
         i: 0,
-        stack: Vec::new(),
+        stack,
         screen: [[false; GRID_X_SIZE]; GRID_Y_SIZE],
         should_render: false,
         delay_timer: 0,
         sound_timer: 0,
-        is_key_pressed: false,
-        key_pressed: None,
-        last_ticked_at: Instant::now(),
         keys_pressed: HashSet::new(),
     }
 }
 
+/// How to build machines that only differ in their engine, to compare them in
+/// property tests and fuzzing.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct MachineConfig {
+    pub memory_size: MemorySize,
+    pub out_of_bounds: OutOfBounds,
+    pub platform: Platform,
+    pub engine: Engine,
+}
+
+impl MachineConfig {
+    /// A machine about to run `rom` from 0x200 with the registers preset and
+    /// `CXNN` seeded, failing when the ROM doesn't fit in memory.
+    pub fn boot(self, rom: &[u8], registers: [u8; 16]) -> Result<Cpu, Fault> {
+        let mut cpu = from_parts(
+            Memory::new(self.memory_size, self.out_of_bounds),
+            Stack::new(self.platform.stack_depth(), StackStorage::Internal),
+        );
+        cpu.set_engine(self.engine);
+        cpu.set_quirks(self.platform.quirks());
+        cpu.seed_random(0);
+        cpu.memory.load(PROGRAM_START as usize, rom)?;
+        cpu.v = registers;
+        cpu.pc = PROGRAM_START;
+        Ok(cpu)
+    }
+}
+
 impl Default for Cpu {
     fn default() -> Self {
         new()
@@ -52,54 +113,127 @@ impl Default for Cpu {
 impl Cpu {
     pub fn load_rom(&mut self, path: &str) -> Result<(), &str> {
         let bytes = fs::read(path).map_err(|_| "can't read rom file")?;
-        self.load_into_memory(bytes, 0x200);
-        self.pc = 0x200;
+        self.load_rom_bytes(&bytes)
+    }
+
+    /// Loads a ROM already in memory, such as one built by `octo::compile`.
+    pub fn load_rom_bytes(&mut self, bytes: &[u8]) -> Result<(), &'static str> {
+        self.load_rom_at(bytes, PROGRAM_START, PROGRAM_START)
+    }
+
+    /// Loads a ROM at `load_address` and starts running it from `entry_point`.
+    pub fn load_rom_at(
+        &mut self,
+        bytes: &[u8],
+        load_address: u16,
+        entry_point: u16,
+    ) -> Result<(), &'static str> {
+        if entry_point as usize + 1 >= self.memory.size() {
+            return Err("entry point is outside of memory");
+        }
+        self.memory
+            .load(load_address as usize, bytes)
+            .map_err(|_| "rom doesn't fit in memory")?;
+        self.pc = entry_point;
         Ok(())
     }
 
     pub fn load_fonts(&mut self, path: &str) -> Result<(), &str> {
         let bytes = fs::read(path).map_err(|_| "can't read fonts file")?;
-        self.load_into_memory(bytes, 0x50);
+        self.memory
+            .load(0x50, &bytes)
+            .map_err(|_| "fonts don't fit in memory")?;
         Ok(())
     }
 
-    fn load_into_memory(&mut self, bytes: Vec<u8>, offset: usize) {
-        bytes.iter().enumerate().for_each(|(i, &x)| {
-            self.memory[offset + i] = x.into();
-        });
+    pub fn memory(&self) -> &Memory {
+        &self.memory
     }
 
-    pub fn tick(&mut self) {
-        let instruction = self.fetch_next_instruction();
-        self.pc += 2;
-        let op_code = decode_instruction(instruction);
+    pub fn memory_mut(&mut self) -> &mut Memory {
+        &mut self.memory
+    }
+
+    /// Runs the next instruction, or idles while waiting for the vertical blank.
+    pub fn tick(&mut self) -> Result<(), Fault> {
+        if self.waiting_for_vblank {
+            return Ok(());
+        }
+        let op_code = self.next_op_code()?;
+        self.pc = self.pc.wrapping_add(2);
+        self.execute(op_code)
+    }
 
+    /// Runs `instructions` instructions with the selected engine, stopping at the
+    /// first fault or when a `DXYN` has to wait for the vertical blank.
+    pub fn run(&mut self, mut instructions: u32) -> Result<(), Fault> {
+        // the journal has to see every fetch
+        if self.blocks.is_none() || self.memory.is_journal_enabled() {
+            for _ in 0..instructions {
+                if self.waiting_for_vblank {
+                    break;
+                }
+                self.tick()?;
+            }
+            return Ok(());
+        }
+
+        while instructions > 0 && !self.waiting_for_vblank {
+            self.invalidate_written();
+            let Some(block) = self.block_at(self.pc as usize) else {
+                self.tick()?;
+                instructions -= 1;
+                continue;
+            };
+            for op in block.ops.iter().take(instructions as usize) {
+                self.pc = self.pc.wrapping_add(2);
+                instructions -= 1;
+                op(self)?;
+                // the rest of the block may have been overwritten
+                if !self.memory.written().is_empty() || self.waiting_for_vblank {
+                    break;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs an already fetched instruction, the program counter pointing past it.
+    pub fn execute(&mut self, op_code: OpCode) -> Result<(), Fault> {
         match op_code {
             OpCode::Jump(next_pc) => self.pc = next_pc,
             OpCode::JumpWithV0Offset(next_pc) => {
-                self.pc = next_pc.saturating_add(self.v[0x0] as u16)
+                let register = if self.quirks.jump_uses_vx {
+                    (next_pc >> 8) as usize & 0xF
+                } else {
+                    0
+                };
+                self.pc = next_pc.saturating_add(self.v[register] as u16)
             }
             OpCode::RetFromSubroutine => {
-                let return_address = self.stack.pop().unwrap();
+                let return_address = self.stack.pop(&mut self.memory)?;
                 self.pc = return_address;
             }
             OpCode::CallSubroutine(next_pc) => {
-                self.stack.push(self.pc);
+                self.stack.push(&mut self.memory, self.pc)?;
                 self.pc = next_pc;
             }
             OpCode::AddRegister { register, value } => self.add_to_register(register, value),
             OpCode::SetRegister { register, value } => self.set_to_register(register, value),
             OpCode::SetRegisterToRegisterValueUsingOR(x, y) => {
                 self.v[x as usize] |= self.v[y as usize];
+                self.reset_flag_after_logic();
             }
             OpCode::SetRegisterToRegisterValueUsingAND(x, y) => {
                 self.v[x as usize] &= self.v[y as usize];
+                self.reset_flag_after_logic();
             }
             OpCode::SetRegisterToRegisterValueUsingXOR(x, y) => {
                 self.v[x as usize] ^= self.v[y as usize];
+                self.reset_flag_after_logic();
             }
             OpCode::SetRegisterWithRandom(x, nn) => {
-                let random_number: u8 = rand::random();
+                let random_number: u8 = self.rng.gen();
                 self.v[x] = random_number & nn;
             }
             OpCode::AddRegisterToRegister(x, y) => {
@@ -114,21 +248,19 @@ impl Cpu {
                 self.v[x as usize] = new_register_value;
                 self.v[0xF] = if is_overflow { 0 } else { 1 };
             }
-            OpCode::ShiftRightRegisterFromRegister(x, _y) => {
+            OpCode::ShiftRightRegisterFromRegister(x, y) => {
+                let value = self.shift_operand(x, y);
                 // Check the least significant bit
-                let least_significant_bit = if self.v[x as usize] & 0x1 == 1 { 1 } else { 0 };
-                self.v[x as usize] >>= 1;
+                let least_significant_bit = if value & 0x1 == 1 { 1 } else { 0 };
+                self.v[x as usize] = value >> 1;
                 self.v[0xF] = least_significant_bit;
             }
-            OpCode::ShiftLeftRegisterFromRegister(x, _y) => {
-                // Check the least significant bit
-                let least_significant_bit = if self.v[x as usize] & 0x80 == 128 {
-                    1
-                } else {
-                    0
-                };
-                self.v[x as usize] <<= 1;
-                self.v[0xF] = least_significant_bit;
+            OpCode::ShiftLeftRegisterFromRegister(x, y) => {
+                let value = self.shift_operand(x, y);
+                // Check the most significant bit
+                let most_significant_bit = if value & 0x80 == 128 { 1 } else { 0 };
+                self.v[x as usize] = value << 1;
+                self.v[0xF] = most_significant_bit;
             }
             OpCode::SubRegisterToRegisterReverse(x, y) => {
                 let (new_register_value, is_overflow) =
@@ -138,50 +270,57 @@ impl Cpu {
             }
             OpCode::SetIndex(index) => self.set_index(index),
             OpCode::ClearScreen => self.clear_screen(),
-            OpCode::Draw(vx, vy, nibble) => self.display(vx, vy, nibble),
+            OpCode::Draw(vx, vy, nibble) => self.display(vx, vy, nibble)?,
             OpCode::SkipIfRegisterEquals(register, value) => {
                 if self.v[register as usize] == value {
-                    self.pc += 2;
+                    self.skip();
                 }
             }
             OpCode::SkipIfRegisterNotEquals(register, value) => {
                 if self.v[register as usize] != value {
-                    self.pc += 2;
+                    self.skip();
                 }
             }
             OpCode::SkipIfBothRegistersEqual(x, y) => {
                 if self.v[x as usize] == self.v[y as usize] {
-                    self.pc += 2;
+                    self.skip();
                 }
             }
             OpCode::SkipIfBothRegistersNotEqual(x, y) => {
                 if self.v[x as usize] != self.v[y as usize] {
-                    self.pc += 2;
+                    self.skip();
                 }
             }
             OpCode::SetRegisterToRegisterValue(x, y) => {
                 self.v[x as usize] = self.v[y as usize];
             }
             OpCode::AddRegisterValueToIndex(x) => {
-                self.i += self.v[x as usize] as u16;
+                self.i = self.i.wrapping_add(self.v[x as usize] as u16);
             }
             OpCode::StoreBCDRepresentationOfRegister(x) => {
                 let value = self.v[x as usize];
                 let hundreds = value / 100;
                 let tens = (value / 10) % 10;
                 let ones = value % 10;
-                self.memory[self.i as usize] = hundreds as u16;
-                self.memory[(self.i + 1) as usize] = tens as u16;
-                self.memory[(self.i + 2) as usize] = ones as u16;
+                let index = self.i as usize;
+                self.memory.write(index, hundreds)?;
+                self.memory.write(index + 1, tens)?;
+                self.memory.write(index + 2, ones)?;
             }
             OpCode::LoadFromRegistersToMemory(x) => {
-                for i in 0..=x {
-                    self.memory[(self.i + i as u16) as usize] = self.v[i as usize] as u16;
+                for i in 0..=x as usize {
+                    self.memory.write(self.i as usize + i, self.v[i])?;
+                }
+                if self.quirks.load_store_increments_index {
+                    self.i = self.i.wrapping_add(x as u16 + 1);
                 }
             }
             OpCode::LoadFromMemoryToRegisters(x) => {
-                for i in 0..=x {
-                    self.v[i as usize] = self.memory[(self.i + i as u16) as usize] as u8;
+                for i in 0..=x as usize {
+                    self.v[i] = self.memory.read(self.i as usize + i)?;
+                }
+                if self.quirks.load_store_increments_index {
+                    self.i = self.i.wrapping_add(x as u16 + 1);
                 }
             }
             OpCode::SetRegisterFromDelayTimer(x) => {
@@ -196,41 +335,114 @@ impl Cpu {
             OpCode::LoadSpriteRepresentationInMemory(x) => {
                 self.i = self.v[x as usize] as u16 * 5;
             }
+            OpCode::LoadIndexLong => {
+                self.i = self.memory.read_word(self.pc as usize)?;
+                self.pc = self.pc.wrapping_add(2);
+            }
             OpCode::GetKey(x) => {
                 if !self.keys_pressed.contains(&self.v[x]) {
-                    self.pc -= 2;
+                    self.pc = self.pc.wrapping_sub(2);
                 }
             }
             OpCode::SkipIfKey(x) => {
                 if self.keys_pressed.contains(&self.v[x]) {
-                    self.pc += 2;
+                    self.skip();
                 }
             }
             OpCode::SkipIfNotKey(x) => {
                 if !self.keys_pressed.contains(&self.v[x]) {
-                    self.pc += 2;
+                    self.skip();
                 }
             }
-            _ => {
-                warn!("Unknown opcode: {}", op_code);
+            OpCode::Unknown(word) => {
+                warn!("Unknown opcode: {word:#06X}");
             }
         }
-        log::info!("pc={}|i={}|regs={:?}", self.pc, self.i, self.v);
+        Ok(())
     }
 
-    pub fn tick_timers(&mut self) {
-        let duration = self.last_ticked_at.elapsed();
-        if duration.as_millis() > (1000 / 60) as u128 {
-            self.sound_timer = self.sound_timer.saturating_sub(1);
-            self.delay_timer = self.delay_timer.saturating_sub(1);
-            self.last_ticked_at = Instant::now();
-        }
+    /// Decrements both timers once, as happens at every 60 Hz frame, whose
+    /// vertical blank also ends any wait of `DXYN`.
+    pub fn decrement_timers(&mut self) {
+        self.waiting_for_vblank = false;
+        self.sound_timer = self.sound_timer.saturating_sub(1);
+        self.delay_timer = self.delay_timer.saturating_sub(1);
     }
 
-    fn set_index(&mut self, index: u16) {
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
+
+    pub fn registers(&self) -> &[u8; 16] {
+        &self.v
+    }
+
+    pub fn index(&self) -> u16 {
+        self.i
+    }
+
+    pub fn stack(&self) -> &Stack {
+        &self.stack
+    }
+
+    pub fn delay_timer(&self) -> u8 {
+        self.delay_timer
+    }
+
+    pub fn sound_timer(&self) -> u8 {
+        self.sound_timer
+    }
+
+    /// Whether a `DXYN` stalls execution until the next frame.
+    pub fn is_waiting_for_vblank(&self) -> bool {
+        self.waiting_for_vblank
+    }
+
+    pub fn is_sound_playing(&self) -> bool {
+        self.sound_timer > 0
+    }
+
+    pub fn registers_mut(&mut self) -> &mut [u8; 16] {
+        &mut self.v
+    }
+
+    pub fn set_pc(&mut self, pc: u16) {
+        self.pc = pc
+    }
+
+    pub fn set_delay_timer(&mut self, value: u8) {
+        self.delay_timer = value
+    }
+
+    pub fn set_sound_timer(&mut self, value: u8) {
+        self.sound_timer = value
+    }
+
+    pub fn set_index(&mut self, index: u16) {
         self.i = index
     }
 
+    /// Skips the next instruction, both words of `F000 NNNN` included.
+    pub fn skip(&mut self) {
+        let pc = self.pc as usize;
+        let long = self.memory.peek(pc) == Some(0xF0) && self.memory.peek(pc + 1) == Some(0x00);
+        self.pc = self.pc.wrapping_add(if long { 4 } else { 2 });
+    }
+
+    fn shift_operand(&self, x: u8, y: u8) -> u8 {
+        if self.quirks.shift_uses_vy {
+            self.v[y as usize]
+        } else {
+            self.v[x as usize]
+        }
+    }
+
+    fn reset_flag_after_logic(&mut self) {
+        if self.quirks.logic_resets_vf {
+            self.v[0xF] = 0;
+        }
+    }
+
     fn add_to_register(&mut self, register: usize, value: u8) {
         self.v[register] = self.v[register].wrapping_add(value);
     }
@@ -239,12 +451,77 @@ impl Cpu {
         self.v[register] = value
     }
 
-    fn fetch_next_instruction(&self) -> u16 {
-        let first_part = self.memory[self.pc as usize];
-        let second_part = self.memory[(self.pc + 1) as usize];
+    fn fetch_next_instruction(&mut self) -> Result<u16, Fault> {
+        self.memory.fetch_word(self.pc as usize)
+    }
 
-        // combine first and second part as u16
-        (first_part << 8) | second_part
+    /// Goes through the decode cache, unless the journal must see the fetch.
+    fn next_op_code(&mut self) -> Result<OpCode, Fault> {
+        let pc = self.pc as usize;
+        self.invalidate_written();
+        let Some(cache) = self
+            .decode_cache
+            .as_mut()
+            .filter(|_| !self.memory.is_journal_enabled())
+        else {
+            return Ok(decode_instruction(self.fetch_next_instruction()?));
+        };
+
+        if let Some(op_code) = cache.get(pc) {
+            return Ok(op_code);
+        }
+        let op_code = decode_instruction(self.memory.fetch_word(pc)?);
+        cache.insert(pc, op_code);
+        Ok(op_code)
+    }
+
+    fn block_at(&mut self, address: usize) -> Option<Rc<Block>> {
+        let blocks = self.blocks.as_mut()?;
+        blocks
+            .get(address)
+            .or_else(|| blocks.compile(&self.memory, address))
+    }
+
+    /// Drops the decoded instructions and blocks covering bytes written since the last call.
+    fn invalidate_written(&mut self) {
+        if self.memory.written().is_empty() {
+            return;
+        }
+        for &address in self.memory.written() {
+            if let Some(cache) = &mut self.decode_cache {
+                cache.invalidate(address);
+            }
+            if let Some(blocks) = &mut self.blocks {
+                blocks.invalidate(address);
+            }
+        }
+        self.memory.clear_written();
+    }
+
+    pub fn engine(&self) -> Engine {
+        self.engine
+    }
+
+    pub fn set_engine(&mut self, engine: Engine) {
+        let size = self.memory.size();
+        self.engine = engine;
+        self.memory
+            .set_write_tracking(engine != Engine::Interpreter);
+        self.decode_cache = (engine != Engine::Interpreter).then(|| DecodeCache::new(size));
+        self.blocks = (engine == Engine::Blocks).then(|| BlockCache::new(size));
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+    }
+
+    /// Makes `CXNN` reproducible, e.g. to compare runs.
+    pub fn seed_random(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
     }
 
     fn clear_screen(&mut self) {
@@ -256,14 +533,15 @@ impl Cpu {
         self.should_render = true;
     }
 
-    fn display(&mut self, x: usize, y: usize, nibble: u8) {
+    /// `DXYN`: draws `nibble` rows of the sprite at I at (VX, VY).
+    pub fn display(&mut self, x: usize, y: usize, nibble: u8) -> Result<(), Fault> {
         let mut vy = self.v[y] % 32;
         self.v[0xF] = 0;
 
         'lines: for sprite_row in 0..nibble {
             let mut vx = self.v[x] % 64;
-            let sprite_index = (self.i + sprite_row as u16) as usize;
-            let sprite_data = self.memory[sprite_index] as u8;
+            let sprite_index = self.i as usize + sprite_row as usize;
+            let sprite_data = self.memory.read(sprite_index)?;
 
             'columns: for i in (0..8).rev() {
                 // update the screen sprite
@@ -275,15 +553,23 @@ impl Cpu {
                 }
                 vx += 1;
                 if vx >= 64 {
-                    break 'columns;
+                    if !self.quirks.sprite_wrapping {
+                        break 'columns;
+                    }
+                    vx = 0;
                 }
             }
             vy += 1;
             if vy >= 32 {
-                break 'lines;
+                if !self.quirks.sprite_wrapping {
+                    break 'lines;
+                }
+                vy = 0;
             }
         }
         self.should_render = true;
+        self.waiting_for_vblank = self.quirks.display_wait;
+        Ok(())
     }
 
     fn draw_pixel(&mut self, x: usize, y: usize, value: bool) -> bool {
@@ -298,15 +584,6 @@ impl Cpu {
         false
     }
 
-    pub fn set_key_pressed(&mut self, key: u16) {
-        self.is_key_pressed = true;
-        self.key_pressed = Some(key);
-    }
-
-    pub fn set_key_released(&mut self) {
-        self.is_key_pressed = false;
-    }
-
     pub fn set_keys_pressed(&mut self, keys: HashSet<u8>) {
         self.keys_pressed = keys;
     }
@@ -314,9 +591,15 @@ impl Cpu {
 
 #[cfg(test)]
 mod tests {
-    use crate::cpu::new;
+    use crate::cpu::{new, Engine, MachineConfig};
     use crate::decoder::decode_instruction;
+    use crate::fault::Fault;
+    use crate::lockstep::differences;
+    use crate::memory::OutOfBounds;
     use crate::opcode::OpCode;
+    use crate::platform::Platform;
+    use clap::ValueEnum;
+    use proptest::prelude::*;
 
     #[test]
     fn can_load_rom_file() {
@@ -324,7 +607,7 @@ mod tests {
         let result = instance.load_rom("./roms/test.ch8");
 
         assert!(result.is_ok());
-        assert_ne!(instance.memory[0x200], 0);
+        assert_ne!(instance.memory.peek(0x200), Some(0));
     }
 
     #[test]
@@ -340,8 +623,177 @@ mod tests {
         let mut instance = new();
         instance.load_rom("./roms/test.ch8").unwrap();
 
-        let instruction = instance.fetch_next_instruction();
+        let instruction = instance.fetch_next_instruction().unwrap();
         let op_code = decode_instruction(instruction);
         assert_eq!(op_code, OpCode::Jump(520))
     }
+
+    #[test]
+    fn runaway_recursion_overflows_the_stack() {
+        let mut instance = new();
+        instance.memory.load(0x200, &[0x22, 0x00]).unwrap();
+        instance.pc = 0x200;
+
+        for _ in 0..16 {
+            instance.tick().unwrap();
+        }
+        assert_eq!(instance.tick(), Err(Fault::StackOverflow { depth: 16 }));
+    }
+
+    #[test]
+    fn skips_over_both_words_of_long_index_loads() {
+        let mut instance = new();
+        // SE V0, 0 then LD I, long 0x1234 (skipped) then LD I, long 0xABCD
+        instance
+            .memory
+            .load(
+                0x200,
+                &[0x30, 0x00, 0xF0, 0x00, 0x12, 0x34, 0xF0, 0x00, 0xAB, 0xCD],
+            )
+            .unwrap();
+        instance.pc = 0x200;
+
+        instance.tick().unwrap();
+        assert_eq!(instance.pc, 0x206);
+        instance.tick().unwrap();
+        assert_eq!((instance.i, instance.pc), (0xABCD, 0x20A));
+    }
+
+    #[test]
+    fn loads_roms_at_the_platform_address_within_memory() {
+        let mut instance = new();
+        let load_address = Platform::Eti660.load_address();
+        instance
+            .load_rom_at(&[0x60, 0x2A], load_address, load_address)
+            .unwrap();
+        instance.tick().unwrap();
+        assert_eq!((instance.v[0], instance.pc), (0x2A, 0x602));
+
+        assert!(instance.load_rom_at(&[0; 2], 0xFFF, 0x200).is_err());
+        assert!(instance.load_rom_at(&[0; 2], 0x200, 0xFFF).is_err());
+        assert!(instance.load_rom_at(&[0; 2], 0xFFE, 0xFFE).is_ok());
+    }
+
+    #[test]
+    fn display_wait_stalls_until_the_next_frame_with_every_engine() {
+        // DRW V0, V0, 1; ADD V1, 1; JP 0x200
+        let rom = [0xD0, 0x01, 0x71, 0x01, 0x12, 0x00];
+        for engine in [Engine::Interpreter, Engine::Cached, Engine::Blocks] {
+            let mut instance = MachineConfig {
+                platform: Platform::CosmacVip,
+                engine,
+                ..MachineConfig::default()
+            }
+            .boot(&rom, [0; 16])
+            .unwrap();
+
+            instance.run(50).unwrap();
+            assert_eq!(
+                (instance.pc(), instance.registers()[1]),
+                (0x202, 0),
+                "{engine:?}"
+            );
+            instance.tick().unwrap();
+            assert_eq!(instance.pc(), 0x202, "{engine:?}");
+
+            instance.decrement_timers();
+            instance.run(50).unwrap();
+            assert_eq!(
+                (instance.pc(), instance.registers()[1]),
+                (0x202, 1),
+                "{engine:?}"
+            );
+        }
+    }
+
+    /// Where the program counter may be after running `op_code` from `pc`.
+    fn allowed_next_pcs(op_code: OpCode, pc: u16) -> Vec<u16> {
+        let next = |instructions: u16| pc.wrapping_add(2 * instructions);
+        match op_code {
+            OpCode::Jump(address) | OpCode::CallSubroutine(address) => vec![address],
+            OpCode::RetFromSubroutine | OpCode::JumpWithV0Offset(_) => vec![],
+            OpCode::SkipIfRegisterEquals(..)
+            | OpCode::SkipIfRegisterNotEquals(..)
+            | OpCode::SkipIfBothRegistersEqual(..)
+            | OpCode::SkipIfBothRegistersNotEqual(..)
+            | OpCode::SkipIfKey(_)
+            | OpCode::SkipIfNotKey(_) => vec![next(1), next(2), next(3)],
+            OpCode::GetKey(_) => vec![pc],
+            OpCode::LoadIndexLong => vec![next(2)],
+            _ => vec![next(1)],
+        }
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(64))]
+
+        #[test]
+        fn arbitrary_programs_never_panic_and_engines_agree(
+            rom in prop::collection::vec(any::<u8>(), 0..=0xE00),
+            registers in any::<[u8; 16]>(),
+            wrap in any::<bool>(),
+            platform in prop::sample::select(Platform::value_variants()),
+        ) {
+            let [mut interpreter, mut blocks] = [Engine::Interpreter, Engine::Blocks]
+                .map(|engine| {
+                    let out_of_bounds = if wrap { OutOfBounds::Wrap } else { OutOfBounds::Fault };
+                    MachineConfig { out_of_bounds, platform, engine, ..MachineConfig::default() }
+                        .boot(&rom, registers)
+                        .unwrap()
+                });
+
+            for step in 0..500 {
+                // frame boundaries end the wait of a `DXYN` with the display wait quirk
+                if step % 50 == 0 {
+                    interpreter.decrement_timers();
+                    blocks.decrement_timers();
+                }
+                let pc = interpreter.pc();
+                let stalled = interpreter.is_waiting_for_vblank();
+                let word = [pc, pc.wrapping_add(1)]
+                    .map(|address| interpreter.memory().peek(address as usize));
+                let results = [interpreter.run(1), blocks.run(1)];
+
+                prop_assert_eq!(results[0], results[1]);
+                prop_assert_eq!(differences(&interpreter, &blocks), Vec::<String>::new());
+                prop_assert!(interpreter.stack().len() <= interpreter.stack().depth());
+                if results[0].is_err() {
+                    break;
+                }
+                if stalled {
+                    prop_assert_eq!(interpreter.pc(), pc);
+                } else if let [Some(high), Some(low)] = word {
+                    let allowed = allowed_next_pcs(decode_instruction(u16::from_be_bytes([high, low])), pc);
+                    prop_assert!(allowed.is_empty() || allowed.contains(&interpreter.pc()));
+                }
+            }
+        }
+
+        #[test]
+        fn arithmetic_sets_vf_to_the_carry_or_borrow(
+            x in 0u16..0xF,
+            y in 0u16..0xF,
+            vx in any::<u8>(),
+            vy in any::<u8>(),
+        ) {
+            let cases = [
+                (0x4, vx.wrapping_add(vy), (vx as u16 + vy as u16 > 0xFF) as u8),
+                (0x5, vx.wrapping_sub(vy), (vx >= vy) as u8),
+                (0x7, vy.wrapping_sub(vx), (vy >= vx) as u8),
+                (0x6, vx >> 1, vx & 1),
+                (0xE, vx << 1, vx >> 7),
+            ];
+            // with x == y both operands are the same register
+            let vy_as_set = if x == y { vx } else { vy };
+            prop_assume!(vy_as_set == vy);
+            for (n, result, flag) in cases {
+                let mut cpu = new();
+                cpu.registers_mut()[y as usize] = vy;
+                cpu.registers_mut()[x as usize] = vx;
+                cpu.execute(decode_instruction(0x8000 | x << 8 | y << 4 | n)).unwrap();
+                prop_assert_eq!(cpu.registers()[x as usize], result, "8XY{:X}", n);
+                prop_assert_eq!(cpu.registers()[0xF], flag, "8XY{:X}", n);
+            }
+        }
+    }
 }
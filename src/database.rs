@@ -0,0 +1,296 @@
+use crate::analysis::analyze;
+use crate::emulator::INSTRUCTIONS_PER_FRAME;
+use crate::frontend::Keymap;
+use crate::palette::{parse_color, Palette};
+use crate::platform::{Platform, Quirks, PROGRAM_START};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use sha1::{Digest, Sha1};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// Entries for the ROMs shipped in `roms/`, in the chip-8-database layout.
+const PROGRAMS: &str = include_str!("../database/programs.json");
+const HASHES: &str = include_str!("../database/sha1-hashes.json");
+const PLATFORMS: &str = include_str!("../database/platforms.json");
+
+/// How a ROM should be run, from the database or guessed from its instructions.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Profile {
+    /// Known only for ROMs found in the database.
+    pub title: Option<String>,
+    pub platform: Platform,
+    pub quirks: Quirks,
+    pub instructions_per_frame: u32,
+    pub keymap: Keymap,
+    pub palette: Option<Palette>,
+}
+
+/// ROM metadata in the format of https://github.com/chip-8/chip-8-database:
+/// programs indexed by the SHA-1 of their ROMs, and the platforms they run on.
+pub struct Database {
+    programs: Vec<Program>,
+    hashes: HashMap<String, usize>,
+    platforms: Vec<PlatformEntry>,
+}
+
+#[derive(Deserialize)]
+struct Program {
+    title: String,
+    #[serde(default)]
+    roms: HashMap<String, Rom>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Rom {
+    #[serde(default)]
+    platforms: Vec<String>,
+    tickrate: Option<u32>,
+    #[serde(default)]
+    quirky_platforms: HashMap<String, QuirkFlags>,
+    keys: Option<HashMap<String, u8>>,
+    colors: Option<Colors>,
+}
+
+#[derive(Deserialize)]
+struct Colors {
+    #[serde(default)]
+    pixels: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PlatformEntry {
+    id: String,
+    default_tickrate: Option<u32>,
+    #[serde(default)]
+    quirks: QuirkFlags,
+}
+
+/// The database's quirk names, each one overriding the platform's default when present.
+#[derive(Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct QuirkFlags {
+    shift: Option<bool>,
+    memory_increment_by_x: Option<bool>,
+    memory_leave_i_unchanged: Option<bool>,
+    wrap: Option<bool>,
+    jump: Option<bool>,
+    logic: Option<bool>,
+    vblank: Option<bool>,
+}
+
+impl QuirkFlags {
+    /// `memoryIncrementByX` can't be told apart from the usual `X + 1` increment here.
+    fn apply(&self, quirks: &mut Quirks) {
+        if let Some(shift) = self.shift {
+            quirks.shift_uses_vy = !shift;
+        }
+        if let Some(leave) = self.memory_leave_i_unchanged {
+            quirks.load_store_increments_index = !leave;
+        }
+        if self.memory_increment_by_x == Some(true) {
+            quirks.load_store_increments_index = true;
+        }
+        if let Some(wrap) = self.wrap {
+            quirks.sprite_wrapping = wrap;
+        }
+        if let Some(jump) = self.jump {
+            quirks.jump_uses_vx = jump;
+        }
+        if let Some(logic) = self.logic {
+            quirks.logic_resets_vf = logic;
+        }
+        if let Some(vblank) = self.vblank {
+            quirks.display_wait = vblank;
+        }
+    }
+}
+
+impl Database {
+    /// The entries compiled into the emulator.
+    pub fn bundled() -> Database {
+        Database::parse(PROGRAMS, HASHES, PLATFORMS).expect("the bundled database should be valid")
+    }
+
+    /// Reads `programs.json`, `sha1-hashes.json` and `platforms.json` from a
+    /// directory, e.g. the `database` folder of a chip-8-database checkout.
+    pub fn load(directory: &Path) -> Result<Database, String> {
+        let read = |name: &str| {
+            let path = directory.join(name);
+            fs::read_to_string(&path).map_err(|e| format!("can't read {}: {e}", path.display()))
+        };
+        Database::parse(
+            &read("programs.json")?,
+            &read("sha1-hashes.json")?,
+            &read("platforms.json")?,
+        )
+    }
+
+    pub fn parse(programs: &str, hashes: &str, platforms: &str) -> Result<Database, String> {
+        Ok(Database {
+            programs: parse_json("programs", programs)?,
+            hashes: parse_json("sha1-hashes", hashes)?,
+            platforms: parse_json("platforms", platforms)?,
+        })
+    }
+
+    /// The database's profile of the ROM, or a guess when it isn't listed.
+    pub fn identify(&self, rom: &[u8]) -> Profile {
+        self.lookup(rom).unwrap_or_else(|| guess(rom))
+    }
+
+    /// Finds the ROM by its SHA-1 and picks the first of its platforms this emulator supports.
+    pub fn lookup(&self, rom: &[u8]) -> Option<Profile> {
+        let hash = sha1_hex(rom);
+        let program = self.programs.get(*self.hashes.get(&hash)?)?;
+        let entry = program.roms.get(&hash)?;
+        let (id, platform) = entry
+            .platforms
+            .iter()
+            .find_map(|id| Some((id, platform_from_id(id)?)))?;
+        let defaults = self.platforms.iter().find(|entry| &entry.id == id);
+
+        let mut quirks = match defaults {
+            Some(defaults) => {
+                let mut quirks = Quirks::default();
+                defaults.quirks.apply(&mut quirks);
+                quirks
+            }
+            None => platform.quirks(),
+        };
+        if let Some(overrides) = entry.quirky_platforms.get(id) {
+            overrides.apply(&mut quirks);
+        }
+
+        Some(Profile {
+            title: Some(program.title.clone()),
+            platform,
+            quirks,
+            instructions_per_frame: entry
+                .tickrate
+                .or(defaults.and_then(|defaults| defaults.default_tickrate))
+                .unwrap_or(INSTRUCTIONS_PER_FRAME),
+            keymap: entry.keys.as_ref().map(keymap).unwrap_or_default(),
+            palette: entry.colors.as_ref().and_then(palette),
+        })
+    }
+}
+
+/// Picks the least capable platform running every instruction reachable
+/// from the entry point, sprites often look like SCHIP's `00FF`.
+pub fn guess(rom: &[u8]) -> Profile {
+    let platform = analyze(rom, PROGRAM_START).platform;
+    Profile {
+        title: None,
+        platform,
+        quirks: platform.quirks(),
+        instructions_per_frame: INSTRUCTIONS_PER_FRAME,
+        keymap: Keymap::default(),
+        palette: None,
+    }
+}
+
+fn platform_from_id(id: &str) -> Option<Platform> {
+    match id {
+        "originalChip8" | "hybridVIP" | "chip8x" => Some(Platform::CosmacVip),
+        "modernChip8" => Some(Platform::Chip8),
+        "chip48" | "superchip1" | "superchip" => Some(Platform::SuperChip),
+        "xochip" => Some(Platform::XoChip),
+        _ => None,
+    }
+}
+
+fn keymap(keys: &HashMap<String, u8>) -> Keymap {
+    let key = |name: &str| keys.get(name).copied().filter(|&key| key < 16);
+    Keymap {
+        up: key("up"),
+        down: key("down"),
+        left: key("left"),
+        right: key("right"),
+        a: key("a"),
+        b: key("b"),
+    }
+}
+
+/// The first two pixel colours, background then foreground.
+fn palette(colors: &Colors) -> Option<Palette> {
+    match colors.pixels.as_slice() {
+        [background, foreground, ..] => Some(Palette {
+            background: parse_color(background)?,
+            foreground: parse_color(foreground)?,
+        }),
+        _ => None,
+    }
+}
+
+fn parse_json<T: DeserializeOwned>(name: &str, json: &str) -> Result<T, String> {
+    serde_json::from_str(json).map_err(|e| format!("invalid {name} database: {e}"))
+}
+
+fn sha1_hex(rom: &[u8]) -> String {
+    Sha1::digest(rom)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::database::{guess, Database};
+    use crate::frontend::Keymap;
+    use crate::platform::{Platform, Quirks};
+    use std::fs;
+
+    #[test]
+    fn looks_up_bundled_roms_by_hash() {
+        let database = Database::bundled();
+        let rom = fs::read("./roms/invaders.ch8").unwrap();
+
+        let profile = database.identify(&rom);
+
+        assert_eq!(profile.title.as_deref(), Some("Space Invaders"));
+        assert_eq!(profile.platform, Platform::Chip8);
+        assert_eq!(profile.quirks, Quirks::default());
+        assert_eq!(profile.instructions_per_frame, 12);
+        assert_eq!(profile.keymap.left, Some(4));
+        assert_eq!(profile.keymap.a, Some(5));
+        assert_eq!(database.lookup(&rom[1..]), None);
+    }
+
+    #[test]
+    fn applies_rom_overrides_and_guesses_unknown_roms() {
+        let database = Database::parse(
+            r##"[{"title": "Demo", "roms": {"aa3e5dcdd77b153f2e59bd0d8794fde33cb4e486": {
+                "platforms": ["megachip8", "superchip"], "tickrate": 200,
+                "quirkyPlatforms": {"superchip": {"jump": false}},
+                "keys": {"up": 2, "b": 16}, "colors": {"pixels": ["#000000", "#FF8000"]}}}}]"##,
+            r#"{"aa3e5dcdd77b153f2e59bd0d8794fde33cb4e486": 0}"#,
+            r#"[{"id": "superchip", "quirks": {"shift": true, "memoryLeaveIUnchanged": true, "jump": true}}]"#,
+        )
+        .unwrap();
+
+        let profile = database.identify(&[0x00, 0xFF]);
+        assert_eq!(profile.title.as_deref(), Some("Demo"));
+        assert_eq!(profile.platform, Platform::SuperChip);
+        assert_eq!(profile.quirks, Quirks::default());
+        assert_eq!(profile.instructions_per_frame, 200);
+        assert_eq!(
+            profile.keymap,
+            Keymap {
+                up: Some(2),
+                ..Keymap::default()
+            }
+        );
+        assert_eq!(profile.palette.unwrap().foreground, (0xFF, 0x80, 0x00));
+
+        assert_eq!(database.identify(&[0x00, 0xFE]).title, None);
+        assert_eq!(guess(&[0x00, 0xFE]).platform, Platform::SuperChip);
+        assert_eq!(guess(&[0xF0, 0x00, 0x12, 0x34]).platform, Platform::XoChip);
+        assert_eq!(guess(&[0x00, 0xE0, 0x12, 0x00]).platform, Platform::Chip8);
+        // a sprite after the program's last jump
+        assert_eq!(guess(&[0x12, 0x00, 0x00, 0xFF]).platform, Platform::Chip8);
+    }
+}
@@ -0,0 +1,291 @@
+use crate::cpu::Cpu;
+use crate::expression::{parse_number, Expression};
+use crate::fault::Fault;
+use crate::memory::{Access, AccessKind};
+use crate::profiler::Profiler;
+use crate::trace::{Registers, Tracer};
+use log::warn;
+use std::fmt::{Display, Formatter};
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum WatchKind {
+    Read,
+    Write,
+    /// Either a read or a write, instruction fetches excluded.
+    Access,
+}
+
+/// Stops when an instruction reads or writes the watched bytes, be it
+/// through `FX55`, `FX65`, `FX33`, a `DXYN` sprite or the call stack.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub addresses: RangeInclusive<usize>,
+    pub kind: WatchKind,
+}
+
+impl Watchpoint {
+    fn matches(&self, access: &Access) -> bool {
+        let kind_matches = match (self.kind, access.kind) {
+            (_, AccessKind::Fetch) => false,
+            (WatchKind::Access, _) => true,
+            (WatchKind::Read, kind) => kind == AccessKind::Read,
+            (WatchKind::Write, kind) => kind == AccessKind::Write,
+        };
+        kind_matches && self.addresses.contains(&access.address)
+    }
+}
+
+/// See [parse_address_range].
+impl FromStr for Watchpoint {
+    type Err = String;
+
+    fn from_str(source: &str) -> Result<Self, Self::Err> {
+        Ok(Watchpoint {
+            addresses: parse_address_range(source)?,
+            kind: WatchKind::Access,
+        })
+    }
+}
+
+/// `0x300` or `0x300-0x30F`, both ends included.
+pub fn parse_address_range(source: &str) -> Result<RangeInclusive<usize>, String> {
+    let parse = |text: &str| parse_address(text).map(usize::from);
+    let (start, end) = match source.split_once('-') {
+        Some((start, end)) => (parse(start)?, parse(end)?),
+        None => (parse(source)?, parse(source)?),
+    };
+    if start > end {
+        return Err(format!("the range `{source}` ends before it starts"));
+    }
+    Ok(start..=end)
+}
+
+/// `0x600`, `1536` or `0b11000000000`.
+pub fn parse_address(source: &str) -> Result<u16, String> {
+    let address = parse_number(source.trim())?;
+    u16::try_from(address).map_err(|_| format!("invalid address `{address}`"))
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Breakpoint {
+    pub address: u16,
+    /// Only stop when this holds, every time the address is reached otherwise.
+    pub condition: Option<Expression>,
+}
+
+/// `0x2A4` or `0x2A4:V0 == 3`.
+impl FromStr for Breakpoint {
+    type Err = String;
+
+    fn from_str(source: &str) -> Result<Self, Self::Err> {
+        let (address, condition) = match source.split_once(':') {
+            Some((address, condition)) => (address, Some(condition.parse()?)),
+            None => (source, None),
+        };
+        let address = parse_address(address)?;
+        Ok(Breakpoint { address, condition })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Stop {
+    Breakpoint(u16),
+    Watchpoint(Access),
+    /// A condition that was false became true.
+    Condition(Expression),
+}
+
+impl Display for Stop {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Stop::Breakpoint(address) => write!(f, "breakpoint at {address:#05X}"),
+            Stop::Watchpoint(access) => write!(
+                f,
+                "watchpoint: {:?} of {:#04X} at {:#05X}",
+                access.kind, access.value, access.address
+            ),
+            Stop::Condition(condition) => write!(f, "condition {condition} became true"),
+        }
+    }
+}
+
+/// Executes instructions one at a time and reports when one of the
+/// breakpoints, watchpoints or conditions is hit, optionally tracing and
+/// profiling them.
+#[derive(Default)]
+pub struct Debugger {
+    breakpoints: Vec<Breakpoint>,
+    watchpoints: Vec<Watchpoint>,
+    /// Each condition along with its value after the last instruction.
+    conditions: Vec<(Expression, bool)>,
+    tracer: Option<Tracer>,
+    profiler: Option<Profiler>,
+}
+
+impl Debugger {
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) {
+        self.breakpoints.push(breakpoint);
+    }
+
+    pub fn remove_breakpoint(&mut self, address: u16) {
+        self.breakpoints
+            .retain(|breakpoint| breakpoint.address != address);
+    }
+
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        self.watchpoints.push(watchpoint);
+    }
+
+    pub fn remove_watchpoint(&mut self, watchpoint: &Watchpoint) {
+        self.watchpoints.retain(|watched| watched != watchpoint);
+    }
+
+    pub fn add_condition(&mut self, condition: Expression) {
+        self.conditions.push((condition, false));
+    }
+
+    pub fn set_tracer(&mut self, tracer: Tracer) {
+        self.tracer = Some(tracer);
+    }
+
+    pub fn set_profiler(&mut self, profiler: Profiler) {
+        self.profiler = Some(profiler);
+    }
+
+    pub fn profiler(&self) -> Option<&Profiler> {
+        self.profiler.as_ref()
+    }
+
+    /// Whether instructions must go through `step` one at a time.
+    pub fn is_observing(&self) -> bool {
+        self.is_active() || self.tracer.is_some() || self.profiler.is_some()
+    }
+
+    pub fn is_active(&self) -> bool {
+        !self.breakpoints.is_empty() || !self.watchpoints.is_empty() || !self.conditions.is_empty()
+    }
+
+    /// Runs one instruction. A breakpoint stops before the instruction at its
+    /// address runs, watchpoints and conditions right after the instruction that triggered them.
+    /// Nothing runs, and nothing is traced or profiled, while a `DXYN` waits for the frame to end.
+    pub fn step(&mut self, cpu: &mut Cpu) -> Result<Option<Stop>, Fault> {
+        if cpu.is_waiting_for_vblank() {
+            return Ok(None);
+        }
+        let instrumented = self.tracer.is_some() || self.profiler.is_some();
+        if (instrumented || !self.watchpoints.is_empty()) && !cpu.memory().is_journal_enabled() {
+            cpu.memory_mut().set_journal_enabled(true);
+        }
+
+        let before = instrumented.then(|| {
+            let pc = cpu.pc();
+            let byte = |offset: u16| cpu.memory().peek(pc.wrapping_add(offset) as usize);
+            let opcode = u16::from_be_bytes([byte(0).unwrap_or(0), byte(1).unwrap_or(0)]);
+            (pc, opcode, Registers::of(cpu))
+        });
+        let result = cpu.tick();
+        let accesses = cpu.memory_mut().drain_journal();
+        if let Some((pc, opcode, registers)) = before {
+            if let Some(profiler) = &mut self.profiler {
+                profiler.record(pc, opcode, &accesses);
+            }
+            if let Some(tracer) = &mut self.tracer {
+                let fault = result.as_ref().err().copied();
+                if let Err(e) = tracer.record(pc, opcode, registers, cpu, &accesses, fault) {
+                    warn!("tracing stopped: {e}");
+                    self.tracer = None;
+                }
+            }
+        }
+
+        result?;
+        if !self.is_active() {
+            return Ok(None);
+        }
+
+        if let Some(access) = accesses.into_iter().find(|access| {
+            self.watchpoints
+                .iter()
+                .any(|watchpoint| watchpoint.matches(access))
+        }) {
+            return Ok(Some(Stop::Watchpoint(access)));
+        }
+
+        let mut stop = None;
+        for (condition, was_true) in self.conditions.iter_mut() {
+            let is_true = condition.is_true(cpu);
+            if is_true && !*was_true && stop.is_none() {
+                stop = Some(Stop::Condition(condition.clone()));
+            }
+            *was_true = is_true;
+        }
+        if stop.is_some() {
+            return Ok(stop);
+        }
+
+        let pc = cpu.pc();
+        let hit = self.breakpoints.iter().any(|breakpoint| {
+            breakpoint.address == pc
+                && breakpoint
+                    .condition
+                    .as_ref()
+                    .is_none_or(|condition| condition.is_true(cpu))
+        });
+        Ok(hit.then_some(Stop::Breakpoint(pc)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cpu::new;
+    use crate::debugger::{parse_address_range, Debugger, Stop, WatchKind, Watchpoint};
+    use crate::memory::{Access, AccessKind};
+
+    #[test]
+    fn stops_on_memory_writes_from_fx55() {
+        let mut cpu = new();
+        // LD I, 0x300 then LD [I], V1
+        cpu.load_rom_bytes(&[0xA3, 0x00, 0xF1, 0x55]).unwrap();
+        let mut debugger = Debugger::default();
+        debugger.add_watchpoint(Watchpoint {
+            addresses: 0x301..=0x301,
+            kind: WatchKind::Write,
+        });
+
+        assert_eq!(debugger.step(&mut cpu), Ok(None));
+        assert_eq!(
+            debugger.step(&mut cpu),
+            Ok(Some(Stop::Watchpoint(Access {
+                kind: AccessKind::Write,
+                address: 0x301,
+                value: 0
+            })))
+        );
+    }
+
+    #[test]
+    fn conditional_breakpoints_stop_only_when_true() {
+        let mut cpu = new();
+        // ADD V0, 1 then JP 0x200
+        cpu.load_rom_bytes(&[0x70, 0x01, 0x12, 0x00]).unwrap();
+        let mut debugger = Debugger::default();
+        debugger.add_breakpoint("0x200:V0 == 3".parse().unwrap());
+
+        let steps = (1..10)
+            .find(|_| debugger.step(&mut cpu).unwrap().is_some())
+            .unwrap();
+        assert_eq!(steps, 6);
+        assert_eq!(cpu.registers()[0], 3);
+    }
+
+    #[test]
+    fn rejects_reversed_ranges_and_ends_outside_the_address_space() {
+        assert_eq!(parse_address_range("0x200-0x2FF"), Ok(0x200..=0x2FF));
+        assert_eq!(parse_address_range("0x300"), Ok(0x300..=0x300));
+        assert!(parse_address_range("0x300-0x200").is_err());
+        assert!(parse_address_range("0x10--1").is_err());
+        assert!(parse_address_range("0x10-0x10000").is_err());
+    }
+}
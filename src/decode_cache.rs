@@ -0,0 +1,65 @@
+use crate::opcode::OpCode;
+
+/// Decoded instructions keyed by the address they start at. The CPU drops the
+/// entries covering written bytes, so self-modifying code such as `FX55` or
+/// `FX33` over the program is decoded again.
+#[derive(Clone, Debug)]
+pub struct DecodeCache {
+    entries: Vec<Option<OpCode>>,
+}
+
+impl DecodeCache {
+    pub fn new(memory_size: usize) -> DecodeCache {
+        DecodeCache {
+            entries: vec![None; memory_size],
+        }
+    }
+
+    pub fn get(&self, address: usize) -> Option<OpCode> {
+        self.entries.get(address).copied().flatten()
+    }
+
+    /// Forgets the instructions overlapping the byte at `address`.
+    pub fn invalidate(&mut self, address: usize) {
+        // an instruction starting one byte earlier covers it too
+        for start in [address.wrapping_sub(1), address] {
+            if let Some(entry) = self.entries.get_mut(start) {
+                *entry = None;
+            }
+        }
+    }
+
+    /// Instructions straddling the end of memory are not cached, their second
+    /// byte depends on the out-of-bounds policy.
+    pub fn insert(&mut self, address: usize, opcode: OpCode) {
+        if address + 1 < self.entries.len() {
+            self.entries[address] = Some(opcode);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cpu::new;
+
+    #[test]
+    fn decodes_again_after_self_modifying_writes() {
+        let mut cpu = new();
+        // 0x200: LD I, 0x206; LD [I], V1; JP 0x206
+        // 0x206: LD V0, 0x01, overwritten with LD V0, 0x02 from V0 and V1
+        cpu.load_rom_bytes(&[0xA2, 0x06, 0xF1, 0x55, 0x12, 0x06, 0x60, 0x01, 0x12, 0x06])
+            .unwrap();
+        cpu.registers_mut()[0] = 0x60;
+        cpu.registers_mut()[1] = 0x02;
+        cpu.set_pc(0x206);
+
+        cpu.tick().unwrap();
+        assert_eq!(cpu.registers()[0], 0x01);
+        cpu.registers_mut()[0] = 0x60;
+        cpu.set_pc(0x200);
+        for _ in 0..4 {
+            cpu.tick().unwrap();
+        }
+        assert_eq!(cpu.registers()[0], 0x02);
+    }
+}
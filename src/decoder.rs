@@ -1,5 +1,3 @@
-use log::info;
-
 use crate::opcode::OpCode;
 
 pub fn decode_instruction(instruction: u16) -> OpCode {
@@ -10,7 +8,7 @@ pub fn decode_instruction(instruction: u16) -> OpCode {
     let nn = (instruction & 0x00FF) as u8;
     let nnn = instruction & 0x0FFF;
 
-    let opcode = match (kind, x, y, n) {
+    match (kind, x, y, n) {
         (0x0, 0x0, 0xE, 0x0) => OpCode::ClearScreen,
         (0x0, 0x0, 0xE, 0xE) => OpCode::RetFromSubroutine,
         (0x1, _, _, _) => OpCode::Jump(nnn),
@@ -51,9 +49,26 @@ pub fn decode_instruction(instruction: u16) -> OpCode {
         (0xF, _, 0x3, 0x3) => OpCode::StoreBCDRepresentationOfRegister(x as u8),
         (0xF, _, 0x5, 0x5) => OpCode::LoadFromRegistersToMemory(x as u8),
         (0xF, _, 0x6, 0x5) => OpCode::LoadFromMemoryToRegisters(x as u8),
-        _ => OpCode::Unknown,
-    };
+        (0xF, 0x0, 0x0, 0x0) => OpCode::LoadIndexLong,
+        _ => OpCode::Unknown(instruction),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::decoder::decode_instruction;
+    use crate::opcode::OpCode;
 
-    info!("instruction={instruction}|opcode={opcode}");
-    opcode
+    #[test]
+    fn decodes_every_word() {
+        for word in 0..=u16::MAX {
+            let op_code = decode_instruction(word);
+            // these families have no invalid encodings
+            if matches!(word >> 12, 0x1..=0x4 | 0x6 | 0x7 | 0xA..=0xD) {
+                assert_ne!(op_code, OpCode::Unknown(word), "{word:04X}");
+            }
+            assert_eq!(op_code.encode(), word, "{op_code}");
+            assert_eq!(decode_instruction(op_code.encode()), op_code);
+        }
+    }
 }
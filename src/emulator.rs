@@ -0,0 +1,397 @@
+use crate::cpu::Cpu;
+use crate::debugger::{Debugger, Stop};
+use crate::decoder::decode_instruction;
+use crate::fault::Fault;
+use crate::frontend::{Audio, Command, Display, Input};
+use crate::mnemonic::Syntax;
+use crate::palette::Palette;
+use log::info;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+pub const FRAME_RATE: u32 = 60;
+/// ~3000 instructions per second at 60 frames per second.
+pub const INSTRUCTIONS_PER_FRAME: u32 = 50;
+/// Speeds the slow-motion hotkey cycles through, back to full speed after the last.
+const SLOW_MOTION_SPEEDS: [f64; 3] = [0.5, 0.25, 0.125];
+/// How far behind the schedule a frame may fall, e.g. after a pause, before
+/// the schedule restarts instead of catching up.
+const MAX_LAG: Duration = Duration::from_millis(100);
+
+/// Host-side features driven at frame boundaries, e.g. screenshots and recordings.
+pub trait Hooks {
+    fn on_frame(&mut self, _cpu: &Cpu) {}
+
+    fn on_command(&mut self, _command: Command, _cpu: &Cpu, _palette: Palette) {}
+}
+
+impl Hooks for () {}
+
+#[derive(Copy, Clone, Debug)]
+pub struct RunConfig {
+    /// Stop after this many frames, run until the input asks to quit otherwise.
+    pub max_frames: Option<u32>,
+    /// Sleep between frames to run at 60 Hz, or run as fast as possible.
+    pub real_time: bool,
+    /// Emulated seconds per wall-clock second when running in real time.
+    pub speed: f64,
+    /// How the next instruction is written when the debugger pauses.
+    pub syntax: Syntax,
+    /// Instructions run between two timer decrements.
+    pub instructions_per_frame: u32,
+}
+
+impl Default for RunConfig {
+    fn default() -> Self {
+        RunConfig {
+            max_frames: None,
+            real_time: false,
+            speed: 1.0,
+            syntax: Syntax::default(),
+            instructions_per_frame: INSTRUCTIONS_PER_FRAME,
+        }
+    }
+}
+
+/// Paces frames by emulated time: frame `n` after the last restart is due
+/// `n / (60 * speed)` seconds after it, whatever the previous frames took.
+pub struct Scheduler {
+    origin: Instant,
+    frames: u32,
+    speed: f64,
+}
+
+impl Scheduler {
+    pub fn new(speed: f64, now: Instant) -> Self {
+        Scheduler {
+            origin: now,
+            frames: 0,
+            speed,
+        }
+    }
+
+    pub fn speed(&self) -> f64 {
+        self.speed
+    }
+
+    pub fn set_speed(&mut self, speed: f64, now: Instant) {
+        self.speed = speed;
+        self.restart(now);
+    }
+
+    /// Forgets the frames run so far, e.g. after running uncapped or paused.
+    pub fn restart(&mut self, now: Instant) {
+        self.origin = now;
+        self.frames = 0;
+    }
+
+    /// Counts a frame and returns how long to wait until the next one is due. A
+    /// speed without a representable frame period runs at normal speed.
+    pub fn frame_done(&mut self, now: Instant) -> Duration {
+        self.frames += 1;
+        let frame_duration = Duration::from_secs_f64(1.0 / FRAME_RATE as f64);
+        let period = Duration::try_from_secs_f64(1.0 / (FRAME_RATE as f64 * self.speed))
+            .unwrap_or(frame_duration);
+        let due = period
+            .checked_mul(self.frames)
+            .and_then(|elapsed| self.origin.checked_add(elapsed));
+        match due {
+            Some(due) if now.saturating_duration_since(due) <= MAX_LAG => {
+                due.saturating_duration_since(now)
+            }
+            _ => {
+                self.restart(now);
+                Duration::ZERO
+            }
+        }
+    }
+}
+
+/// Runs one 60 Hz frame: a batch of instructions followed by a timer decrement.
+/// Frontends render, sample input and capture at this boundary. The frame is
+/// cut short when the debugger stops.
+pub fn run_frame(
+    cpu: &mut Cpu,
+    debugger: &mut Debugger,
+    instructions: u32,
+) -> Result<Option<Stop>, Fault> {
+    if !debugger.is_observing() {
+        cpu.run(instructions)?;
+        cpu.decrement_timers();
+        return Ok(None);
+    }
+    for _ in 0..instructions {
+        // don't trace or profile the idling of a stalled `DXYN`
+        if cpu.is_waiting_for_vblank() {
+            break;
+        }
+        if let Some(stop) = debugger.step(cpu)? {
+            return Ok(Some(stop));
+        }
+    }
+    cpu.decrement_timers();
+    Ok(None)
+}
+
+/// The loop shared by every frontend: input, a frame of emulation, then output.
+pub fn run(
+    cpu: &mut Cpu,
+    display: &mut dyn Display,
+    audio: &mut dyn Audio,
+    input: &mut dyn Input,
+    hooks: &mut dyn Hooks,
+    debugger: &mut Debugger,
+    config: RunConfig,
+) -> Result<(), String> {
+    let frame_duration = Duration::from_secs_f64(1.0 / FRAME_RATE as f64);
+    let describe_fault = |fault: Fault, cpu: &Cpu| format!("{fault} (pc={:#05X})", cpu.pc());
+    display.render(&cpu.screen)?;
+
+    let mut scheduler = Scheduler::new(config.speed, Instant::now());
+    let mut instructions_per_frame = config.instructions_per_frame;
+    let mut turbo = false;
+    let mut paused = false;
+    let mut heatmap = false;
+    let mut frame = 0;
+    while config
+        .max_frames
+        .is_none_or(|max_frames| frame < max_frames)
+    {
+        let mut advance = false;
+        for command in input.poll() {
+            match command {
+                Command::Quit => return Ok(()),
+                Command::Continue => paused = false,
+                Command::Step if paused => {
+                    // a stalled `DXYN` only resumes at the end of the frame
+                    if cpu.is_waiting_for_vblank() {
+                        cpu.decrement_timers();
+                    }
+                    debugger
+                        .step(cpu)
+                        .map_err(|fault| describe_fault(fault, cpu))?;
+                    info!("step: {}", describe_state(cpu, config.syntax));
+                }
+                Command::TogglePause => {
+                    paused = !paused;
+                    info!("{}", if paused { "paused" } else { "resumed" });
+                }
+                Command::AdvanceFrame if paused => advance = true,
+                Command::ToggleTurbo => {
+                    turbo = !turbo;
+                    info!("turbo {}", if turbo { "on" } else { "off" });
+                }
+                Command::SlowMotion => {
+                    let speed = SLOW_MOTION_SPEEDS
+                        .into_iter()
+                        .find(|&speed| speed < scheduler.speed())
+                        .unwrap_or(1.0);
+                    scheduler.set_speed(speed, Instant::now());
+                    info!("speed x{speed}");
+                }
+                Command::Faster => {
+                    instructions_per_frame += (instructions_per_frame / 4).max(1);
+                    info!("{instructions_per_frame} instructions per frame");
+                }
+                Command::Slower => {
+                    instructions_per_frame =
+                        (instructions_per_frame - (instructions_per_frame / 4).max(1)).max(1);
+                    info!("{instructions_per_frame} instructions per frame");
+                }
+                Command::ToggleHeatmap if debugger.profiler().is_some() => {
+                    heatmap = !heatmap;
+                    if !heatmap {
+                        display.set_overlay(None);
+                        cpu.should_render = true;
+                    }
+                }
+                Command::ToggleHeatmap => info!("the heatmap needs profiling, see --profile"),
+                _ => hooks.on_command(command, cpu, display.palette()),
+            }
+        }
+        cpu.set_keys_pressed(input.pressed_keys());
+
+        if !paused || advance {
+            let stop = run_frame(cpu, debugger, instructions_per_frame)
+                .map_err(|fault| describe_fault(fault, cpu))?;
+            if let Some(stop) = stop {
+                info!("{stop}, paused: {}", describe_state(cpu, config.syntax));
+                paused = true;
+            }
+        }
+        if let Some(profiler) = debugger.profiler().filter(|_| heatmap) {
+            display.set_overlay(Some(profiler.heatmap()));
+            cpu.should_render = true;
+        }
+        if cpu.should_render {
+            display.render(&cpu.screen)?;
+            cpu.should_render = false;
+        }
+        audio.set_beeping(cpu.is_sound_playing());
+        hooks.on_frame(cpu);
+        frame += 1;
+
+        if config.real_time {
+            if turbo {
+                scheduler.restart(Instant::now());
+            } else if paused {
+                // keep polling the input at 60 Hz whatever the speed
+                sleep(frame_duration);
+                scheduler.restart(Instant::now());
+            } else {
+                sleep(scheduler.frame_done(Instant::now()));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn describe_state(cpu: &Cpu, syntax: Syntax) -> String {
+    let byte = |offset: u16| {
+        let address = cpu.pc().wrapping_add(offset) as usize;
+        cpu.memory().peek(address).unwrap_or_default()
+    };
+    let next = decode_instruction(u16::from_be_bytes([byte(0), byte(1)]));
+    format!(
+        "pc={:#05X} ({}) i={:#05X} sp={} dt={} st={} v={:02X?}",
+        cpu.pc(),
+        next.mnemonic(syntax),
+        cpu.index(),
+        cpu.stack().len(),
+        cpu.delay_timer(),
+        cpu.sound_timer(),
+        cpu.registers()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cpu::new;
+    use crate::debugger::Debugger;
+    use crate::emulator::{run, RunConfig, Scheduler};
+    use crate::frontend::doubles::{RecordingAudio, RecordingDisplay, ScriptedInput};
+    use crate::frontend::Command;
+    use crate::platform::Platform;
+    use std::collections::HashSet;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn drives_the_frontend_until_quit() {
+        let mut cpu = new();
+        cpu.load_rom("./roms/2-ibm-logo.ch8").unwrap();
+        let mut display = RecordingDisplay::default();
+        let mut audio = RecordingAudio::default();
+        let mut input = ScriptedInput::new(vec![
+            (HashSet::new(), vec![]),
+            (HashSet::from([0x1]), vec![]),
+            (HashSet::new(), vec![Command::Quit]),
+        ]);
+
+        run(
+            &mut cpu,
+            &mut display,
+            &mut audio,
+            &mut input,
+            &mut (),
+            &mut Debugger::default(),
+            RunConfig::default(),
+        )
+        .unwrap();
+
+        assert_eq!(audio.beeps, vec![false, false]);
+        assert!(display.frames.len() > 1);
+        assert!(display
+            .frames
+            .last()
+            .unwrap()
+            .iter()
+            .flatten()
+            .any(|&lit| lit));
+    }
+
+    #[test]
+    fn steps_past_a_display_wait_while_paused() {
+        let mut cpu = new();
+        cpu.set_quirks(Platform::CosmacVip.quirks());
+        // DRW V0, V0, 1; ADD V1, 1; JP 0x200
+        cpu.load_rom_bytes(&[0xD0, 0x01, 0x71, 0x01, 0x12, 0x00])
+            .unwrap();
+        let mut input = ScriptedInput::new(vec![
+            (HashSet::new(), vec![Command::TogglePause]),
+            (HashSet::new(), vec![Command::Step, Command::Step]),
+            (HashSet::new(), vec![Command::Quit]),
+        ]);
+
+        run(
+            &mut cpu,
+            &mut RecordingDisplay::default(),
+            &mut RecordingAudio::default(),
+            &mut input,
+            &mut (),
+            &mut Debugger::default(),
+            RunConfig::default(),
+        )
+        .unwrap();
+
+        assert_eq!((cpu.pc(), cpu.registers()[1]), (0x204, 1));
+    }
+
+    #[test]
+    fn paces_frames_by_emulated_time() {
+        let start = Instant::now();
+        let mut scheduler = Scheduler::new(0.5, start);
+        let period = Duration::from_secs_f64(1.0 / 30.0);
+
+        // a slow frame is made up for by a shorter wait for the next one
+        let close = |wait: Duration, expected: Duration| {
+            assert!(
+                wait.abs_diff(expected) < Duration::from_micros(1),
+                "{wait:?}"
+            )
+        };
+        close(scheduler.frame_done(start + period / 4), period * 3 / 4);
+        assert_eq!(scheduler.frame_done(start + period * 2), Duration::ZERO);
+        close(scheduler.frame_done(start + period * 2), period);
+
+        // far behind, e.g. after a pause, the schedule restarts
+        let late = start + Duration::from_secs(5);
+        assert_eq!(scheduler.frame_done(late), Duration::ZERO);
+        close(scheduler.frame_done(late), period);
+
+        // speeds without a frame period don't panic
+        for speed in [1e-300, 0.0, -1.0, f64::NAN] {
+            scheduler.set_speed(speed, start);
+            scheduler.frame_done(start);
+        }
+    }
+
+    #[test]
+    fn pauses_and_advances_a_frame_at_a_time() {
+        let mut cpu = new();
+        cpu.load_rom_bytes(&[0x70, 0x01, 0x12, 0x00]).unwrap();
+        let mut input = ScriptedInput::new(vec![
+            (HashSet::new(), vec![Command::TogglePause]),
+            (HashSet::new(), vec![Command::AdvanceFrame]),
+            (HashSet::new(), vec![]),
+            (HashSet::new(), vec![Command::Faster, Command::AdvanceFrame]),
+            (HashSet::new(), vec![Command::Quit]),
+        ]);
+
+        run(
+            &mut cpu,
+            &mut RecordingDisplay::default(),
+            &mut RecordingAudio::default(),
+            &mut input,
+            &mut (),
+            &mut Debugger::default(),
+            RunConfig {
+                instructions_per_frame: 4,
+                ..RunConfig::default()
+            },
+        )
+        .unwrap();
+
+        // two instructions per loop, 4 then 5 instructions per frame
+        assert_eq!(cpu.registers()[0], 2 + 3);
+    }
+}
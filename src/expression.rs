@@ -0,0 +1,315 @@
+use crate::cpu::Cpu;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// Boolean and arithmetic expressions over the machine state, such as
+/// `V3 == 0x10 && I > 0x300` or `[I + 2] != 0`, used by conditional breakpoints.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expression {
+    Number(i64),
+    Register(usize),
+    Index,
+    ProgramCounter,
+    StackPointer,
+    DelayTimer,
+    SoundTimer,
+    /// Byte of memory at the computed address.
+    Memory(Box<Expression>),
+    Not(Box<Expression>),
+    Binary(Box<Expression>, Operator, Box<Expression>),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Operator {
+    Or,
+    And,
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+    Add,
+    Sub,
+    BitAnd,
+    BitOr,
+}
+
+impl Operator {
+    fn apply(self, left: i64, right: i64) -> i64 {
+        match self {
+            Operator::Or => (left != 0 || right != 0) as i64,
+            Operator::And => (left != 0 && right != 0) as i64,
+            Operator::Equal => (left == right) as i64,
+            Operator::NotEqual => (left != right) as i64,
+            Operator::Less => (left < right) as i64,
+            Operator::LessOrEqual => (left <= right) as i64,
+            Operator::Greater => (left > right) as i64,
+            Operator::GreaterOrEqual => (left >= right) as i64,
+            Operator::Add => left.wrapping_add(right),
+            Operator::Sub => left.wrapping_sub(right),
+            Operator::BitAnd => left & right,
+            Operator::BitOr => left | right,
+        }
+    }
+
+    fn symbol(self) -> &'static str {
+        match self {
+            Operator::Or => "||",
+            Operator::And => "&&",
+            Operator::Equal => "==",
+            Operator::NotEqual => "!=",
+            Operator::Less => "<",
+            Operator::LessOrEqual => "<=",
+            Operator::Greater => ">",
+            Operator::GreaterOrEqual => ">=",
+            Operator::Add => "+",
+            Operator::Sub => "-",
+            Operator::BitAnd => "&",
+            Operator::BitOr => "|",
+        }
+    }
+}
+
+/// Operators from the loosest to the tightest binding.
+const PRECEDENCE: [&[Operator]; 5] = [
+    &[Operator::Or],
+    &[Operator::And],
+    &[
+        Operator::Equal,
+        Operator::NotEqual,
+        Operator::LessOrEqual,
+        Operator::GreaterOrEqual,
+        Operator::Less,
+        Operator::Greater,
+    ],
+    &[Operator::BitOr, Operator::BitAnd],
+    &[Operator::Add, Operator::Sub],
+];
+
+impl Expression {
+    pub fn evaluate(&self, cpu: &Cpu) -> i64 {
+        match self {
+            Expression::Number(value) => *value,
+            Expression::Register(x) => cpu.registers()[*x] as i64,
+            Expression::Index => cpu.index() as i64,
+            Expression::ProgramCounter => cpu.pc() as i64,
+            Expression::StackPointer => cpu.stack().len() as i64,
+            Expression::DelayTimer => cpu.delay_timer() as i64,
+            Expression::SoundTimer => cpu.sound_timer() as i64,
+            Expression::Memory(address) => {
+                let size = cpu.memory().size() as i64;
+                let address = address.evaluate(cpu).rem_euclid(size) as usize;
+                cpu.memory().peek(address).unwrap_or_default() as i64
+            }
+            Expression::Not(expression) => (expression.evaluate(cpu) == 0) as i64,
+            Expression::Binary(left, operator, right) => {
+                operator.apply(left.evaluate(cpu), right.evaluate(cpu))
+            }
+        }
+    }
+
+    pub fn is_true(&self, cpu: &Cpu) -> bool {
+        self.evaluate(cpu) != 0
+    }
+}
+
+impl FromStr for Expression {
+    type Err = String;
+
+    fn from_str(source: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser {
+            source: source.as_bytes(),
+            position: 0,
+        };
+        let expression = parser.binary(0)?;
+        parser.skip_whitespace();
+        if parser.position < source.len() {
+            return Err(format!(
+                "unexpected `{}` at column {}",
+                &source[parser.position..],
+                parser.position + 1
+            ));
+        }
+        Ok(expression)
+    }
+}
+
+impl Display for Expression {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Expression::Number(value) => write!(f, "{value:#X}"),
+            Expression::Register(x) => write!(f, "V{x:X}"),
+            Expression::Index => write!(f, "I"),
+            Expression::ProgramCounter => write!(f, "PC"),
+            Expression::StackPointer => write!(f, "SP"),
+            Expression::DelayTimer => write!(f, "DT"),
+            Expression::SoundTimer => write!(f, "ST"),
+            Expression::Memory(address) => write!(f, "[{address}]"),
+            Expression::Not(expression) => write!(f, "!{expression}"),
+            Expression::Binary(left, operator, right) => {
+                write!(f, "({left} {} {right})", operator.symbol())
+            }
+        }
+    }
+}
+
+struct Parser<'a> {
+    source: &'a [u8],
+    position: usize,
+}
+
+impl Parser<'_> {
+    fn binary(&mut self, level: usize) -> Result<Expression, String> {
+        if level == PRECEDENCE.len() {
+            return self.unary();
+        }
+
+        let mut left = self.binary(level + 1)?;
+        while let Some(operator) = self.operator(PRECEDENCE[level]) {
+            let right = self.binary(level + 1)?;
+            left = Expression::Binary(Box::new(left), operator, Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn operator(&mut self, candidates: &[Operator]) -> Option<Operator> {
+        self.skip_whitespace();
+        let rest = &self.source[self.position..];
+        let operator = candidates.iter().copied().find(|operator| {
+            let symbol = operator.symbol().as_bytes();
+            // `&` and `|` must not swallow the first half of `&&` and `||`
+            rest.starts_with(symbol) && !(symbol.len() == 1 && rest.get(1) == Some(&symbol[0]))
+        })?;
+        self.position += operator.symbol().len();
+        Some(operator)
+    }
+
+    fn unary(&mut self) -> Result<Expression, String> {
+        self.skip_whitespace();
+        match self.source.get(self.position) {
+            Some(b'!') => {
+                self.position += 1;
+                Ok(Expression::Not(Box::new(self.unary()?)))
+            }
+            Some(b'(') => {
+                self.position += 1;
+                let expression = self.binary(0)?;
+                self.expect(b')')?;
+                Ok(expression)
+            }
+            Some(b'[') => {
+                self.position += 1;
+                let address = self.binary(0)?;
+                self.expect(b']')?;
+                Ok(Expression::Memory(Box::new(address)))
+            }
+            Some(c) if c.is_ascii_alphanumeric() => self.atom(),
+            Some(&c) => Err(format!(
+                "unexpected `{}` at column {}",
+                c as char,
+                self.position + 1
+            )),
+            None => Err("unexpected end of expression".to_string()),
+        }
+    }
+
+    fn atom(&mut self) -> Result<Expression, String> {
+        let start = self.position;
+        while self
+            .source
+            .get(self.position)
+            .is_some_and(|c| c.is_ascii_alphanumeric() || *c == b'_')
+        {
+            self.position += 1;
+        }
+        let word = std::str::from_utf8(&self.source[start..self.position])
+            .unwrap()
+            .to_ascii_uppercase();
+
+        let expression = match word.as_str() {
+            "I" => Expression::Index,
+            "PC" => Expression::ProgramCounter,
+            "SP" => Expression::StackPointer,
+            "DT" => Expression::DelayTimer,
+            "ST" => Expression::SoundTimer,
+            register if register.len() == 2 && register.starts_with('V') => {
+                let x = usize::from_str_radix(&register[1..], 16)
+                    .map_err(|_| format!("unknown register `{word}`"))?;
+                Expression::Register(x)
+            }
+            number => Expression::Number(parse_number(number)?),
+        };
+        Ok(expression)
+    }
+
+    fn expect(&mut self, expected: u8) -> Result<(), String> {
+        self.skip_whitespace();
+        if self.source.get(self.position) == Some(&expected) {
+            self.position += 1;
+            Ok(())
+        } else {
+            Err(format!(
+                "expected `{}` at column {}",
+                expected as char,
+                self.position + 1
+            ))
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self
+            .source
+            .get(self.position)
+            .is_some_and(|c| c.is_ascii_whitespace())
+        {
+            self.position += 1;
+        }
+    }
+}
+
+/// Decimal, `0x` hexadecimal or `0b` binary.
+pub fn parse_number(text: &str) -> Result<i64, String> {
+    let lowercase = text.to_ascii_lowercase();
+    let parsed = if let Some(hex) = lowercase.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16)
+    } else if let Some(binary) = lowercase.strip_prefix("0b") {
+        i64::from_str_radix(binary, 2)
+    } else {
+        lowercase.parse()
+    };
+    parsed.map_err(|_| format!("invalid number `{text}`"))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cpu::new;
+    use crate::expression::Expression;
+
+    #[test]
+    fn evaluates_conditions_against_the_cpu() {
+        let mut cpu = new();
+        // LD V3, 0x10 then LD I, 0x301
+        cpu.load_rom_bytes(&[0x63, 0x10, 0xA3, 0x01]).unwrap();
+        let condition: Expression = "V3 == 0x10 && I > 0x300".parse().unwrap();
+
+        cpu.tick().unwrap();
+        assert!(!condition.is_true(&cpu));
+        cpu.tick().unwrap();
+        assert!(condition.is_true(&cpu));
+    }
+
+    #[test]
+    fn respects_precedence_and_memory_reads() {
+        let expression: Expression = "v0 + 1 == 2 || !(PC & 0b1) && [0x200] != 0"
+            .parse()
+            .unwrap();
+
+        assert_eq!(
+            expression.to_string(),
+            "(((V0 + 0x1) == 0x2) || (!(PC & 0x1) && ([0x200] != 0x0)))"
+        );
+        assert!("V3 ==".parse::<Expression>().is_err());
+        assert!("VG == 1".parse::<Expression>().is_err());
+    }
+}
@@ -0,0 +1,24 @@
+use std::fmt::{Display, Formatter};
+
+/// Conditions that stop emulation instead of panicking the host.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Fault {
+    MemoryOutOfBounds { address: usize, size: usize },
+    StackOverflow { depth: usize },
+    StackUnderflow,
+}
+
+impl Display for Fault {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Fault::MemoryOutOfBounds { address, size } => write!(
+                f,
+                "memory access at {address:#06X} is outside of the {size} bytes of memory"
+            ),
+            Fault::StackOverflow { depth } => {
+                write!(f, "call stack overflow, more than {depth} nested calls")
+            }
+            Fault::StackUnderflow => write!(f, "return from subroutine with an empty call stack"),
+        }
+    }
+}
@@ -0,0 +1,251 @@
+use crate::palette::{Palette, Rgb};
+use crate::screen::{GRID_X_SIZE, GRID_Y_SIZE};
+use clap::ValueEnum;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum Preset {
+    #[default]
+    None,
+    Scanlines,
+    Grid,
+    Crt,
+}
+
+/// Software post-processing applied to the upscaled framebuffer.
+/// Every stage runs on the CPU so it works without any GPU support.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Filter {
+    /// How much every other output row is darkened, from 0.0 to 1.0.
+    pub scanlines: f32,
+    /// Width in pixels of the gap drawn on the right and bottom of each dot.
+    pub grid_gap: u32,
+    /// Strength of the glow added around lit pixels.
+    pub bloom: f32,
+    /// Barrel distortion factor, 0.0 keeps the picture flat.
+    pub curvature: f32,
+}
+
+impl From<Preset> for Filter {
+    fn from(preset: Preset) -> Self {
+        match preset {
+            Preset::None => Filter::default(),
+            Preset::Scanlines => Filter {
+                scanlines: 0.4,
+                ..Filter::default()
+            },
+            Preset::Grid => Filter {
+                grid_gap: 1,
+                ..Filter::default()
+            },
+            Preset::Crt => Filter {
+                scanlines: 0.3,
+                grid_gap: 1,
+                bloom: 0.5,
+                curvature: 0.06,
+            },
+        }
+    }
+}
+
+impl Filter {
+    pub fn is_enabled(&self) -> bool {
+        *self != Filter::default()
+    }
+
+    /// Renders `screen` at `scale` pixels per dot and returns a packed RGB24 buffer
+    /// of `GRID_X_SIZE * scale` by `GRID_Y_SIZE * scale` pixels.
+    pub fn apply(
+        &self,
+        screen: &[[bool; GRID_X_SIZE]; GRID_Y_SIZE],
+        palette: &Palette,
+        scale: u32,
+    ) -> Vec<u8> {
+        let scale = scale.max(1) as usize;
+        let width = GRID_X_SIZE * scale;
+        let height = GRID_Y_SIZE * scale;
+
+        let mut image = self.rasterize(screen, palette, scale);
+        if self.bloom > 0.0 {
+            self.add_bloom(&mut image, palette, width, height, scale / 2);
+        }
+        if self.scanlines > 0.0 {
+            let factor = 1.0 - self.scanlines.clamp(0.0, 1.0);
+            image
+                .chunks_mut(width * 3)
+                .skip(1)
+                .step_by(2)
+                .flatten()
+                .for_each(|channel| *channel *= factor);
+        }
+        if self.curvature > 0.0 {
+            image = self.curve(&image, width, height);
+        }
+
+        image
+            .iter()
+            .map(|channel| channel.round().clamp(0.0, 255.0) as u8)
+            .collect()
+    }
+
+    fn rasterize(
+        &self,
+        screen: &[[bool; GRID_X_SIZE]; GRID_Y_SIZE],
+        palette: &Palette,
+        scale: usize,
+    ) -> Vec<f32> {
+        let gap = (self.grid_gap as usize).min(scale - 1);
+        let width = GRID_X_SIZE * scale;
+        let mut image = Vec::with_capacity(width * GRID_Y_SIZE * scale * 3);
+
+        for py in 0..GRID_Y_SIZE * scale {
+            for px in 0..width {
+                let in_gap = px % scale >= scale - gap || py % scale >= scale - gap;
+                let lit = screen[py / scale][px / scale] && !in_gap;
+                let color = if in_gap {
+                    darken(palette.background, 0.5)
+                } else {
+                    palette.color(lit)
+                };
+                image.extend([color.0 as f32, color.1 as f32, color.2 as f32]);
+            }
+        }
+        image
+    }
+
+    fn add_bloom(
+        &self,
+        image: &mut [f32],
+        palette: &Palette,
+        width: usize,
+        height: usize,
+        radius: usize,
+    ) {
+        // only lit pixels glow, the background stays untouched
+        let foreground = palette.foreground;
+        let foreground = [
+            foreground.0 as f32,
+            foreground.1 as f32,
+            foreground.2 as f32,
+        ];
+        let source: Vec<f32> = image
+            .chunks(3)
+            .flat_map(|pixel| {
+                if pixel == foreground {
+                    foreground
+                } else {
+                    [0.0; 3]
+                }
+            })
+            .collect();
+
+        let radius = radius.max(1);
+        let horizontal = box_blur(&source, width, height, radius, true);
+        let blurred = box_blur(&horizontal, width, height, radius, false);
+
+        image
+            .iter_mut()
+            .zip(blurred)
+            .for_each(|(channel, glow)| *channel += glow * self.bloom);
+    }
+
+    fn curve(&self, image: &[f32], width: usize, height: usize) -> Vec<f32> {
+        let mut curved = vec![0.0; image.len()];
+
+        for py in 0..height {
+            for px in 0..width {
+                // map to [-1, 1] and push the coordinates outwards the further they are from the center
+                let nx = 2.0 * px as f32 / (width - 1) as f32 - 1.0;
+                let ny = 2.0 * py as f32 / (height - 1) as f32 - 1.0;
+                let distortion = 1.0 + self.curvature * (nx * nx + ny * ny);
+                let (sx, sy) = (nx * distortion, ny * distortion);
+                if !(-1.0..=1.0).contains(&sx) || !(-1.0..=1.0).contains(&sy) {
+                    continue;
+                }
+
+                let source_x = ((sx + 1.0) / 2.0 * (width - 1) as f32).round() as usize;
+                let source_y = ((sy + 1.0) / 2.0 * (height - 1) as f32).round() as usize;
+                let from = (source_y * width + source_x) * 3;
+                let to = (py * width + px) * 3;
+                curved[to..to + 3].copy_from_slice(&image[from..from + 3]);
+            }
+        }
+        curved
+    }
+}
+
+fn darken(color: Rgb, factor: f32) -> Rgb {
+    (
+        (color.0 as f32 * factor) as u8,
+        (color.1 as f32 * factor) as u8,
+        (color.2 as f32 * factor) as u8,
+    )
+}
+
+/// One pass of a separable box blur, either along rows or along columns.
+fn box_blur(
+    image: &[f32],
+    width: usize,
+    height: usize,
+    radius: usize,
+    horizontal: bool,
+) -> Vec<f32> {
+    let mut blurred = vec![0.0; image.len()];
+    let (lines, length) = if horizontal {
+        (height, width)
+    } else {
+        (width, height)
+    };
+    let index = |line: usize, position: usize| {
+        if horizontal {
+            (line * width + position) * 3
+        } else {
+            (position * width + line) * 3
+        }
+    };
+
+    for line in 0..lines {
+        for position in 0..length {
+            let start = position.saturating_sub(radius);
+            let end = (position + radius).min(length - 1);
+            let mut sum = [0.0; 3];
+            for sample in start..=end {
+                let i = index(line, sample);
+                sum[0] += image[i];
+                sum[1] += image[i + 1];
+                sum[2] += image[i + 2];
+            }
+            let count = (end - start + 1) as f32;
+            let i = index(line, position);
+            blurred[i] = sum[0] / count;
+            blurred[i + 1] = sum[1] / count;
+            blurred[i + 2] = sum[2] / count;
+        }
+    }
+    blurred
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::filters::{Filter, Preset};
+    use crate::palette::Palette;
+    use crate::screen::{GRID_X_SIZE, GRID_Y_SIZE};
+
+    #[test]
+    fn outputs_a_scaled_rgb_buffer() {
+        let screen = [[false; GRID_X_SIZE]; GRID_Y_SIZE];
+        let pixels = Filter::from(Preset::Crt).apply(&screen, &Palette::default(), 4);
+
+        assert_eq!(pixels.len(), GRID_X_SIZE * 4 * GRID_Y_SIZE * 4 * 3);
+    }
+
+    #[test]
+    fn scanlines_darken_odd_rows() {
+        let screen = [[true; GRID_X_SIZE]; GRID_Y_SIZE];
+        let palette = Palette::default();
+        let pixels = Filter::from(Preset::Scanlines).apply(&screen, &palette, 2);
+        let row = GRID_X_SIZE * 2 * 3;
+
+        assert_eq!(pixels[0], palette.foreground.0);
+        assert!(pixels[row] < palette.foreground.0);
+    }
+}
@@ -0,0 +1,142 @@
+use crate::palette::Palette;
+use crate::screen::{GRID_X_SIZE, GRID_Y_SIZE};
+use std::collections::HashSet;
+
+/// Host-level requests coming from the input device, as opposed to keypad presses.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Command {
+    Quit,
+    Screenshot,
+    ToggleRecording,
+    /// Resume after the debugger stopped.
+    Continue,
+    /// Run a single instruction while stopped.
+    Step,
+    /// Show or hide the profiler's memory heatmap.
+    ToggleHeatmap,
+    /// Stop or resume emulation, independently of the debugger.
+    TogglePause,
+    /// Run a single frame while paused.
+    AdvanceFrame,
+    /// Run as fast as the host allows.
+    ToggleTurbo,
+    /// Cycle through fractions of the normal speed.
+    SlowMotion,
+    /// Run more instructions per frame.
+    Faster,
+    /// Run fewer instructions per frame.
+    Slower,
+}
+
+/// RGB24 picture drawn over the screen, e.g. the profiler's memory heatmap.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Overlay {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+/// CHIP-8 keys a ROM uses as controls, bound to the arrow keys, space and enter
+/// on top of the usual hex keypad layout.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Keymap {
+    pub up: Option<u8>,
+    pub down: Option<u8>,
+    pub left: Option<u8>,
+    pub right: Option<u8>,
+    /// Bound to space.
+    pub a: Option<u8>,
+    /// Bound to enter.
+    pub b: Option<u8>,
+}
+
+pub trait Display {
+    fn render(&mut self, screen: &[[bool; GRID_X_SIZE]; GRID_Y_SIZE]) -> Result<(), String>;
+
+    fn palette(&self) -> Palette {
+        Palette::default()
+    }
+
+    /// Drawn over every following frame until cleared, ignored by frontends that can't show it.
+    fn set_overlay(&mut self, _overlay: Option<Overlay>) {}
+}
+
+pub trait Audio {
+    /// Called once per frame with whether the sound timer is running.
+    fn set_beeping(&mut self, beeping: bool);
+}
+
+pub trait Input {
+    /// Processes pending host events, called once per frame before the CPU runs.
+    fn poll(&mut self) -> Vec<Command>;
+
+    /// The CHIP-8 keys (0x0 to 0xF) currently held down.
+    fn pressed_keys(&mut self) -> HashSet<u8>;
+
+    /// Binds the ROM's controls to extra host keys, ignored by inputs without a keyboard.
+    fn set_keymap(&mut self, _keymap: Keymap) {}
+}
+
+#[cfg(test)]
+pub mod doubles {
+    use crate::frontend::{Audio, Command, Display, Input};
+    use crate::screen::{GRID_X_SIZE, GRID_Y_SIZE};
+    use std::collections::{HashSet, VecDeque};
+
+    /// Keeps every rendered frame.
+    #[derive(Default)]
+    pub struct RecordingDisplay {
+        pub frames: Vec<[[bool; GRID_X_SIZE]; GRID_Y_SIZE]>,
+    }
+
+    impl Display for RecordingDisplay {
+        fn render(&mut self, screen: &[[bool; GRID_X_SIZE]; GRID_Y_SIZE]) -> Result<(), String> {
+            self.frames.push(*screen);
+            Ok(())
+        }
+    }
+
+    /// Keeps the beeper state of every frame.
+    #[derive(Default)]
+    pub struct RecordingAudio {
+        pub beeps: Vec<bool>,
+    }
+
+    impl Audio for RecordingAudio {
+        fn set_beeping(&mut self, beeping: bool) {
+            self.beeps.push(beeping);
+        }
+    }
+
+    /// Replays one entry per frame: the keys held and the commands issued.
+    #[derive(Default)]
+    pub struct ScriptedInput {
+        pub frames: VecDeque<(HashSet<u8>, Vec<Command>)>,
+        current: HashSet<u8>,
+    }
+
+    impl ScriptedInput {
+        pub fn new(frames: Vec<(HashSet<u8>, Vec<Command>)>) -> ScriptedInput {
+            ScriptedInput {
+                frames: frames.into(),
+                current: HashSet::new(),
+            }
+        }
+    }
+
+    impl Input for ScriptedInput {
+        fn poll(&mut self) -> Vec<Command> {
+            match self.frames.pop_front() {
+                Some((keys, commands)) => {
+                    self.current = keys;
+                    commands
+                }
+                None => vec![Command::Quit],
+            }
+        }
+
+        fn pressed_keys(&mut self) -> HashSet<u8> {
+            self.current.clone()
+        }
+    }
+}
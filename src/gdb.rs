@@ -0,0 +1,640 @@
+use crate::cpu::Cpu;
+use crate::debugger::{Breakpoint, Debugger, Stop, WatchKind, Watchpoint};
+use crate::emulator::FRAME_RATE;
+use crate::fault::Fault;
+use crate::frontend::{Audio, Command, Display, Input};
+use crate::memory::AccessKind;
+use log::{info, warn};
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+/// V0-VF, I, PC, SP, DT and ST, in the order of the `g` packet.
+pub const REGISTER_COUNT: usize = 21;
+const INDEX: usize = 16;
+const PROGRAM_COUNTER: usize = 17;
+const STACK_POINTER: usize = 18;
+const DELAY_TIMER: usize = 19;
+const SOUND_TIMER: usize = 20;
+
+const INTERRUPT: u8 = 0x03;
+const SIGINT: u8 = 2;
+const SIGTRAP: u8 = 5;
+const SIGSEGV: u8 = 11;
+
+/// What the stub does once a packet has been answered.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Response {
+    Reply(String),
+    Step,
+    Continue,
+    /// Reply then close the connection, the debugger detached or killed the program.
+    Close(String),
+}
+
+/// Debugger state behind the GDB remote serial protocol, independent of the transport.
+pub struct Session<'a> {
+    cpu: &'a mut Cpu,
+    debugger: Debugger,
+    /// Instructions run between two 60 Hz timer decrements.
+    instructions_per_frame: u32,
+    /// Instructions run since the last 60 Hz timer decrement.
+    cycle: u32,
+    last_stop: String,
+}
+
+impl<'a> Session<'a> {
+    pub fn new(cpu: &'a mut Cpu, debugger: Debugger, instructions_per_frame: u32) -> Session<'a> {
+        Session {
+            cpu,
+            debugger,
+            instructions_per_frame,
+            cycle: 0,
+            last_stop: stop_reply(SIGTRAP, ""),
+        }
+    }
+
+    pub fn handle(&mut self, packet: &str) -> Response {
+        let mut chars = packet.chars();
+        let command = chars.next();
+        let arguments = chars.as_str();
+        let reply = match command {
+            Some('?') => Some(self.last_stop.clone()),
+            Some('g') => Some(self.read_registers()),
+            Some('G') => self.write_registers(arguments),
+            Some('p') => self.read_register(arguments),
+            Some('P') => self.write_register(arguments),
+            Some('m') => self.read_memory(arguments),
+            Some('M') => self.write_memory(arguments),
+            Some('Z') => self.set_point(arguments, true),
+            Some('z') => self.set_point(arguments, false),
+            Some('s' | 'c') => {
+                if let Ok(address) = u16::from_str_radix(arguments, 16) {
+                    self.cpu.set_pc(address);
+                }
+                return if command == Some('s') {
+                    Response::Step
+                } else {
+                    Response::Continue
+                };
+            }
+            Some('D') => return Response::Close("OK".to_string()),
+            Some('k') => return Response::Close(String::new()),
+            Some('H') => Some("OK".to_string()),
+            Some('q' | 'Q') => Some(self.query(packet)),
+            _ => Some(String::new()),
+        };
+        Response::Reply(reply.unwrap_or_else(|| "E01".to_string()))
+    }
+
+    /// Runs one instruction, decrementing the timers every `instructions_per_frame`
+    /// instructions so that programs see them at the same pace as in the emulator loop.
+    /// A `DXYN` waiting for the vertical blank ends the frame early instead.
+    pub fn execute(&mut self) -> Result<Option<Stop>, Fault> {
+        if self.cpu.is_waiting_for_vblank() {
+            self.end_frame();
+            return Ok(None);
+        }
+        let stop = self.debugger.step(self.cpu)?;
+        self.cycle += 1;
+        if self.cycle >= self.instructions_per_frame {
+            self.end_frame();
+        }
+        Ok(stop)
+    }
+
+    /// Runs the next instruction for a single step, past any display wait.
+    pub fn step(&mut self) -> Result<Option<Stop>, Fault> {
+        if self.cpu.is_waiting_for_vblank() {
+            self.end_frame();
+        }
+        self.execute()
+    }
+
+    fn end_frame(&mut self) {
+        self.cycle = 0;
+        self.cpu.decrement_timers();
+    }
+
+    pub fn is_at_frame_boundary(&self) -> bool {
+        self.cycle == 0
+    }
+
+    /// Builds and remembers the reply to an execution that just ended.
+    pub fn stopped(&mut self, result: Result<Option<Stop>, Fault>) -> String {
+        self.last_stop = match result {
+            Ok(Some(Stop::Breakpoint(_))) => stop_reply(SIGTRAP, "swbreak:;"),
+            Ok(Some(Stop::Watchpoint(access))) => {
+                let kind = match access.kind {
+                    AccessKind::Write => "watch",
+                    _ => "rwatch",
+                };
+                stop_reply(SIGTRAP, &format!("{kind}:{:x};", access.address))
+            }
+            Ok(_) => stop_reply(SIGTRAP, ""),
+            Err(fault) => {
+                warn!("{fault} (pc={:#05X})", self.cpu.pc());
+                stop_reply(SIGSEGV, "")
+            }
+        };
+        self.last_stop.clone()
+    }
+
+    fn interrupted(&mut self) -> String {
+        self.last_stop = stop_reply(SIGINT, "");
+        self.last_stop.clone()
+    }
+
+    fn registers(&self) -> [u16; REGISTER_COUNT] {
+        let mut registers = [0; REGISTER_COUNT];
+        for (register, &value) in registers.iter_mut().zip(self.cpu.registers()) {
+            *register = value as u16;
+        }
+        registers[INDEX] = self.cpu.index();
+        registers[PROGRAM_COUNTER] = self.cpu.pc();
+        registers[STACK_POINTER] = self.cpu.stack().len() as u16;
+        registers[DELAY_TIMER] = self.cpu.delay_timer() as u16;
+        registers[SOUND_TIMER] = self.cpu.sound_timer() as u16;
+        registers
+    }
+
+    /// The stack pointer is read-only, the stack itself is not addressable.
+    fn set_register(&mut self, register: usize, value: u16) {
+        match register {
+            0..=15 => self.cpu.registers_mut()[register] = value as u8,
+            INDEX => self.cpu.set_index(value),
+            PROGRAM_COUNTER => self.cpu.set_pc(value),
+            DELAY_TIMER => self.cpu.set_delay_timer(value as u8),
+            SOUND_TIMER => self.cpu.set_sound_timer(value as u8),
+            _ => {}
+        }
+    }
+
+    fn read_registers(&self) -> String {
+        self.registers()
+            .iter()
+            .enumerate()
+            .map(|(register, &value)| encode_register(register, value))
+            .collect()
+    }
+
+    fn write_registers(&mut self, hex: &str) -> Option<String> {
+        let bytes = decode_hex(hex)?;
+        let mut offset = 0;
+        for register in 0..REGISTER_COUNT {
+            let size = register_size(register);
+            let value = decode_register(bytes.get(offset..offset + size)?);
+            self.set_register(register, value);
+            offset += size;
+        }
+        Some("OK".to_string())
+    }
+
+    fn read_register(&self, arguments: &str) -> Option<String> {
+        let register = usize::from_str_radix(arguments, 16).ok()?;
+        let value = *self.registers().get(register)?;
+        Some(encode_register(register, value))
+    }
+
+    fn write_register(&mut self, arguments: &str) -> Option<String> {
+        let (register, hex) = arguments.split_once('=')?;
+        let register = usize::from_str_radix(register, 16).ok()?;
+        let bytes = decode_hex(hex)?;
+        if register >= REGISTER_COUNT || bytes.len() != register_size(register) {
+            return None;
+        }
+        self.set_register(register, decode_register(&bytes));
+        Some("OK".to_string())
+    }
+
+    fn read_memory(&self, arguments: &str) -> Option<String> {
+        let (address, length) = parse_range(arguments)?;
+        let bytes = self
+            .cpu
+            .memory()
+            .as_slice()
+            .get(address..address.checked_add(length)?)?;
+        Some(encode_hex(bytes))
+    }
+
+    fn write_memory(&mut self, arguments: &str) -> Option<String> {
+        let (range, hex) = arguments.split_once(':')?;
+        let (address, length) = parse_range(range)?;
+        let bytes = decode_hex(hex).filter(|bytes| bytes.len() == length)?;
+        address
+            .checked_add(length)
+            .filter(|&end| end <= self.cpu.memory().size())?;
+        self.cpu.memory_mut().load(address, &bytes).ok()?;
+        Some("OK".to_string())
+    }
+
+    /// `Z0`/`Z1` are breakpoints, `Z2`, `Z3` and `Z4` write, read and access watchpoints.
+    fn set_point(&mut self, arguments: &str, insert: bool) -> Option<String> {
+        let mut fields = arguments.split(',');
+        let kind = fields.next()?;
+        let address = usize::from_str_radix(fields.next()?, 16).ok()?;
+        let length = usize::from_str_radix(fields.next()?, 16).ok()?.max(1);
+
+        let watch_kind = match kind {
+            "0" | "1" => {
+                let address = u16::try_from(address).ok()?;
+                if insert {
+                    self.debugger.add_breakpoint(Breakpoint {
+                        address,
+                        condition: None,
+                    });
+                } else {
+                    self.debugger.remove_breakpoint(address);
+                }
+                return Some("OK".to_string());
+            }
+            "2" => WatchKind::Write,
+            "3" => WatchKind::Read,
+            "4" => WatchKind::Access,
+            _ => return Some(String::new()),
+        };
+        if address >= self.cpu.memory().size() {
+            return None;
+        }
+        let watchpoint = Watchpoint {
+            addresses: address..=address.checked_add(length - 1)?,
+            kind: watch_kind,
+        };
+        if insert {
+            self.debugger.add_watchpoint(watchpoint);
+        } else {
+            self.debugger.remove_watchpoint(&watchpoint);
+        }
+        Some("OK".to_string())
+    }
+
+    fn query(&self, packet: &str) -> String {
+        if packet.starts_with("qSupported") {
+            "PacketSize=4000;qXfer:features:read+;QStartNoAckMode+;swbreak+".to_string()
+        } else if let Some(range) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+            let Some((offset, length)) = parse_range(range) else {
+                return "E01".to_string();
+            };
+            let xml = target_xml();
+            let chunk = xml.get(offset.min(xml.len())..).unwrap_or_default();
+            match chunk.len() > length {
+                true => format!("m{}", &chunk[..length]),
+                false => format!("l{chunk}"),
+            }
+        } else if packet == "qAttached" {
+            "1".to_string()
+        } else if packet == "QStartNoAckMode" {
+            "OK".to_string()
+        } else {
+            String::new()
+        }
+    }
+}
+
+/// Waits for a debugger on `address`, e.g. `127.0.0.1:1234`, and serves it until it
+/// detaches. The program starts stopped, the frontend shows it and reports the keys.
+pub fn serve(
+    address: &str,
+    cpu: &mut Cpu,
+    display: &mut dyn Display,
+    audio: &mut dyn Audio,
+    input: &mut dyn Input,
+    debugger: Debugger,
+    instructions_per_frame: u32,
+) -> Result<(), String> {
+    let listener =
+        TcpListener::bind(address).map_err(|e| format!("can't listen on {address}: {e}"))?;
+    info!("waiting for a debugger on {address}, e.g. `target remote {address}`");
+    let (stream, peer) = listener.accept().map_err(|e| e.to_string())?;
+    info!("debugger attached from {peer}");
+
+    let frame_duration = Duration::from_secs_f64(1.0 / FRAME_RATE as f64);
+    stream
+        .set_read_timeout(Some(frame_duration))
+        .map_err(|e| e.to_string())?;
+    let mut connection = Connection {
+        stream,
+        acknowledge: true,
+    };
+    let mut session = Session::new(cpu, debugger, instructions_per_frame);
+    display.render(&session.cpu.screen)?;
+
+    loop {
+        let Some(packet) = connection.read_packet(&mut || idle(input))? else {
+            return Ok(());
+        };
+        let reply = match session.handle(&packet) {
+            Response::Reply(reply) => reply,
+            Response::Close(reply) => {
+                connection.send(&reply)?;
+                info!("debugger detached");
+                return Ok(());
+            }
+            Response::Step => {
+                let result = session.step();
+                session.stopped(result)
+            }
+            Response::Continue => loop {
+                let started = Instant::now();
+                let result = session.execute();
+                if !matches!(result, Ok(None)) {
+                    break session.stopped(result);
+                }
+                if !session.is_at_frame_boundary() {
+                    continue;
+                }
+
+                audio.set_beeping(session.cpu.is_sound_playing());
+                if session.cpu.should_render {
+                    display.render(&session.cpu.screen)?;
+                    session.cpu.should_render = false;
+                }
+                if input.poll().contains(&Command::Quit) {
+                    return Ok(());
+                }
+                session.cpu.set_keys_pressed(input.pressed_keys());
+                if connection.is_interrupted()? {
+                    break session.interrupted();
+                }
+                if let Some(remaining) = frame_duration.checked_sub(started.elapsed()) {
+                    sleep(remaining);
+                }
+            },
+        };
+
+        audio.set_beeping(false);
+        display.render(&session.cpu.screen)?;
+        connection.send(&reply)?;
+        if packet == "QStartNoAckMode" {
+            connection.acknowledge = false;
+        }
+    }
+}
+
+/// Keeps the frontend responsive while waiting for the debugger, `false` when asked to quit.
+fn idle(input: &mut dyn Input) -> bool {
+    !input.poll().contains(&Command::Quit)
+}
+
+struct Connection {
+    stream: TcpStream,
+    /// Cleared once the debugger switched to no-acknowledgment mode.
+    acknowledge: bool,
+}
+
+impl Connection {
+    /// Returns the next `$packet#checksum` payload, or `None` when the debugger went
+    /// away or `idle` asked to stop.
+    fn read_packet(&mut self, idle: &mut dyn FnMut() -> bool) -> Result<Option<String>, String> {
+        loop {
+            let mut frame = vec![];
+            let mut byte = [0];
+            loop {
+                match self.stream.read(&mut byte) {
+                    Ok(0) => return Ok(None),
+                    Ok(_) => {
+                        frame.push(byte[0]);
+                        if frame.len() >= 3 && frame[frame.len() - 3] == b'#' {
+                            break;
+                        }
+                    }
+                    Err(e) if is_timeout(&e) => {
+                        if !idle() {
+                            return Ok(None);
+                        }
+                    }
+                    Err(e) => return Err(e.to_string()),
+                }
+            }
+
+            match parse_frame(&frame) {
+                Some(packet) => {
+                    if self.acknowledge {
+                        self.write(b"+")?;
+                    }
+                    return Ok(Some(packet));
+                }
+                None if self.acknowledge => self.write(b"-")?,
+                None => {}
+            }
+        }
+    }
+
+    fn send(&mut self, payload: &str) -> Result<(), String> {
+        self.write(encode_packet(payload).as_bytes())
+    }
+
+    /// Checks without blocking whether the debugger sent a break (Ctrl-C).
+    fn is_interrupted(&mut self) -> Result<bool, String> {
+        self.stream
+            .set_nonblocking(true)
+            .map_err(|e| e.to_string())?;
+        let mut byte = [0];
+        let result = self.stream.read(&mut byte);
+        self.stream
+            .set_nonblocking(false)
+            .map_err(|e| e.to_string())?;
+        match result {
+            Ok(1) => Ok(byte[0] == INTERRUPT),
+            Ok(_) => Err("debugger disconnected".to_string()),
+            Err(e) if is_timeout(&e) => Ok(false),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<(), String> {
+        self.stream.write_all(bytes).map_err(|e| e.to_string())
+    }
+}
+
+fn is_timeout(error: &io::Error) -> bool {
+    matches!(error.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut)
+}
+
+/// Extracts the payload of `...$payload#xx`, `None` if the checksum doesn't match.
+/// Bytes before the `$`, such as acknowledgments and breaks, are skipped.
+fn parse_frame(frame: &[u8]) -> Option<String> {
+    let start = frame.iter().position(|&byte| byte == b'$')? + 1;
+    let end = frame.len().checked_sub(3).filter(|&end| end >= start)?;
+    let checksum = u8::from_str_radix(std::str::from_utf8(&frame[end + 1..]).ok()?, 16).ok()?;
+    let payload = &frame[start..end];
+    (checksum_of(payload) == checksum)
+        .then(|| String::from_utf8_lossy(&unescape(payload)).into_owned())
+}
+
+/// `}` escapes the next byte, XORed with 0x20.
+fn unescape(payload: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(payload.len());
+    let mut escaped = false;
+    for &byte in payload {
+        match (escaped, byte) {
+            (false, b'}') => escaped = true,
+            (true, _) => {
+                bytes.push(byte ^ 0x20);
+                escaped = false;
+            }
+            (false, _) => bytes.push(byte),
+        }
+    }
+    bytes
+}
+
+fn checksum_of(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0, |sum, &byte| sum.wrapping_add(byte))
+}
+
+pub fn encode_packet(payload: &str) -> String {
+    format!("${payload}#{:02x}", checksum_of(payload.as_bytes()))
+}
+
+fn stop_reply(signal: u8, info: &str) -> String {
+    match info.is_empty() {
+        true => format!("S{signal:02x}"),
+        false => format!("T{signal:02x}{info}"),
+    }
+}
+
+/// I and PC are 16 bits wide, sent little-endian like GDB assumes for unknown targets.
+fn register_size(register: usize) -> usize {
+    match register {
+        INDEX | PROGRAM_COUNTER => 2,
+        _ => 1,
+    }
+}
+
+fn encode_register(register: usize, value: u16) -> String {
+    encode_hex(&value.to_le_bytes()[..register_size(register)])
+}
+
+fn decode_register(bytes: &[u8]) -> u16 {
+    bytes
+        .iter()
+        .rev()
+        .fold(0, |value, &byte| value << 8 | byte as u16)
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// `address,length` in hexadecimal.
+fn parse_range(arguments: &str) -> Option<(usize, usize)> {
+    let (address, length) = arguments.split_once(',')?;
+    Some((
+        usize::from_str_radix(address, 16).ok()?,
+        usize::from_str_radix(length, 16).ok()?,
+    ))
+}
+
+/// Describes the registers so that GDB needs no built-in CHIP-8 support.
+fn target_xml() -> String {
+    let mut registers: Vec<String> = (0..16)
+        .map(|x| format!(r#"<reg name="v{x:x}" bitsize="8" type="uint8"/>"#))
+        .collect();
+    registers.extend([
+        r#"<reg name="i" bitsize="16" type="data_ptr"/>"#.to_string(),
+        r#"<reg name="pc" bitsize="16" type="code_ptr"/>"#.to_string(),
+        r#"<reg name="sp" bitsize="8" type="uint8"/>"#.to_string(),
+        r#"<reg name="dt" bitsize="8" type="uint8"/>"#.to_string(),
+        r#"<reg name="st" bitsize="8" type="uint8"/>"#.to_string(),
+    ]);
+    format!(
+        r#"<?xml version="1.0"?><!DOCTYPE target SYSTEM "gdb-target.dtd"><target version="1.0"><feature name="org.chip8.core">{}</feature></target>"#,
+        registers.concat()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cpu::new;
+    use crate::debugger::Debugger;
+    use crate::emulator::INSTRUCTIONS_PER_FRAME;
+    use crate::gdb::{encode_packet, parse_frame, Response, Session};
+    use crate::platform::Platform;
+
+    #[test]
+    fn frames_packets_with_checksums() {
+        assert_eq!(encode_packet("OK"), "$OK#9a");
+        assert_eq!(parse_frame(b"+$g#67"), Some("g".to_string()));
+        assert_eq!(parse_frame(b"$g#00"), None);
+        let escaped = encode_packet("M300,1:}]");
+        assert_eq!(
+            parse_frame(escaped.as_bytes()),
+            Some("M300,1:}".to_string())
+        );
+    }
+
+    #[test]
+    fn maps_registers_memory_and_breakpoints_onto_the_cpu() {
+        let mut cpu = new();
+        // LD V1, 0x42 then JP 0x200
+        cpu.load_rom_bytes(&[0x61, 0x42, 0x12, 0x00]).unwrap();
+        let mut session = Session::new(&mut cpu, Debugger::default(), INSTRUCTIONS_PER_FRAME);
+        let reply = |text: &str| Response::Reply(text.to_string());
+
+        assert_eq!(session.handle("m200,2"), reply("6142"));
+        assert_eq!(session.handle("Z0,202,2"), reply("OK"));
+        assert_eq!(session.handle("c"), Response::Continue);
+        let result = session.execute();
+        assert_eq!(session.stopped(result), "T05swbreak:;");
+        assert_eq!(session.handle("p1"), reply("42"));
+        assert_eq!(session.handle("p11"), reply("0202"));
+
+        assert_eq!(session.handle("P10=0003"), reply("OK"));
+        assert_eq!(session.handle("M300,2:abcd"), reply("OK"));
+        assert_eq!(session.handle("m300,2"), reply("abcd"));
+        assert_eq!(session.handle("m1000,1"), reply("E01"));
+        assert_eq!(session.handle("\u{FFFD}m200,2"), reply(""));
+        assert_eq!(session.handle(""), reply(""));
+        assert_eq!(session.handle("mffffffffffffffff,1"), reply("E01"));
+        assert_eq!(session.handle("Mffffffffffffffff,2:abcd"), reply("E01"));
+        assert_eq!(session.handle("Z2,ffffffffffffffff,2"), reply("E01"));
+        assert_eq!(session.handle("Z2,1000,1"), reply("E01"));
+        assert_eq!(session.handle("D"), Response::Close("OK".to_string()));
+        assert_eq!(cpu.index(), 0x300);
+    }
+
+    #[test]
+    fn single_steps_past_a_display_wait() {
+        let mut cpu = new();
+        cpu.set_quirks(Platform::CosmacVip.quirks());
+        // DRW V0, V0, 1; ADD V1, 1; JP 0x200
+        cpu.load_rom_bytes(&[0xD0, 0x01, 0x71, 0x01, 0x12, 0x00])
+            .unwrap();
+        let mut session = Session::new(&mut cpu, Debugger::default(), INSTRUCTIONS_PER_FRAME);
+
+        // registers are little-endian
+        for pc in ["0202", "0402", "0002"] {
+            assert_eq!(session.handle("s"), Response::Step);
+            let result = session.step();
+            assert_eq!(session.stopped(result), "S05");
+            assert_eq!(session.handle("p11"), Response::Reply(pc.to_string()));
+        }
+        assert_eq!(session.handle("p1"), Response::Reply("01".to_string()));
+    }
+
+    #[test]
+    fn decrements_the_timers_at_the_configured_rate() {
+        let mut cpu = new();
+        // LD V0, 10; LD DT, V0; JP 0x204
+        cpu.load_rom_bytes(&[0x60, 0x0A, 0xF0, 0x15, 0x12, 0x04])
+            .unwrap();
+        let mut session = Session::new(&mut cpu, Debugger::default(), 4);
+
+        for _ in 0..8 {
+            session.step().unwrap();
+        }
+        assert_eq!(cpu.delay_timer(), 8);
+    }
+}
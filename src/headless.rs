@@ -0,0 +1,26 @@
+use crate::frontend::{Audio, Command, Display, Input};
+use crate::screen::{GRID_X_SIZE, GRID_Y_SIZE};
+use std::collections::HashSet;
+
+/// Frontend without any window, sound or keyboard, used to run ROMs in batch.
+pub struct Headless;
+
+impl Display for Headless {
+    fn render(&mut self, _screen: &[[bool; GRID_X_SIZE]; GRID_Y_SIZE]) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+impl Audio for Headless {
+    fn set_beeping(&mut self, _beeping: bool) {}
+}
+
+impl Input for Headless {
+    fn poll(&mut self) -> Vec<Command> {
+        vec![]
+    }
+
+    fn pressed_keys(&mut self) -> HashSet<u8> {
+        HashSet::new()
+    }
+}
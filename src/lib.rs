@@ -0,0 +1,35 @@
+pub mod analysis;
+pub mod blocks;
+pub mod container;
+pub mod cpu;
+pub mod database;
+pub mod debugger;
+pub mod decode_cache;
+pub mod decoder;
+pub mod emulator;
+pub mod expression;
+pub mod fault;
+pub mod filters;
+pub mod frontend;
+pub mod gdb;
+pub mod headless;
+pub mod lockstep;
+pub mod memory;
+pub mod mnemonic;
+pub mod movie;
+pub mod octo;
+pub mod opcode;
+pub mod palette;
+pub mod platform;
+pub mod profiler;
+pub mod recorder;
+#[cfg(feature = "sdl")]
+pub mod renderer;
+pub mod screen;
+pub mod screenshot;
+#[cfg(feature = "sdl")]
+pub mod sdl;
+pub mod sprites;
+pub mod stack;
+pub mod terminal;
+pub mod trace;
@@ -0,0 +1,275 @@
+use crate::cpu::Cpu;
+use crate::fault::Fault;
+use crate::mnemonic::{disassemble, Syntax};
+use crate::movie::Movie;
+use crate::screen::GRID_X_SIZE;
+use std::fmt;
+
+/// Differing memory bytes listed before the rest are summarized.
+const MAX_MEMORY_DIFFERENCES: usize = 8;
+/// Instructions disassembled on each side of the one that diverged.
+const DISASSEMBLY_CONTEXT: u16 = 4;
+
+/// The first instruction after which two machines no longer agree.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Divergence {
+    pub frame: u32,
+    /// Number of instructions both machines ran before this one.
+    pub cycle: u64,
+    pub pc: u16,
+    /// One line per differing register, memory byte range or screen.
+    pub differences: Vec<String>,
+    /// Instructions around `pc` in the first machine's memory, `pc` marked with `>`.
+    pub disassembly: Vec<String>,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "diverged at cycle {} (frame {}) after the instruction at {:#06X}",
+            self.cycle, self.frame, self.pc
+        )?;
+        for difference in &self.differences {
+            writeln!(f, "  {difference}")?;
+        }
+        writeln!(f)?;
+        for line in &self.disassembly {
+            writeln!(f, "{line}")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    /// Both machines ran every frame in the same state.
+    Identical {
+        cycles: u64,
+    },
+    /// Both machines faulted the same way, in the same state.
+    Faulted {
+        cycle: u64,
+        fault: Fault,
+    },
+    Diverged(Divergence),
+}
+
+/// Runs `frames` frames of `instructions_per_frame` instructions of `movie` on
+/// both machines one instruction at a time, comparing them after every
+/// instruction. Both should be loaded with the same ROM and seeded the same way.
+pub fn compare(
+    left: &mut Cpu,
+    right: &mut Cpu,
+    movie: &Movie,
+    frames: u32,
+    instructions_per_frame: u32,
+    syntax: Syntax,
+) -> Outcome {
+    let mut cycle = 0;
+    for frame in 0..frames {
+        let keys = movie.keys_at(frame);
+        left.set_keys_pressed(keys.clone());
+        right.set_keys_pressed(keys);
+
+        for _ in 0..instructions_per_frame {
+            let pc = left.pc();
+            let results = [left.run(1), right.run(1)];
+
+            let mut differences = differences(left, right);
+            if results[0] != results[1] {
+                let describe = |result: &Result<(), Fault>| match result {
+                    Ok(()) => "no fault".to_string(),
+                    Err(fault) => fault.to_string(),
+                };
+                differences.insert(
+                    0,
+                    format!("{} vs {}", describe(&results[0]), describe(&results[1])),
+                );
+            }
+            if !differences.is_empty() {
+                return Outcome::Diverged(Divergence {
+                    frame,
+                    cycle,
+                    pc,
+                    differences,
+                    disassembly: disassemble_around(left.memory().as_slice(), pc, syntax),
+                });
+            }
+            if let Err(fault) = results[0] {
+                return Outcome::Faulted { cycle, fault };
+            }
+            cycle += 1;
+        }
+        left.decrement_timers();
+        right.decrement_timers();
+    }
+    Outcome::Identical { cycles: cycle }
+}
+
+/// Describes every part of the machine state that differs between `left` and `right`.
+pub fn differences(left: &Cpu, right: &Cpu) -> Vec<String> {
+    let mut differences = vec![];
+    let mut compare = |name: &str, left: u16, right: u16| {
+        if left != right {
+            differences.push(format!("{name}: {left:#04X} vs {right:#04X}"));
+        }
+    };
+    compare("PC", left.pc(), right.pc());
+    for (register, (l, r)) in left.registers().iter().zip(right.registers()).enumerate() {
+        compare(&format!("V{register:X}"), *l as u16, *r as u16);
+    }
+    compare("I", left.index(), right.index());
+    compare("SP", left.stack().len() as u16, right.stack().len() as u16);
+    compare("DT", left.delay_timer() as u16, right.delay_timer() as u16);
+    compare("ST", left.sound_timer() as u16, right.sound_timer() as u16);
+    compare(
+        "vblank wait",
+        left.is_waiting_for_vblank() as u16,
+        right.is_waiting_for_vblank() as u16,
+    );
+
+    let (left_memory, right_memory) = (left.memory().as_slice(), right.memory().as_slice());
+    if left_memory.len() != right_memory.len() {
+        differences.push(format!(
+            "memory size: {} vs {} bytes",
+            left_memory.len(),
+            right_memory.len()
+        ));
+    }
+    let mut differing = (0..left_memory.len().min(right_memory.len()))
+        .filter(|&address| left_memory[address] != right_memory[address]);
+    for address in differing.by_ref().take(MAX_MEMORY_DIFFERENCES) {
+        differences.push(format!(
+            "memory {address:#06X}: {:#04X} vs {:#04X}",
+            left_memory[address], right_memory[address]
+        ));
+    }
+    let more = differing.count();
+    if more > 0 {
+        differences.push(format!("and {more} more memory bytes"));
+    }
+
+    let pixels: Vec<(usize, usize)> = (0..left.screen.len() * GRID_X_SIZE)
+        .map(|pixel| (pixel % GRID_X_SIZE, pixel / GRID_X_SIZE))
+        .filter(|&(x, y)| left.screen[y][x] != right.screen[y][x])
+        .collect();
+    if let Some((x, y)) = pixels.first() {
+        differences.push(format!(
+            "screen: {} pixels differ, the first at ({x}, {y})",
+            pixels.len()
+        ));
+    }
+    differences
+}
+
+/// The instructions before and after `pc`, assuming they are aligned with it.
+pub fn disassemble_around(memory: &[u8], pc: u16, syntax: Syntax) -> Vec<String> {
+    let first = pc.saturating_sub(2 * DISASSEMBLY_CONTEXT);
+    let marked = format!("{pc:#06X}");
+    disassemble(memory, first, 2 * DISASSEMBLY_CONTEXT as usize + 1, syntax)
+        .into_iter()
+        .map(|line| {
+            let marker = if line.starts_with(&marked) { '>' } else { ' ' };
+            format!("{marker} {line}")
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cpu::{from_parts, new, Cpu, Engine};
+    use crate::emulator::INSTRUCTIONS_PER_FRAME;
+    use crate::lockstep::{compare, differences, Outcome};
+    use crate::memory::{Memory, MemorySize, OutOfBounds};
+    use crate::mnemonic::Syntax;
+    use crate::movie::Movie;
+    use crate::platform::Platform;
+    use crate::stack::Stack;
+
+    fn boot(rom: &str) -> Cpu {
+        let mut cpu = new();
+        cpu.seed_random(3);
+        cpu.load_fonts("./roms/fonts.ch8").unwrap();
+        cpu.load_rom(rom).unwrap();
+        cpu
+    }
+
+    #[test]
+    fn engines_run_in_lockstep() {
+        let mut interpreter = boot("./roms/invaders.ch8");
+        interpreter.set_engine(Engine::Interpreter);
+        let mut blocks = boot("./roms/invaders.ch8");
+        blocks.set_engine(Engine::Blocks);
+        let movie: Movie = "60 5\n70 -\n100 4\n200 6".parse().unwrap();
+
+        assert_eq!(
+            compare(
+                &mut interpreter,
+                &mut blocks,
+                &movie,
+                300,
+                INSTRUCTIONS_PER_FRAME,
+                Syntax::Cowgod
+            ),
+            Outcome::Identical { cycles: 15000 }
+        );
+    }
+
+    #[test]
+    fn reports_the_first_quirk_difference() {
+        let [mut modern, mut vip] = [new(), new()];
+        vip.set_quirks(Platform::CosmacVip.quirks());
+        // LD V1, 0x0F then LD V2, 0x01 then OR V1, V2, which resets VF on the VIP
+        for cpu in [&mut modern, &mut vip] {
+            cpu.registers_mut()[0xF] = 1;
+            cpu.load_rom_bytes(&[0x61, 0x0F, 0x62, 0x01, 0x81, 0x21])
+                .unwrap();
+        }
+
+        let Outcome::Diverged(divergence) = compare(
+            &mut modern,
+            &mut vip,
+            &Movie::default(),
+            1,
+            INSTRUCTIONS_PER_FRAME,
+            Syntax::Cowgod,
+        ) else {
+            panic!("the quirk should make a difference");
+        };
+        assert_eq!((divergence.cycle, divergence.pc), (2, 0x204));
+        assert_eq!(divergence.differences, ["VF: 0x01 vs 0x00"]);
+        assert!(divergence.disassembly[4].starts_with("> 0x0204  8121"));
+        assert!(divergence.disassembly[4].ends_with("OR V1, V2"));
+    }
+
+    #[test]
+    fn reports_memory_sizes_and_runs_at_the_given_rate() {
+        let standard = new();
+        let extended = from_parts(
+            Memory::new(MemorySize::Extended, OutOfBounds::Fault),
+            Stack::default(),
+        );
+        assert_eq!(
+            differences(&standard, &extended),
+            ["memory size: 4096 vs 65536 bytes"]
+        );
+
+        let [mut left, mut right] = [new(), new()];
+        for cpu in [&mut left, &mut right] {
+            // JP 0x200
+            cpu.load_rom_bytes(&[0x12, 0x00]).unwrap();
+        }
+        assert_eq!(
+            compare(
+                &mut left,
+                &mut right,
+                &Movie::default(),
+                2,
+                7,
+                Syntax::Cowgod
+            ),
+            Outcome::Identical { cycles: 14 }
+        );
+    }
+}
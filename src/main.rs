@@ -1,143 +1,356 @@
-mod cpu;
-mod decoder;
-mod opcode;
-mod renderer;
+mod capture;
+mod cli;
 
-use crate::cpu::Cpu;
+use crate::capture::Capture;
+use crate::cli::{Args, Frontend};
+use clap::Parser;
+use log::{error, info};
+use my_chip_8::container::{self, Cartridge};
+use my_chip_8::cpu::Cpu;
+use my_chip_8::database::{guess, Database, Profile};
+use my_chip_8::debugger::{Debugger, WatchKind, Watchpoint};
+use my_chip_8::emulator::RunConfig;
+use my_chip_8::frontend::{Audio, Display, Input};
+use my_chip_8::headless::Headless;
+use my_chip_8::memory::Memory;
+use my_chip_8::profiler::Profiler;
+use my_chip_8::screenshot::save_rgb_png;
+use my_chip_8::stack::{Stack, StackStorage, VIP_STACK_ADDRESS};
+use my_chip_8::terminal::Terminal;
+use my_chip_8::trace::Tracer;
+#[cfg(feature = "sdl")]
 use rfd::FileDialog;
-use sdl2::event::Event;
-use sdl2::keyboard::{Keycode, Scancode};
 use simplelog::{
-    ColorChoice, CombinedLogger, Config, ConfigBuilder, LevelFilter, TermLogger, TerminalMode,
-    WriteLogger,
+    ColorChoice, CombinedLogger, Config, ConfigBuilder, LevelFilter, SharedLogger, TermLogger,
+    TerminalMode, WriteLogger,
 };
-use std::collections::HashSet;
-use std::fs::File;
-use std::thread::sleep;
-use std::time::{Duration, SystemTime};
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
 
 fn main() {
-    CombinedLogger::init(vec![
-        TermLogger::new(
+    let args = Args::parse();
+
+    let mut loggers: Vec<Box<dyn SharedLogger>> = vec![WriteLogger::new(
+        LevelFilter::Info,
+        Config::default(),
+        File::create("chip8.log").unwrap(),
+    )];
+    // the terminal frontend owns the TTY, logging there would garble the picture
+    if args.headless || args.frontend != Frontend::Terminal {
+        loggers.push(TermLogger::new(
             LevelFilter::Info,
             ConfigBuilder::new()
                 .set_time_level(LevelFilter::Off)
                 .build(),
             TerminalMode::Mixed,
             ColorChoice::Auto,
-        ),
-        WriteLogger::new(
-            LevelFilter::Info,
-            Config::default(),
-            File::create("chip8.log").unwrap(),
-        ),
-    ])
-    .unwrap();
+        ));
+    }
+    CombinedLogger::init(loggers).unwrap();
+
+    let rom_path = args.rom.clone().unwrap_or_else(pick_rom);
+    let cartridge = match container::load(&rom_path) {
+        Ok(cartridge) => cartridge,
+        Err(e) => {
+            error!("{e}");
+            return;
+        }
+    };
+    let rom = &cartridge.rom;
+    let profile = match profile(&args, &cartridge) {
+        Ok(profile) => profile,
+        Err(e) => {
+            error!("{e}");
+            return;
+        }
+    };
+    let palette = profile.palette.unwrap_or_default();
+
+    let stack_storage = if args.vip_stack {
+        StackStorage::Memory(VIP_STACK_ADDRESS)
+    } else {
+        StackStorage::Internal
+    };
+    let mut cpu = my_chip_8::cpu::from_parts(
+        Memory::new(args.memory_size, args.out_of_bounds),
+        Stack::new(profile.platform.stack_depth(), stack_storage),
+    );
 
-    let sdl_context = sdl2::init().unwrap();
-    let mut screen = renderer::new(&sdl_context);
-    let mut cpu = Cpu::default();
+    cpu.set_engine(args.engine);
+    cpu.set_quirks(profile.quirks);
+    if let Some(seed) = args.seed {
+        cpu.seed_random(seed);
+    }
 
     // CPU -- Loading fonts and rom
     cpu.load_fonts("./roms/fonts.ch8").unwrap();
 
-    let rom_path = FileDialog::new()
-        .add_filter("ch8", &["ch8"])
-        .set_directory("./roms")
-        .pick_file()
-        .expect("You need to choose a rom");
-
-    // DSL --
-    cpu.load_rom(
-        rom_path
-            .to_owned()
-            .to_str()
-            .expect("there should be a path"),
-    )
-    .unwrap();
-
-    let mut event_pump = sdl_context.event_pump().unwrap();
-    'running: loop {
-        let start = SystemTime::now();
-
-        for event in event_pump.poll_iter() {
-            match event {
-                Event::Quit { .. }
-                | Event::KeyDown {
-                    keycode: Some(Keycode::Escape),
-                    ..
-                } => {
-                    break 'running;
-                }
-                Event::KeyDown {
-                    scancode: Some(scancode),
-                    ..
-                } => {
-                    // info!("Key pressed {keycode} with scancode {scancode}");
-                    let key: Option<u16> = match scancode {
-                        Scancode::Num1 => Some(0x1),
-                        Scancode::Num2 => Some(0x2),
-                        Scancode::Num3 => Some(0x3),
-                        Scancode::Num4 => Some(0xC),
-                        Scancode::Q => Some(0x4),
-                        Scancode::W => Some(0x5),
-                        Scancode::E => Some(0x6),
-                        Scancode::R => Some(0xD),
-                        Scancode::A => Some(0x7),
-                        Scancode::S => Some(0x8),
-                        Scancode::D => Some(0x9),
-                        Scancode::F => Some(0xE),
-                        Scancode::Z => Some(0xA),
-                        Scancode::X => Some(0x0),
-                        Scancode::C => Some(0xB),
-                        Scancode::V => Some(0xF),
-                        _ => None,
-                    };
-
-                    if let Some(code) = key {
-                        cpu.set_key_pressed(code);
-                    } else {
-                        cpu.set_key_released();
+    let load_address = args.load_address.unwrap_or(profile.platform.load_address());
+    let entry_point = args.entry_point.unwrap_or(load_address);
+    if let Err(e) = cpu.load_rom_at(rom, load_address, entry_point) {
+        error!(
+            "{e} (loading {} bytes at {load_address:#05X}, starting at {entry_point:#05X})",
+            rom.len()
+        );
+        return;
+    }
+
+    let mut capture = Capture::new(&args, rom_path);
+    let mut debugger = debugger(&args);
+    if let Some(path) = &args.trace {
+        match tracer(&args, path) {
+            Ok(tracer) => debugger.set_tracer(tracer),
+            Err(e) => error!("can't trace: {e}"),
+        }
+    }
+    if args.profile.is_some() || args.heatmap.is_some() {
+        debugger.set_profiler(Profiler::new(cpu.memory().size()));
+    }
+    let real_time = RunConfig {
+        max_frames: None,
+        real_time: true,
+        speed: args.speed,
+        syntax: args.syntax,
+        instructions_per_frame: args
+            .instructions_per_frame
+            .unwrap_or(profile.instructions_per_frame),
+    };
+
+    let result = if args.headless {
+        if args.record {
+            capture.start_recording(palette);
+        }
+        let result = drive(
+            &args,
+            &mut cpu,
+            &mut Headless,
+            &mut Headless,
+            &mut Headless,
+            &mut capture,
+            &mut debugger,
+            RunConfig {
+                max_frames: Some(args.frames),
+                real_time: false,
+                ..real_time
+            },
+        );
+        if args.screenshot {
+            capture.take_screenshot(&cpu, &palette);
+        }
+        result
+    } else {
+        match args.frontend {
+            Frontend::Sdl => run_sdl(
+                &args,
+                &mut cpu,
+                &mut capture,
+                &mut debugger,
+                real_time,
+                &profile,
+            ),
+            Frontend::Terminal => Terminal::enter(palette)
+                .map_err(|e| e.to_string())
+                .and_then(|mut terminal| {
+                    terminal.input.set_keymap(profile.keymap);
+                    if args.record {
+                        capture.start_recording(terminal.display.palette());
                     }
-                }
-                Event::KeyUp { .. } => {
-                    cpu.set_key_released();
-                }
-                _ => {}
-            }
+                    drive(
+                        &args,
+                        &mut cpu,
+                        &mut terminal.display,
+                        &mut terminal.audio,
+                        &mut terminal.input,
+                        &mut capture,
+                        &mut debugger,
+                        real_time,
+                    )
+                }),
         }
-        let keys = event_pump
-            .keyboard_state()
-            .pressed_scancodes()
-            .map(|scancode| match scancode {
-                Scancode::Num1 => 0x1,
-                Scancode::Num2 => 0x2,
-                Scancode::Num3 => 0x3,
-                Scancode::Num4 => 0xC,
-                Scancode::Q => 0x4,
-                Scancode::W => 0x5,
-                Scancode::E => 0x6,
-                Scancode::R => 0xD,
-                Scancode::A => 0x7,
-                Scancode::S => 0x8,
-                Scancode::D => 0x9,
-                Scancode::F => 0xE,
-                Scancode::Z => 0xA,
-                Scancode::X => 0x0,
-                Scancode::C => 0xB,
-                Scancode::V => 0xF,
-                _ => 0x0,
-            })
-            .collect::<HashSet<u8>>();
-        cpu.set_keys_pressed(keys);
-
-        cpu.tick_timers();
-        cpu.tick();
-        if cpu.should_render {
-            screen.render(cpu.screen);
-            cpu.should_render = false;
+    };
+
+    if let Err(e) = result {
+        error!("emulation stopped: {e}");
+    }
+    if let Some(profiler) = debugger.profiler() {
+        save_profile(&args, profiler, &cpu);
+    }
+}
+
+/// How to run the ROM: `--platform` wins over the settings saved in Octo
+/// cartridges, then the database, the file extension and finally guessing.
+fn profile(args: &Args, cartridge: &Cartridge) -> Result<Profile, String> {
+    let database = match &args.database {
+        Some(directory) => Database::load(directory)?,
+        None => Database::bundled(),
+    };
+    let mut profile = if let Some(profile) = &cartridge.profile {
+        info!("Octo cartridge, runs on {:?}", profile.platform);
+        profile.clone()
+    } else if let Some(profile) = database.lookup(&cartridge.rom) {
+        info!(
+            "{}, runs on {:?}",
+            profile.title.as_deref().unwrap_or_default(),
+            profile.platform
+        );
+        profile
+    } else if let Some(platform) = cartridge.platform {
+        info!("unknown ROM, runs on {platform:?} according to its extension");
+        Profile {
+            platform,
+            quirks: platform.quirks(),
+            ..guess(&cartridge.rom)
         }
+    } else {
+        let profile = guess(&cartridge.rom);
+        info!("unknown ROM, guessed {:?}", profile.platform);
+        profile
+    };
+    if let Some(platform) = args.platform {
+        profile.platform = platform;
+        profile.quirks = platform.quirks();
+    }
+    Ok(profile)
+}
 
-        sleep(Duration::from_secs_f64(1.0 / 3000.0).saturating_sub(start.elapsed().unwrap()));
+fn save_profile(args: &Args, profiler: &Profiler, cpu: &Cpu) {
+    if let Some(path) = &args.profile {
+        match fs::write(path, profiler.report(cpu.memory().as_slice(), args.syntax)) {
+            Ok(()) => info!("profile saved to {}", path.display()),
+            Err(e) => error!("can't write {}: {e}", path.display()),
+        }
+    }
+    if let Some(path) = &args.heatmap {
+        let heatmap = profiler.heatmap();
+        match save_rgb_png(path, heatmap.width, heatmap.height, &heatmap.pixels) {
+            Ok(()) => info!("heatmap saved to {}", path.display()),
+            Err(e) => error!("{e}"),
+        }
+    }
+}
+
+/// Runs the emulator loop, or hands the CPU over to a remote debugger with `--gdb`.
+#[allow(clippy::too_many_arguments)]
+fn drive(
+    args: &Args,
+    cpu: &mut Cpu,
+    display: &mut dyn Display,
+    audio: &mut dyn Audio,
+    input: &mut dyn Input,
+    capture: &mut Capture,
+    debugger: &mut Debugger,
+    config: RunConfig,
+) -> Result<(), String> {
+    match &args.gdb {
+        Some(address) => my_chip_8::gdb::serve(
+            address,
+            cpu,
+            display,
+            audio,
+            input,
+            std::mem::take(debugger),
+            config.instructions_per_frame,
+        ),
+        None => my_chip_8::emulator::run(cpu, display, audio, input, capture, debugger, config),
     }
 }
+
+fn debugger(args: &Args) -> Debugger {
+    let mut debugger = Debugger::default();
+    args.breakpoints
+        .iter()
+        .for_each(|breakpoint| debugger.add_breakpoint(breakpoint.clone()));
+    args.break_if
+        .iter()
+        .for_each(|condition| debugger.add_condition(condition.clone()));
+
+    let watchpoints = [
+        (&args.watch, WatchKind::Access),
+        (&args.watch_read, WatchKind::Read),
+        (&args.watch_write, WatchKind::Write),
+    ];
+    for (watchpoints, kind) in watchpoints {
+        for watchpoint in watchpoints {
+            debugger.add_watchpoint(Watchpoint {
+                kind,
+                ..watchpoint.clone()
+            });
+        }
+    }
+    debugger
+}
+
+fn tracer(args: &Args, path: &Path) -> Result<Tracer, String> {
+    let tracer = match args.trace_ring {
+        Some(capacity) => Tracer::ring(path, args.trace_format, capacity),
+        None => Tracer::stream(path, args.trace_format)?,
+    };
+    Ok(match &args.trace_range {
+        Some(addresses) => tracer.with_addresses(addresses.clone()),
+        None => tracer,
+    })
+}
+
+#[cfg(feature = "sdl")]
+fn pick_rom() -> PathBuf {
+    FileDialog::new()
+        .add_filter(
+            "CHIP-8 programs",
+            &["ch8", "c8x", "sc8", "xo8", "8o", "gif", "zip", "gz"],
+        )
+        .add_filter("ROMs", &["ch8", "c8x", "sc8", "xo8"])
+        .add_filter("Octo source", &["8o"])
+        .add_filter("Octo cartridges", &["gif"])
+        .add_filter("Archives", &["zip", "gz"])
+        .set_directory("./roms")
+        .pick_file()
+        .expect("You need to choose a rom")
+}
+
+#[cfg(not(feature = "sdl"))]
+fn pick_rom() -> PathBuf {
+    panic!("You need to pass a rom path, the file dialog requires the sdl feature")
+}
+
+#[cfg(feature = "sdl")]
+fn run_sdl(
+    args: &Args,
+    cpu: &mut Cpu,
+    capture: &mut Capture,
+    debugger: &mut Debugger,
+    config: RunConfig,
+    profile: &Profile,
+) -> Result<(), String> {
+    let sdl_context = sdl2::init()?;
+    let mut display = my_chip_8::renderer::new(&sdl_context, args.filter.into());
+    display.palette = profile.palette.unwrap_or_default();
+    let mut audio = my_chip_8::sdl::SdlAudio::new(&sdl_context);
+    let mut input = my_chip_8::sdl::SdlInput::new(&sdl_context);
+    input.set_keymap(profile.keymap);
+
+    if args.record {
+        capture.start_recording(display.palette);
+    }
+    drive(
+        args,
+        cpu,
+        &mut display,
+        &mut audio,
+        &mut input,
+        capture,
+        debugger,
+        config,
+    )
+}
+
+#[cfg(not(feature = "sdl"))]
+fn run_sdl(
+    _args: &Args,
+    _cpu: &mut Cpu,
+    _capture: &mut Capture,
+    _debugger: &mut Debugger,
+    _config: RunConfig,
+    _profile: &Profile,
+) -> Result<(), String> {
+    Err("this build has no SDL support, use --frontend terminal or --headless".to_string())
+}
@@ -0,0 +1,259 @@
+use crate::fault::Fault;
+use clap::ValueEnum;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum MemorySize {
+    /// 4 KiB, the original COSMAC VIP and SUPER-CHIP address space
+    #[default]
+    #[value(name = "4k")]
+    Standard,
+    /// 64 KiB, as used by XO-CHIP
+    #[value(name = "64k")]
+    Extended,
+}
+
+impl MemorySize {
+    pub fn bytes(self) -> usize {
+        match self {
+            MemorySize::Standard => 0x1000,
+            MemorySize::Extended => 0x10000,
+        }
+    }
+}
+
+/// What happens when an instruction reaches past the end of memory.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutOfBounds {
+    /// Addresses wrap around, like the truncated address bus of the original hardware
+    #[default]
+    Wrap,
+    /// The access stops emulation with a fault
+    Fault,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AccessKind {
+    /// Instruction fetch by the CPU.
+    Fetch,
+    Read,
+    Write,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Access {
+    pub kind: AccessKind,
+    pub address: usize,
+    pub value: u8,
+}
+
+/// Byte-addressed RAM. Tools can enable the access journal to observe every
+/// read and write performed by the CPU, and drain it after each instruction.
+#[derive(Clone, Debug)]
+pub struct Memory {
+    bytes: Vec<u8>,
+    out_of_bounds: OutOfBounds,
+    journal: Option<Vec<Access>>,
+    /// Addresses changed since the last `clear_written`, for caches of decoded instructions.
+    written: Option<Vec<usize>>,
+}
+
+impl Default for Memory {
+    fn default() -> Self {
+        Memory::new(MemorySize::default(), OutOfBounds::default())
+    }
+}
+
+impl Memory {
+    pub fn new(size: MemorySize, out_of_bounds: OutOfBounds) -> Memory {
+        Memory {
+            bytes: vec![0; size.bytes()],
+            out_of_bounds,
+            journal: None,
+            written: None,
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn read(&mut self, address: usize) -> Result<u8, Fault> {
+        self.read_as(AccessKind::Read, address)
+    }
+
+    pub fn write(&mut self, address: usize, value: u8) -> Result<(), Fault> {
+        let address = self.resolve(address)?;
+        self.bytes[address] = value;
+        self.record(AccessKind::Write, address, value);
+        if let Some(written) = &mut self.written {
+            written.push(address);
+        }
+        Ok(())
+    }
+
+    /// Big-endian 16-bit read, each byte being resolved on its own so that
+    /// an instruction straddling the end of memory wraps like any other access.
+    pub fn read_word(&mut self, address: usize) -> Result<u16, Fault> {
+        let high = self.read(address)?;
+        let low = self.read(address + 1)?;
+        Ok(u16::from_be_bytes([high, low]))
+    }
+
+    /// Big-endian 16-bit write, nothing being written unless both bytes can be.
+    pub fn write_word(&mut self, address: usize, value: u16) -> Result<(), Fault> {
+        self.resolve(address)?;
+        self.resolve(address + 1)?;
+        let [high, low] = value.to_be_bytes();
+        self.write(address, high)?;
+        self.write(address + 1, low)
+    }
+
+    /// Same as `read_word`, journaled as an instruction fetch.
+    pub fn fetch_word(&mut self, address: usize) -> Result<u16, Fault> {
+        let high = self.read_as(AccessKind::Fetch, address)?;
+        let low = self.read_as(AccessKind::Fetch, address + 1)?;
+        Ok(u16::from_be_bytes([high, low]))
+    }
+
+    /// Reads without going through the journal nor the out-of-bounds policy.
+    pub fn peek(&self, address: usize) -> Option<u8> {
+        self.bytes.get(address).copied()
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Copies `bytes` at `offset`, failing if they don't fit regardless of the policy.
+    pub fn load(&mut self, offset: usize, bytes: &[u8]) -> Result<(), Fault> {
+        let size = self.bytes.len();
+        let Some(end) = offset.checked_add(bytes.len()).filter(|&end| end <= size) else {
+            return Err(Fault::MemoryOutOfBounds {
+                address: offset.saturating_add(bytes.len().saturating_sub(1)),
+                size,
+            });
+        };
+        self.bytes[offset..end].copy_from_slice(bytes);
+        if let Some(written) = &mut self.written {
+            written.extend(offset..end);
+        }
+        Ok(())
+    }
+
+    pub fn set_journal_enabled(&mut self, enabled: bool) {
+        self.journal = enabled.then(Vec::new);
+    }
+
+    /// Returns the accesses recorded since the last call, oldest first.
+    pub fn drain_journal(&mut self) -> Vec<Access> {
+        self.journal
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    pub fn is_journal_enabled(&self) -> bool {
+        self.journal.is_some()
+    }
+
+    /// Unlike the journal, also sees `load`, and only keeps the addresses.
+    pub fn set_write_tracking(&mut self, enabled: bool) {
+        self.written = enabled.then(Vec::new);
+    }
+
+    pub fn written(&self) -> &[usize] {
+        self.written.as_deref().unwrap_or_default()
+    }
+
+    pub fn clear_written(&mut self) {
+        if let Some(written) = &mut self.written {
+            written.clear();
+        }
+    }
+
+    fn read_as(&mut self, kind: AccessKind, address: usize) -> Result<u8, Fault> {
+        let address = self.resolve(address)?;
+        let value = self.bytes[address];
+        self.record(kind, address, value);
+        Ok(value)
+    }
+
+    fn resolve(&self, address: usize) -> Result<usize, Fault> {
+        let size = self.bytes.len();
+        match self.out_of_bounds {
+            _ if address < size => Ok(address),
+            OutOfBounds::Wrap => Ok(address % size),
+            OutOfBounds::Fault => Err(Fault::MemoryOutOfBounds { address, size }),
+        }
+    }
+
+    fn record(&mut self, kind: AccessKind, address: usize, value: u8) {
+        if let Some(journal) = &mut self.journal {
+            journal.push(Access {
+                kind,
+                address,
+                value,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fault::Fault;
+    use crate::memory::{Access, AccessKind, Memory, MemorySize, OutOfBounds};
+
+    #[test]
+    fn wraps_out_of_range_addresses() {
+        let mut memory = Memory::new(MemorySize::Standard, OutOfBounds::Wrap);
+        memory.write(0x1000, 0xAB).unwrap();
+
+        assert_eq!(memory.peek(0x000), Some(0xAB));
+        assert_eq!(memory.read_word(0xFFF).unwrap(), 0x00AB);
+    }
+
+    #[test]
+    fn faults_on_out_of_range_addresses() {
+        let mut memory = Memory::new(MemorySize::Standard, OutOfBounds::Fault);
+
+        assert_eq!(
+            memory.read(0x1000),
+            Err(Fault::MemoryOutOfBounds {
+                address: 0x1000,
+                size: 0x1000
+            })
+        );
+        assert!(Memory::new(MemorySize::Extended, OutOfBounds::Fault)
+            .read(0x1000)
+            .is_ok());
+        assert!(memory.load(0xFFF, &[1, 2]).is_err());
+        assert!(memory.load(usize::MAX, &[1, 2]).is_err());
+        assert!(memory.load(0x1001, &[]).is_err());
+    }
+
+    #[test]
+    fn journals_accesses_when_enabled() {
+        let mut memory = Memory::default();
+        memory.write(0x300, 1).unwrap();
+        memory.set_journal_enabled(true);
+        memory.write(0x301, 2).unwrap();
+        memory.read(0x300).unwrap();
+
+        assert_eq!(
+            memory.drain_journal(),
+            vec![
+                Access {
+                    kind: AccessKind::Write,
+                    address: 0x301,
+                    value: 2
+                },
+                Access {
+                    kind: AccessKind::Read,
+                    address: 0x300,
+                    value: 1
+                },
+            ]
+        );
+        assert!(memory.drain_journal().is_empty());
+    }
+}
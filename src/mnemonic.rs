@@ -0,0 +1,207 @@
+use crate::decoder::decode_instruction;
+use crate::opcode::OpCode;
+use clap::ValueEnum;
+use std::fmt::{self, Display, Formatter};
+
+/// Assembly language instructions are written in.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum Syntax {
+    /// Cowgod's technical reference, e.g. `XOR V1, V2`
+    #[default]
+    Cowgod,
+    /// Octo's assembly language, e.g. `v1 ^= v2`
+    Octo,
+}
+
+/// An instruction written in a given syntax, with hex operands.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Mnemonic {
+    op_code: OpCode,
+    syntax: Syntax,
+    /// The word following `F000`, printed when known.
+    long_address: Option<u16>,
+}
+
+impl OpCode {
+    pub fn mnemonic(self, syntax: Syntax) -> Mnemonic {
+        Mnemonic {
+            op_code: self,
+            syntax,
+            long_address: None,
+        }
+    }
+}
+
+impl Mnemonic {
+    pub fn with_long_address(mut self, address: u16) -> Mnemonic {
+        self.long_address = Some(address);
+        self
+    }
+
+    fn fmt_cowgod(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.op_code {
+            OpCode::ClearScreen => write!(f, "CLS"),
+            OpCode::RetFromSubroutine => write!(f, "RET"),
+            OpCode::Jump(nnn) => write!(f, "JP {nnn:#05X}"),
+            OpCode::JumpWithV0Offset(nnn) => write!(f, "JP V0, {nnn:#05X}"),
+            OpCode::CallSubroutine(nnn) => write!(f, "CALL {nnn:#05X}"),
+            OpCode::SetRegister { register, value } => write!(f, "LD V{register:X}, {value:#04X}"),
+            OpCode::AddRegister { register, value } => write!(f, "ADD V{register:X}, {value:#04X}"),
+            OpCode::SetIndex(nnn) => write!(f, "LD I, {nnn:#05X}"),
+            OpCode::Draw(x, y, n) => write!(f, "DRW V{x:X}, V{y:X}, {n:#X}"),
+            OpCode::SkipIfRegisterEquals(x, nn) => write!(f, "SE V{x:X}, {nn:#04X}"),
+            OpCode::SkipIfRegisterNotEquals(x, nn) => write!(f, "SNE V{x:X}, {nn:#04X}"),
+            OpCode::SkipIfBothRegistersEqual(x, y) => write!(f, "SE V{x:X}, V{y:X}"),
+            OpCode::SetRegisterToRegisterValue(x, y) => write!(f, "LD V{x:X}, V{y:X}"),
+            OpCode::SetRegisterToRegisterValueUsingOR(x, y) => write!(f, "OR V{x:X}, V{y:X}"),
+            OpCode::SetRegisterToRegisterValueUsingAND(x, y) => write!(f, "AND V{x:X}, V{y:X}"),
+            OpCode::SetRegisterToRegisterValueUsingXOR(x, y) => write!(f, "XOR V{x:X}, V{y:X}"),
+            OpCode::AddRegisterToRegister(x, y) => write!(f, "ADD V{x:X}, V{y:X}"),
+            OpCode::SubRegisterToRegister(x, y) => write!(f, "SUB V{x:X}, V{y:X}"),
+            OpCode::ShiftRightRegisterFromRegister(x, y) => write!(f, "SHR V{x:X}, V{y:X}"),
+            OpCode::ShiftLeftRegisterFromRegister(x, y) => write!(f, "SHL V{x:X}, V{y:X}"),
+            OpCode::SubRegisterToRegisterReverse(x, y) => write!(f, "SUBN V{x:X}, V{y:X}"),
+            OpCode::LoadFromMemoryToRegisters(x) => write!(f, "LD V{x:X}, [I]"),
+            OpCode::LoadFromRegistersToMemory(x) => write!(f, "LD [I], V{x:X}"),
+            OpCode::StoreBCDRepresentationOfRegister(x) => write!(f, "LD B, V{x:X}"),
+            OpCode::AddRegisterValueToIndex(x) => write!(f, "ADD I, V{x:X}"),
+            OpCode::SetRegisterFromDelayTimer(x) => write!(f, "LD V{x:X}, DT"),
+            OpCode::SetSoundTimerFromRegister(x) => write!(f, "LD ST, V{x:X}"),
+            OpCode::SetDelayTimerFromRegister(x) => write!(f, "LD DT, V{x:X}"),
+            OpCode::SkipIfBothRegistersNotEqual(x, y) => write!(f, "SNE V{x:X}, V{y:X}"),
+            OpCode::SetRegisterWithRandom(x, nn) => write!(f, "RND V{x:X}, {nn:#04X}"),
+            OpCode::LoadSpriteRepresentationInMemory(x) => write!(f, "LD F, V{x:X}"),
+            OpCode::GetKey(x) => write!(f, "LD V{x:X}, K"),
+            OpCode::SkipIfKey(x) => write!(f, "SKP V{x:X}"),
+            OpCode::SkipIfNotKey(x) => write!(f, "SKNP V{x:X}"),
+            OpCode::LoadIndexLong => match self.long_address {
+                Some(address) => write!(f, "LD I, LONG {address:#06X}"),
+                None => write!(f, "LD I, LONG"),
+            },
+            OpCode::Unknown(word) if word >> 12 == 0 => write!(f, "SYS {word:#05X}"),
+            OpCode::Unknown(word) => write!(f, "DW {word:#06X}"),
+        }
+    }
+
+    fn fmt_octo(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.op_code {
+            OpCode::ClearScreen => write!(f, "clear"),
+            OpCode::RetFromSubroutine => write!(f, "return"),
+            OpCode::Jump(nnn) => write!(f, "jump {nnn:#05X}"),
+            OpCode::JumpWithV0Offset(nnn) => write!(f, "jump0 {nnn:#05X}"),
+            OpCode::CallSubroutine(nnn) => write!(f, ":call {nnn:#05X}"),
+            OpCode::SetRegister { register, value } => write!(f, "v{register:x} := {value:#04X}"),
+            OpCode::AddRegister { register, value } => write!(f, "v{register:x} += {value:#04X}"),
+            OpCode::SetIndex(nnn) => write!(f, "i := {nnn:#05X}"),
+            OpCode::Draw(x, y, n) => write!(f, "sprite v{x:x} v{y:x} {n:#X}"),
+            // Octo writes skips as the condition for running the next instruction
+            OpCode::SkipIfRegisterEquals(x, nn) => write!(f, "if v{x:x} != {nn:#04X} then"),
+            OpCode::SkipIfRegisterNotEquals(x, nn) => write!(f, "if v{x:x} == {nn:#04X} then"),
+            OpCode::SkipIfBothRegistersEqual(x, y) => write!(f, "if v{x:x} != v{y:x} then"),
+            OpCode::SkipIfBothRegistersNotEqual(x, y) => write!(f, "if v{x:x} == v{y:x} then"),
+            OpCode::SetRegisterToRegisterValue(x, y) => write!(f, "v{x:x} := v{y:x}"),
+            OpCode::SetRegisterToRegisterValueUsingOR(x, y) => write!(f, "v{x:x} |= v{y:x}"),
+            OpCode::SetRegisterToRegisterValueUsingAND(x, y) => write!(f, "v{x:x} &= v{y:x}"),
+            OpCode::SetRegisterToRegisterValueUsingXOR(x, y) => write!(f, "v{x:x} ^= v{y:x}"),
+            OpCode::AddRegisterToRegister(x, y) => write!(f, "v{x:x} += v{y:x}"),
+            OpCode::SubRegisterToRegister(x, y) => write!(f, "v{x:x} -= v{y:x}"),
+            OpCode::ShiftRightRegisterFromRegister(x, y) => write!(f, "v{x:x} >>= v{y:x}"),
+            OpCode::ShiftLeftRegisterFromRegister(x, y) => write!(f, "v{x:x} <<= v{y:x}"),
+            OpCode::SubRegisterToRegisterReverse(x, y) => write!(f, "v{x:x} =- v{y:x}"),
+            OpCode::LoadFromMemoryToRegisters(x) => write!(f, "load v{x:x}"),
+            OpCode::LoadFromRegistersToMemory(x) => write!(f, "save v{x:x}"),
+            OpCode::StoreBCDRepresentationOfRegister(x) => write!(f, "bcd v{x:x}"),
+            OpCode::AddRegisterValueToIndex(x) => write!(f, "i += v{x:x}"),
+            OpCode::SetRegisterFromDelayTimer(x) => write!(f, "v{x:x} := delay"),
+            OpCode::SetSoundTimerFromRegister(x) => write!(f, "buzzer := v{x:x}"),
+            OpCode::SetDelayTimerFromRegister(x) => write!(f, "delay := v{x:x}"),
+            OpCode::SetRegisterWithRandom(x, nn) => write!(f, "v{x:x} := random {nn:#04X}"),
+            OpCode::LoadSpriteRepresentationInMemory(x) => write!(f, "i := hex v{x:x}"),
+            OpCode::GetKey(x) => write!(f, "v{x:x} := key"),
+            OpCode::SkipIfKey(x) => write!(f, "if v{x:x} -key then"),
+            OpCode::SkipIfNotKey(x) => write!(f, "if v{x:x} key then"),
+            OpCode::LoadIndexLong => match self.long_address {
+                Some(address) => write!(f, "i := long {address:#06X}"),
+                None => write!(f, "i := long"),
+            },
+            // raw bytes, which Octo assembles as they are
+            OpCode::Unknown(word) => write!(f, "{:#04X} {:#04X}", word >> 8, word & 0xFF),
+        }
+    }
+}
+
+impl Display for Mnemonic {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.syntax {
+            Syntax::Cowgod => self.fmt_cowgod(f),
+            Syntax::Octo => self.fmt_octo(f),
+        }
+    }
+}
+
+/// One line per instruction from `start` on, as `address  word  mnemonic`. Both
+/// words of `F000 NNNN` are shown on the same line.
+pub fn disassemble(memory: &[u8], start: u16, instructions: usize, syntax: Syntax) -> Vec<String> {
+    let word = |address: u16| {
+        let byte = |address: u16| memory.get(address as usize).copied();
+        Some(u16::from_be_bytes([
+            byte(address)?,
+            byte(address.wrapping_add(1))?,
+        ]))
+    };
+    let mut lines = vec![];
+    let mut address = start;
+    while lines.len() < instructions {
+        let Some(opcode) = word(address) else {
+            break;
+        };
+        let op_code = decode_instruction(opcode);
+        let mut mnemonic = op_code.mnemonic(syntax);
+        let mut words = format!("{opcode:04X}");
+        if op_code == OpCode::LoadIndexLong {
+            if let Some(long_address) = word(address.wrapping_add(2)) {
+                mnemonic = mnemonic.with_long_address(long_address);
+                words = format!("{opcode:04X} {long_address:04X}");
+            }
+        }
+        lines.push(format!("{address:#06X}  {words:<9}  {mnemonic}"));
+        address = address.wrapping_add(op_code.size());
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::decoder::decode_instruction;
+    use crate::mnemonic::{disassemble, Syntax};
+
+    #[test]
+    fn formats_instructions_in_both_syntaxes() {
+        let cases = [
+            (0x8123, "XOR V1, V2", "v1 ^= v2"),
+            (0x1208, "JP 0x208", "jump 0x208"),
+            (0xD12F, "DRW V1, V2, 0xF", "sprite v1 v2 0xF"),
+            (0x3A07, "SE VA, 0x07", "if va != 0x07 then"),
+            (0xFB65, "LD VB, [I]", "load vb"),
+            (0x0123, "SYS 0x123", "0x01 0x23"),
+        ];
+        for (word, cowgod, octo) in cases {
+            let op_code = decode_instruction(word);
+            assert_eq!(op_code.mnemonic(Syntax::Cowgod).to_string(), cowgod);
+            assert_eq!(op_code.mnemonic(Syntax::Octo).to_string(), octo);
+            assert_eq!(format!("{op_code:#}"), cowgod);
+        }
+    }
+
+    #[test]
+    fn disassembles_long_index_loads_as_one_instruction() {
+        let memory = [0xF0, 0x00, 0x12, 0x34, 0x00, 0xE0];
+        assert_eq!(
+            disassemble(&memory, 0, 3, Syntax::Octo),
+            [
+                "0x0000  F000 1234  i := long 0x1234",
+                "0x0004  00E0       clear"
+            ]
+        );
+    }
+}
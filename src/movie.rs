@@ -0,0 +1,93 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Keys held on each frame of a run, to replay the same input everywhere.
+///
+/// The text format has one `<frame> <keys>` line per change, the keys being
+/// comma-separated hex digits or `-` for none, held until the next line:
+///
+/// ```text
+/// # start the game, then hold left
+/// 30 5
+/// 32 -
+/// 120 4,6
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Movie {
+    /// Frames the held keys change at, in increasing order.
+    changes: Vec<(u32, HashSet<u8>)>,
+}
+
+impl Movie {
+    pub fn load(path: &Path) -> Result<Movie, String> {
+        fs::read_to_string(path)
+            .map_err(|e| format!("can't read {}: {e}", path.display()))?
+            .parse()
+    }
+
+    /// Keys held during `frame`.
+    pub fn keys_at(&self, frame: u32) -> HashSet<u8> {
+        let held = self.changes.partition_point(|(start, _)| *start <= frame);
+        match held {
+            0 => HashSet::new(),
+            _ => self.changes[held - 1].1.clone(),
+        }
+    }
+}
+
+impl FromStr for Movie {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut changes: Vec<(u32, HashSet<u8>)> = vec![];
+        for (number, line) in s.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let error = |message: &str| format!("line {}: {message}", number + 1);
+
+            let (frame, keys) = line.split_once(char::is_whitespace).unwrap_or((line, "-"));
+            let frame: u32 = frame
+                .parse()
+                .map_err(|_| error(&format!("invalid frame {frame}")))?;
+            if changes.last().is_some_and(|(last, _)| *last >= frame) {
+                return Err(error("frames must increase"));
+            }
+            let keys = match keys.trim() {
+                "-" => HashSet::new(),
+                keys => keys
+                    .split(',')
+                    .map(|key| {
+                        u8::from_str_radix(key.trim(), 16)
+                            .ok()
+                            .filter(|key| *key < 16)
+                            .ok_or_else(|| error(&format!("invalid key {key}")))
+                    })
+                    .collect::<Result<_, _>>()?,
+            };
+            changes.push((frame, keys));
+        }
+        Ok(Movie { changes })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::movie::Movie;
+    use std::collections::HashSet;
+
+    #[test]
+    fn holds_keys_until_the_next_change() {
+        let movie: Movie = "# comment\n30 5\n32 -\n120 4, a\n".parse().unwrap();
+
+        assert_eq!(movie.keys_at(0), HashSet::new());
+        assert_eq!(movie.keys_at(31), HashSet::from([5]));
+        assert_eq!(movie.keys_at(100), HashSet::new());
+        assert_eq!(movie.keys_at(5000), HashSet::from([4, 0xA]));
+        assert!("3 5\n2 6".parse::<Movie>().is_err());
+        assert!("3 g".parse::<Movie>().is_err());
+    }
+}
@@ -0,0 +1,976 @@
+use crate::expression::parse_number;
+use crate::opcode::OpCode;
+use std::collections::{HashMap, VecDeque};
+
+/// Address Octo programs are assembled for.
+pub const ORIGIN: u16 = 0x200;
+/// Macro expansions allowed in one program, to stop runaway recursion.
+const MAX_EXPANSIONS: usize = 100_000;
+
+#[derive(Clone, Debug, PartialEq)]
+struct Token {
+    text: String,
+    line: usize,
+}
+
+/// How a label's address is written where it's referenced before being defined.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Reference {
+    /// The low 12 bits of the instruction word.
+    Nnn,
+    /// A whole big-endian word, as after `F000`.
+    Word,
+    /// One byte of `address >> shift | or`, for `:unpack`.
+    Byte { shift: u8, or: u8 },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Value {
+    Known(i64),
+    /// A label defined further down.
+    Forward(String),
+}
+
+/// What an immediate or register operand compares or assigns.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Operand {
+    Register(u8),
+    Byte(u8),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    Greater,
+    LessOrEqual,
+    GreaterOrEqual,
+    Key,
+    NotKey,
+}
+
+impl Comparison {
+    fn negate(self) -> Comparison {
+        match self {
+            Comparison::Equal => Comparison::NotEqual,
+            Comparison::NotEqual => Comparison::Equal,
+            Comparison::Less => Comparison::GreaterOrEqual,
+            Comparison::Greater => Comparison::LessOrEqual,
+            Comparison::LessOrEqual => Comparison::Greater,
+            Comparison::GreaterOrEqual => Comparison::Less,
+            Comparison::Key => Comparison::NotKey,
+            Comparison::NotKey => Comparison::Key,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct Condition {
+    register: u8,
+    comparison: Comparison,
+    /// Missing for key tests.
+    operand: Option<Operand>,
+}
+
+/// Structured control flow waiting for its closing word.
+enum Block {
+    /// `if ... begin`, with the jump taken when the condition is false.
+    If { jump: u16 },
+    /// `else`, with the jump over the else branch.
+    Else { jump: u16 },
+    /// `loop`, with the jumps out of it of its `while`s.
+    Loop { start: u16, exits: Vec<u16> },
+}
+
+struct Macro {
+    parameters: Vec<String>,
+    body: Vec<Token>,
+}
+
+/// Compiles Octo source code into a ROM to load at `ORIGIN`.
+pub fn compile(source: &str) -> Result<Vec<u8>, String> {
+    let mut compiler = Compiler::new(source);
+    compiler
+        .run()
+        .map_err(|e| format!("line {}: {e}", compiler.line))?;
+    Ok(compiler.rom)
+}
+
+struct Compiler {
+    tokens: VecDeque<Token>,
+    /// Line of the last token taken, for error messages.
+    line: usize,
+    rom: Vec<u8>,
+    here: u16,
+    labels: HashMap<String, u16>,
+    constants: HashMap<String, f64>,
+    aliases: HashMap<String, u8>,
+    macros: HashMap<String, Macro>,
+    /// Forward references, patched once the whole program is read.
+    references: Vec<(u16, String, Reference, usize)>,
+    blocks: Vec<Block>,
+    /// Label given by `:next`, pointing into the next instruction.
+    next: Option<String>,
+    expansions: usize,
+}
+
+impl Compiler {
+    fn new(source: &str) -> Compiler {
+        let tokens = source
+            .lines()
+            .enumerate()
+            .flat_map(|(number, line)| {
+                let code = line.split('#').next().unwrap_or_default();
+                code.split_whitespace().map(move |text| Token {
+                    text: text.to_string(),
+                    line: number + 1,
+                })
+            })
+            .collect();
+        Compiler {
+            tokens,
+            line: 1,
+            rom: vec![],
+            here: ORIGIN,
+            labels: HashMap::new(),
+            constants: HashMap::new(),
+            aliases: HashMap::new(),
+            macros: HashMap::new(),
+            references: vec![],
+            blocks: vec![],
+            next: None,
+            expansions: 0,
+        }
+    }
+
+    fn run(&mut self) -> Result<(), String> {
+        // programs start at `main`, wherever it's defined
+        self.emit_address(0x1000, Value::Forward("main".to_string()))?;
+        while let Some(token) = self.tokens.pop_front() {
+            self.line = token.line;
+            self.statement(&token.text)?;
+        }
+        if let Some(block) = self.blocks.last() {
+            return Err(match block {
+                Block::If { .. } | Block::Else { .. } => "`begin` without `end`",
+                Block::Loop { .. } => "`loop` without `again`",
+            }
+            .to_string());
+        }
+        if let Some(name) = &self.next {
+            return Err(format!("`:next {name}` isn't followed by an instruction"));
+        }
+
+        for (address, name, reference, line) in std::mem::take(&mut self.references) {
+            let Some(&target) = self.labels.get(&name) else {
+                self.line = line;
+                return Err(match name.as_str() {
+                    "main" => "the program has no `: main` label".to_string(),
+                    _ => format!("undefined name `{name}`"),
+                });
+            };
+            let offset = (address - ORIGIN) as usize;
+            match reference {
+                Reference::Nnn => {
+                    self.line = line;
+                    let target = self.check_nnn(target as i64)?;
+                    self.rom[offset] = self.rom[offset] & 0xF0 | (target >> 8) as u8;
+                    self.rom[offset + 1] = target as u8;
+                }
+                Reference::Word => {
+                    self.rom[offset..offset + 2].copy_from_slice(&target.to_be_bytes())
+                }
+                Reference::Byte { shift, or } => self.rom[offset] = (target >> shift) as u8 | or,
+            }
+        }
+        Ok(())
+    }
+
+    fn take(&mut self) -> Result<String, String> {
+        let token = self
+            .tokens
+            .pop_front()
+            .ok_or("unexpected end of the program")?;
+        self.line = token.line;
+        Ok(token.text)
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<(), String> {
+        let token = self.take()?;
+        if token != expected {
+            return Err(format!("expected `{expected}`, found `{token}`"));
+        }
+        Ok(())
+    }
+
+    fn peek_is(&self, text: &str) -> bool {
+        self.tokens.front().is_some_and(|token| token.text == text)
+    }
+
+    fn statement(&mut self, token: &str) -> Result<(), String> {
+        if let Some(x) = self.register_named(token) {
+            return self.assignment(x);
+        }
+        if self.macros.contains_key(token) {
+            return self.expand(token);
+        }
+        match token {
+            ":" => {
+                let name = self.name()?;
+                self.define(name, self.here)?;
+            }
+            ":alias" => {
+                let name = self.name()?;
+                let register = self.register()?;
+                self.aliases.insert(name, register);
+            }
+            ":const" => {
+                let name = self.name()?;
+                let value = self.known_value()?;
+                self.constants.insert(name, value as f64);
+            }
+            ":calc" => {
+                let name = self.name()?;
+                self.expect("{")?;
+                let value = self.calc()?;
+                self.constants.insert(name, value);
+            }
+            ":macro" => self.define_macro()?,
+            ":org" => {
+                let address = self.known_value()?;
+                if !(ORIGIN as i64..=0xFFFF).contains(&address) {
+                    return Err(format!("can't assemble at {address:#X}"));
+                }
+                self.here = address as u16;
+            }
+            ":byte" => {
+                let value = self.known_value()?;
+                self.emit_byte(self.check_byte(value)?)?;
+            }
+            ":next" => self.next = Some(self.name()?),
+            ":unpack" => self.unpack()?,
+            ":call" => {
+                let target = self.value()?;
+                self.emit_address(0x2000, target)?;
+            }
+            ":proto" | ":breakpoint" => {
+                self.take()?;
+            }
+            ":monitor" => {
+                self.take()?;
+                self.take()?;
+            }
+            "clear" => self.emit_op(OpCode::ClearScreen)?,
+            "return" | ";" => self.emit_op(OpCode::RetFromSubroutine)?,
+            "hires" => self.emit_word(0x00FF)?,
+            "lores" => self.emit_word(0x00FE)?,
+            "exit" => self.emit_word(0x00FD)?,
+            "scroll-left" => self.emit_word(0x00FC)?,
+            "scroll-right" => self.emit_word(0x00FB)?,
+            "scroll-down" | "scroll-up" => {
+                let rows = self.nibble()?;
+                let base = if token == "scroll-down" {
+                    0x00C0
+                } else {
+                    0x00D0
+                };
+                self.emit_word(base | rows as u16)?;
+            }
+            "audio" => self.emit_word(0xF002)?,
+            "plane" => {
+                let planes = self.nibble()?;
+                self.emit_word(0xF001 | (planes as u16) << 8)?;
+            }
+            "bcd" => {
+                let x = self.register()?;
+                self.emit_op(OpCode::StoreBCDRepresentationOfRegister(x))?;
+            }
+            "save" | "load" => {
+                let x = self.register()?;
+                if self.peek_is("-") {
+                    // XO-CHIP ranges, `5XY2` and `5XY3`
+                    self.take()?;
+                    let y = self.register()?;
+                    let base = if token == "save" { 0x5002 } else { 0x5003 };
+                    self.emit_word(base | (x as u16) << 8 | (y as u16) << 4)?;
+                } else if token == "save" {
+                    self.emit_op(OpCode::LoadFromRegistersToMemory(x))?;
+                } else {
+                    self.emit_op(OpCode::LoadFromMemoryToRegisters(x))?;
+                }
+            }
+            "saveflags" | "loadflags" => {
+                let x = self.register()?;
+                let base = if token == "saveflags" { 0xF075 } else { 0xF085 };
+                self.emit_word(base | (x as u16) << 8)?;
+            }
+            "sprite" => {
+                let x = self.register()?;
+                let y = self.register()?;
+                let rows = self.nibble()?;
+                self.emit_op(OpCode::Draw(x as usize, y as usize, rows))?;
+            }
+            "jump" | "jump0" | "native" => {
+                let target = self.value()?;
+                let base = match token {
+                    "jump" => 0x1000,
+                    "jump0" => 0xB000,
+                    _ => 0x0000,
+                };
+                self.emit_address(base, target)?;
+            }
+            "delay" | "buzzer" | "pitch" => {
+                self.expect(":=")?;
+                let x = self.register()?;
+                match token {
+                    "delay" => self.emit_op(OpCode::SetDelayTimerFromRegister(x))?,
+                    "buzzer" => self.emit_op(OpCode::SetSoundTimerFromRegister(x))?,
+                    _ => self.emit_word(0xF03A | (x as u16) << 8)?,
+                }
+            }
+            "i" => self.index()?,
+            "if" => self.conditional()?,
+            "else" => {
+                let Some(Block::If { jump }) = self.blocks.pop() else {
+                    return Err("`else` without `if ... begin`".to_string());
+                };
+                let skip = self.here;
+                self.emit_word(0x1000)?;
+                self.patch(jump, self.here)?;
+                self.blocks.push(Block::Else { jump: skip });
+            }
+            "end" => match self.blocks.pop() {
+                Some(Block::If { jump } | Block::Else { jump }) => self.patch(jump, self.here)?,
+                _ => return Err("`end` without `if ... begin`".to_string()),
+            },
+            "loop" => self.blocks.push(Block::Loop {
+                start: self.here,
+                exits: vec![],
+            }),
+            "while" => {
+                let condition = self.condition()?;
+                let Some(index) = self
+                    .blocks
+                    .iter()
+                    .rposition(|block| matches!(block, Block::Loop { .. }))
+                else {
+                    return Err("`while` outside of a loop".to_string());
+                };
+                // leave the loop unless the condition holds
+                self.emit_skip_unless(Condition {
+                    comparison: condition.comparison.negate(),
+                    ..condition
+                })?;
+                let exit = self.here;
+                self.emit_word(0x1000)?;
+                if let Block::Loop { exits, .. } = &mut self.blocks[index] {
+                    exits.push(exit);
+                }
+            }
+            "again" => {
+                let Some(Block::Loop { start, exits }) = self.blocks.pop() else {
+                    return Err("`again` without `loop`".to_string());
+                };
+                self.emit_address(0x1000, Value::Known(start as i64))?;
+                for exit in exits {
+                    self.patch(exit, self.here)?;
+                }
+            }
+            _ => match self.value_of(token)? {
+                // anything else is data, or a call to a subroutine
+                Value::Known(value) if self.is_literal(token) => {
+                    self.emit_byte(self.check_byte(value)?)?
+                }
+                target => self.emit_address(0x2000, target)?,
+            },
+        }
+        Ok(())
+    }
+
+    fn define(&mut self, name: String, address: u16) -> Result<(), String> {
+        if self.labels.insert(name.clone(), address).is_some() {
+            return Err(format!("`{name}` is defined twice"));
+        }
+        Ok(())
+    }
+
+    fn name(&mut self) -> Result<String, String> {
+        let name = self.take()?;
+        if self.is_literal(&name) || self.register_named(&name).is_some() {
+            return Err(format!("`{name}` can't be used as a name"));
+        }
+        Ok(name)
+    }
+
+    fn is_literal(&self, token: &str) -> bool {
+        parse_number(token).is_ok()
+    }
+
+    fn register_named(&self, token: &str) -> Option<u8> {
+        if let Some(&register) = self.aliases.get(token) {
+            return Some(register);
+        }
+        let digit = token.strip_prefix(['v', 'V'])?;
+        match digit.len() {
+            1 => u8::from_str_radix(digit, 16).ok(),
+            _ => None,
+        }
+    }
+
+    fn register(&mut self) -> Result<u8, String> {
+        let token = self.take()?;
+        self.register_named(&token)
+            .ok_or_else(|| format!("expected a register, found `{token}`"))
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        let token = self.take()?;
+        if token == "{" {
+            return Ok(Value::Known(self.calc()? as i64));
+        }
+        self.value_of(&token)
+    }
+
+    fn value_of(&self, token: &str) -> Result<Value, String> {
+        if let Ok(number) = parse_number(token) {
+            return Ok(Value::Known(number));
+        }
+        if let Some(&constant) = self.constants.get(token) {
+            return Ok(Value::Known(constant as i64));
+        }
+        if let Some(&address) = self.labels.get(token) {
+            return Ok(Value::Known(address as i64));
+        }
+        if token.starts_with(':') || token.parse::<f64>().is_ok() {
+            return Err(format!("unexpected `{token}`"));
+        }
+        Ok(Value::Forward(token.to_string()))
+    }
+
+    fn known_value(&mut self) -> Result<i64, String> {
+        match self.value()? {
+            Value::Known(value) => Ok(value),
+            Value::Forward(name) => Err(format!("undefined name `{name}`")),
+        }
+    }
+
+    fn check_byte(&self, value: i64) -> Result<u8, String> {
+        if !(-128..=255).contains(&value) {
+            return Err(format!("{value} doesn't fit in a byte"));
+        }
+        Ok(value as u8)
+    }
+
+    fn check_nnn(&self, value: i64) -> Result<u16, String> {
+        if !(0..=0xFFF).contains(&value) {
+            return Err(format!("address {value:#X} doesn't fit in 12 bits"));
+        }
+        Ok(value as u16)
+    }
+
+    fn nibble(&mut self) -> Result<u8, String> {
+        let value = self.known_value()?;
+        if !(0..=0xF).contains(&value) {
+            return Err(format!("{value} doesn't fit in a nibble"));
+        }
+        Ok(value as u8)
+    }
+
+    fn byte(&mut self) -> Result<u8, String> {
+        let value = self.known_value()?;
+        self.check_byte(value)
+    }
+
+    fn emit_byte(&mut self, byte: u8) -> Result<(), String> {
+        let offset = (self.here - ORIGIN) as usize;
+        if offset >= self.rom.len() {
+            self.rom.resize(offset + 1, 0);
+        }
+        self.rom[offset] = byte;
+        self.here = self
+            .here
+            .checked_add(1)
+            .ok_or("the program doesn't fit in 64k")?;
+        Ok(())
+    }
+
+    fn emit_word(&mut self, word: u16) -> Result<(), String> {
+        if let Some(name) = self.next.take() {
+            self.define(name, self.here + 1)?;
+        }
+        let [high, low] = word.to_be_bytes();
+        self.emit_byte(high)?;
+        self.emit_byte(low)
+    }
+
+    fn emit_op(&mut self, op_code: OpCode) -> Result<(), String> {
+        self.emit_word(op_code.encode())
+    }
+
+    /// Emits `base | NNN`, patched later if the address isn't known yet.
+    fn emit_address(&mut self, base: u16, target: Value) -> Result<(), String> {
+        match target {
+            Value::Known(address) => {
+                let address = self.check_nnn(address)?;
+                self.emit_word(base | address)
+            }
+            Value::Forward(name) => {
+                self.references
+                    .push((self.here, name, Reference::Nnn, self.line));
+                self.emit_word(base)
+            }
+        }
+    }
+
+    /// Points the jump at `address` to `target`.
+    fn patch(&mut self, address: u16, target: u16) -> Result<(), String> {
+        let target = self.check_nnn(target as i64)?;
+        let offset = (address - ORIGIN) as usize;
+        self.rom[offset] = self.rom[offset] & 0xF0 | (target >> 8) as u8;
+        self.rom[offset + 1] = target as u8;
+        Ok(())
+    }
+
+    fn index(&mut self) -> Result<(), String> {
+        match self.take()?.as_str() {
+            ":=" => match self.take()?.as_str() {
+                "long" => {
+                    let target = self.value()?;
+                    self.emit_op(OpCode::LoadIndexLong)?;
+                    match target {
+                        Value::Known(address) if (0..=0xFFFF).contains(&address) => {
+                            self.emit_word(address as u16)
+                        }
+                        Value::Known(address) => {
+                            Err(format!("{address:#X} doesn't fit in 16 bits"))
+                        }
+                        Value::Forward(name) => {
+                            self.references
+                                .push((self.here, name, Reference::Word, self.line));
+                            self.emit_word(0)
+                        }
+                    }
+                }
+                "hex" => {
+                    let x = self.register()?;
+                    self.emit_op(OpCode::LoadSpriteRepresentationInMemory(x))
+                }
+                "bighex" => {
+                    let x = self.register()?;
+                    self.emit_word(0xF030 | (x as u16) << 8)
+                }
+                token => {
+                    let target = match token {
+                        "{" => Value::Known(self.calc()? as i64),
+                        _ => self.value_of(token)?,
+                    };
+                    self.emit_address(0xA000, target)
+                }
+            },
+            "+=" => {
+                let x = self.register()?;
+                self.emit_op(OpCode::AddRegisterValueToIndex(x))
+            }
+            token => Err(format!("unexpected `{token}` after `i`")),
+        }
+    }
+
+    fn operand(&mut self) -> Result<Operand, String> {
+        let token = self.take()?;
+        if let Some(register) = self.register_named(&token) {
+            return Ok(Operand::Register(register));
+        }
+        let value = match token.as_str() {
+            "{" => self.calc()? as i64,
+            _ => match self.value_of(&token)? {
+                Value::Known(value) => value,
+                Value::Forward(name) => return Err(format!("undefined name `{name}`")),
+            },
+        };
+        Ok(Operand::Byte(self.check_byte(value)?))
+    }
+
+    fn assignment(&mut self, x: u8) -> Result<(), String> {
+        let operator = self.take()?;
+        let register_op = |op: fn(u8, u8) -> OpCode, operand: Operand| match operand {
+            Operand::Register(y) => Ok(op(x, y)),
+            Operand::Byte(_) => Err(format!("`{operator}` needs a register")),
+        };
+        let op_code = match operator.as_str() {
+            ":=" => match self.tokens.front().map(|token| token.text.as_str()) {
+                Some("random") => {
+                    self.take()?;
+                    OpCode::SetRegisterWithRandom(x as usize, self.byte()?)
+                }
+                Some("key") => {
+                    self.take()?;
+                    OpCode::GetKey(x as usize)
+                }
+                Some("delay") => {
+                    self.take()?;
+                    OpCode::SetRegisterFromDelayTimer(x)
+                }
+                _ => match self.operand()? {
+                    Operand::Register(y) => OpCode::SetRegisterToRegisterValue(x, y),
+                    Operand::Byte(value) => OpCode::SetRegister {
+                        register: x as usize,
+                        value,
+                    },
+                },
+            },
+            "+=" => match self.operand()? {
+                Operand::Register(y) => OpCode::AddRegisterToRegister(x, y),
+                Operand::Byte(value) => OpCode::AddRegister {
+                    register: x as usize,
+                    value,
+                },
+            },
+            "-=" => match self.operand()? {
+                Operand::Register(y) => OpCode::SubRegisterToRegister(x, y),
+                Operand::Byte(value) => OpCode::AddRegister {
+                    register: x as usize,
+                    value: value.wrapping_neg(),
+                },
+            },
+            "=-" => register_op(OpCode::SubRegisterToRegisterReverse, self.operand()?)?,
+            "|=" => register_op(OpCode::SetRegisterToRegisterValueUsingOR, self.operand()?)?,
+            "&=" => register_op(OpCode::SetRegisterToRegisterValueUsingAND, self.operand()?)?,
+            "^=" => register_op(OpCode::SetRegisterToRegisterValueUsingXOR, self.operand()?)?,
+            ">>=" => register_op(OpCode::ShiftRightRegisterFromRegister, self.operand()?)?,
+            "<<=" => register_op(OpCode::ShiftLeftRegisterFromRegister, self.operand()?)?,
+            _ => return Err(format!("unexpected `{operator}` after a register")),
+        };
+        self.emit_op(op_code)
+    }
+
+    fn condition(&mut self) -> Result<Condition, String> {
+        let register = self.register()?;
+        let comparison = match self.take()?.as_str() {
+            "==" => Comparison::Equal,
+            "!=" => Comparison::NotEqual,
+            "<" => Comparison::Less,
+            ">" => Comparison::Greater,
+            "<=" => Comparison::LessOrEqual,
+            ">=" => Comparison::GreaterOrEqual,
+            "key" => Comparison::Key,
+            "-key" => Comparison::NotKey,
+            token => return Err(format!("expected a comparison, found `{token}`")),
+        };
+        let operand = match comparison {
+            Comparison::Key | Comparison::NotKey => None,
+            _ => Some(self.operand()?),
+        };
+        Ok(Condition {
+            register,
+            comparison,
+            operand,
+        })
+    }
+
+    fn conditional(&mut self) -> Result<(), String> {
+        let condition = self.condition()?;
+        match self.take()?.as_str() {
+            "then" => self.emit_skip_unless(condition),
+            "begin" => {
+                self.emit_skip_unless(Condition {
+                    comparison: condition.comparison.negate(),
+                    ..condition
+                })?;
+                let jump = self.here;
+                self.emit_word(0x1000)?;
+                self.blocks.push(Block::If { jump });
+                Ok(())
+            }
+            token => Err(format!("expected `then` or `begin`, found `{token}`")),
+        }
+    }
+
+    /// Emits the instructions skipping the next one when `condition` is false.
+    fn emit_skip_unless(&mut self, condition: Condition) -> Result<(), String> {
+        let x = condition.register;
+        let operand = condition.operand.unwrap_or(Operand::Byte(0));
+        let op_code = match (condition.comparison, operand) {
+            (Comparison::Equal, Operand::Byte(nn)) => OpCode::SkipIfRegisterNotEquals(x, nn),
+            (Comparison::Equal, Operand::Register(y)) => OpCode::SkipIfBothRegistersNotEqual(x, y),
+            (Comparison::NotEqual, Operand::Byte(nn)) => OpCode::SkipIfRegisterEquals(x, nn),
+            (Comparison::NotEqual, Operand::Register(y)) => OpCode::SkipIfBothRegistersEqual(x, y),
+            (Comparison::Key, _) => OpCode::SkipIfNotKey(x as usize),
+            (Comparison::NotKey, _) => OpCode::SkipIfKey(x as usize),
+            (comparison, operand) => {
+                // VF := operand - VX sets VF to operand >= VX, VF := VX - operand to VX >= operand
+                match operand {
+                    Operand::Byte(value) => self.emit_op(OpCode::SetRegister {
+                        register: 0xF,
+                        value,
+                    })?,
+                    Operand::Register(y) => {
+                        self.emit_op(OpCode::SetRegisterToRegisterValue(0xF, y))?
+                    }
+                }
+                let (subtraction, holds) = match comparison {
+                    Comparison::LessOrEqual => (OpCode::SubRegisterToRegister(0xF, x), 1),
+                    Comparison::Greater => (OpCode::SubRegisterToRegister(0xF, x), 0),
+                    Comparison::GreaterOrEqual => (OpCode::SubRegisterToRegisterReverse(0xF, x), 1),
+                    _ => (OpCode::SubRegisterToRegisterReverse(0xF, x), 0),
+                };
+                self.emit_op(subtraction)?;
+                OpCode::SkipIfRegisterNotEquals(0xF, holds)
+            }
+        };
+        self.emit_op(op_code)
+    }
+
+    fn unpack(&mut self) -> Result<(), String> {
+        let or = match self.take()?.as_str() {
+            "long" => None,
+            token => match self.value_of(token)? {
+                Value::Known(nibble @ 0..=0xF) => Some((nibble as u8) << 4),
+                _ => return Err(format!("expected a nibble or `long`, found `{token}`")),
+            },
+        };
+        let target = self.value()?;
+        // v0 gets the high byte and v1 the low one
+        for (register, shift, or) in [(0, 8, or.unwrap_or(0)), (1, 0, 0)] {
+            let value = match &target {
+                Value::Known(address) => (*address >> shift) as u8 | or,
+                Value::Forward(name) => {
+                    let reference = Reference::Byte { shift, or };
+                    self.references
+                        .push((self.here + 1, name.clone(), reference, self.line));
+                    0
+                }
+            };
+            self.emit_op(OpCode::SetRegister { register, value })?;
+        }
+        Ok(())
+    }
+
+    fn define_macro(&mut self) -> Result<(), String> {
+        let name = self.name()?;
+        let mut parameters = vec![];
+        loop {
+            match self.take()?.as_str() {
+                "{" => break,
+                parameter => parameters.push(parameter.to_string()),
+            }
+        }
+        let mut body = vec![];
+        let mut depth = 1;
+        loop {
+            let token = self.tokens.pop_front().ok_or("unterminated `:macro`")?;
+            match token.text.as_str() {
+                "{" => depth += 1,
+                "}" if depth == 1 => break,
+                "}" => depth -= 1,
+                _ => {}
+            }
+            body.push(token);
+        }
+        self.macros.insert(name, Macro { parameters, body });
+        Ok(())
+    }
+
+    fn expand(&mut self, name: &str) -> Result<(), String> {
+        self.expansions += 1;
+        if self.expansions > MAX_EXPANSIONS {
+            return Err(format!("too many macro expansions, is `{name}` recursive?"));
+        }
+        let count = self.macros[name].parameters.len();
+        let arguments = (0..count)
+            .map(|_| self.take())
+            .collect::<Result<Vec<String>, String>>()?;
+        let line = self.line;
+        let definition = &self.macros[name];
+        let expanded: Vec<Token> = definition
+            .body
+            .iter()
+            .map(|token| {
+                let text = match definition.parameters.iter().position(|p| *p == token.text) {
+                    Some(index) => arguments[index].clone(),
+                    None => token.text.clone(),
+                };
+                Token { text, line }
+            })
+            .collect();
+        for token in expanded.into_iter().rev() {
+            self.tokens.push_front(token);
+        }
+        Ok(())
+    }
+
+    /// Evaluates a `:calc` expression up to its closing brace. Like Octo, binary
+    /// operators have no precedence and are evaluated from right to left.
+    fn calc(&mut self) -> Result<f64, String> {
+        let value = self.calc_expression()?;
+        self.expect("}")?;
+        Ok(value)
+    }
+
+    fn calc_expression(&mut self) -> Result<f64, String> {
+        let left = self.calc_term()?;
+        let Some(operator) = self.tokens.front().map(|token| token.text.clone()) else {
+            return Ok(left);
+        };
+        if operator == "<<" || operator == ">>" {
+            self.take()?;
+            let right = self.calc_expression()?;
+            let amount = u32::try_from(right as i64).ok();
+            let shifted = match operator.as_str() {
+                "<<" => amount.and_then(|amount| (left as i64).checked_shl(amount)),
+                _ => amount.and_then(|amount| (left as i64).checked_shr(amount)),
+            };
+            return shifted
+                .map(|value| value as f64)
+                .ok_or_else(|| "shift out of range".to_string());
+        }
+        let apply: fn(f64, f64) -> f64 = match operator.as_str() {
+            "+" => |a, b| a + b,
+            "-" => |a, b| a - b,
+            "*" => |a, b| a * b,
+            "/" => |a, b| a / b,
+            "%" => |a, b| a % b,
+            "&" => |a, b| (a as i64 & b as i64) as f64,
+            "|" => |a, b| (a as i64 | b as i64) as f64,
+            "^" => |a, b| (a as i64 ^ b as i64) as f64,
+            "pow" => f64::powf,
+            "min" => f64::min,
+            "max" => f64::max,
+            "<" => |a, b| (a < b) as i64 as f64,
+            ">" => |a, b| (a > b) as i64 as f64,
+            "<=" => |a, b| (a <= b) as i64 as f64,
+            ">=" => |a, b| (a >= b) as i64 as f64,
+            "==" => |a, b| (a == b) as i64 as f64,
+            "!=" => |a, b| (a != b) as i64 as f64,
+            _ => return Ok(left),
+        };
+        self.take()?;
+        let right = self.calc_expression()?;
+        Ok(apply(left, right))
+    }
+
+    fn calc_term(&mut self) -> Result<f64, String> {
+        let token = self.take()?;
+        let unary: fn(f64) -> f64 = match token.as_str() {
+            "(" => {
+                let value = self.calc_expression()?;
+                self.expect(")")?;
+                return Ok(value);
+            }
+            "-" => |a| -a,
+            "~" => |a| !(a as i64) as f64,
+            "!" => |a| (a == 0.0) as i64 as f64,
+            "abs" => f64::abs,
+            "sqrt" => f64::sqrt,
+            "sin" => f64::sin,
+            "cos" => f64::cos,
+            "tan" => f64::tan,
+            "exp" => f64::exp,
+            "log" => f64::ln,
+            "sign" => f64::signum,
+            "ceil" => f64::ceil,
+            "floor" => f64::floor,
+            "@" => {
+                let address = self.calc_term()? as i64;
+                let byte = (address - ORIGIN as i64)
+                    .try_into()
+                    .ok()
+                    .and_then(|offset: usize| self.rom.get(offset));
+                return Ok(byte.copied().unwrap_or(0) as f64);
+            }
+            "HERE" => return Ok(self.here as f64),
+            "PI" => return Ok(std::f64::consts::PI),
+            "E" => return Ok(std::f64::consts::E),
+            name => {
+                if let Some(&constant) = self.constants.get(name) {
+                    return Ok(constant);
+                }
+                if let Ok(number) = name.parse::<f64>() {
+                    return Ok(number);
+                }
+                return match self.value_of(name)? {
+                    Value::Known(value) => Ok(value as f64),
+                    Value::Forward(name) => Err(format!("undefined name `{name}`")),
+                };
+            }
+        };
+        Ok(unary(self.calc_term()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cpu::new;
+    use crate::octo::compile;
+
+    #[test]
+    fn compiles_control_flow_macros_and_forward_labels() {
+        let rom = compile(
+            "
+            :alias counter v2
+            :const LIMIT 3
+            :calc DOUBLE { LIMIT * 2 }
+            :macro bump register { register += 1 }
+            : main
+              counter := 0
+              v3 := 0
+              loop
+                bump counter
+                if counter > DOUBLE then v3 += 10
+                while counter != 8
+              again
+              if v3 == 20 begin v4 := 1 else v4 := 2 end
+              i := data
+              load v1
+              count-down
+            : halt jump halt
+            : count-down
+              loop
+                v5 += -1
+                if v5 == 0 then return
+              again
+            : data 0xAB 0b11001101
+            ",
+        );
+        let rom = rom.unwrap_or_else(|e| panic!("{e}"));
+        assert_eq!(&rom[..4], &[0x12, 0x02, 0x62, 0x00]);
+
+        let mut cpu = new();
+        cpu.load_rom_bytes(&rom).unwrap();
+        cpu.registers_mut()[5] = 4;
+        for _ in 0..200 {
+            cpu.tick().unwrap();
+        }
+        let v = cpu.registers();
+        assert_eq!((v[2], v[3], v[4], v[5]), (8, 20, 1, 0));
+        assert_eq!((v[0], v[1]), (0xAB, 0b11001101));
+    }
+
+    #[test]
+    fn reports_errors_with_their_line() {
+        assert_eq!(
+            compile(": main\nclear\nv0 := 300"),
+            Err("line 3: 300 doesn't fit in a byte".to_string())
+        );
+        assert_eq!(
+            compile(": main\nloop\nclear"),
+            Err("line 3: `loop` without `again`".to_string())
+        );
+        assert_eq!(
+            compile(": main jump nowhere"),
+            Err("line 1: undefined name `nowhere`".to_string())
+        );
+        assert_eq!(
+            compile(": main\n:calc X { 1 << 3 }\nv0 := X"),
+            Ok(vec![0x12, 0x02, 0x60, 0x08])
+        );
+        assert_eq!(
+            compile(": main\n:calc X { 1 << 64 }"),
+            Err("line 2: shift out of range".to_string())
+        );
+        assert_eq!(
+            compile(": main\n:calc X { 1 >> -1 }"),
+            Err("line 2: shift out of range".to_string())
+        );
+    }
+}
@@ -1,6 +1,9 @@
+use crate::mnemonic::Syntax;
 use std::fmt::{Display, Formatter};
+use strum_macros::IntoStaticStr;
 
-#[derive(Copy, Clone, Debug, PartialEq)]
+/// Variant names are available as `&'static str` through `Into`, e.g. for statistics.
+#[derive(Copy, Clone, Debug, PartialEq, IntoStaticStr)]
 pub enum OpCode {
     ClearScreen,                                // CLS
     RetFromSubroutine,                          // RET
@@ -31,106 +34,127 @@ pub enum OpCode {
     SetSoundTimerFromRegister(u8),              // LD ST, Vx
     SetDelayTimerFromRegister(u8),              // LD DT, Vx
     SkipIfBothRegistersNotEqual(u8, u8),        // SNE Vx, Vy
-    Unknown,
+    Unknown(u16),                               // a word no instruction decodes from
     SetRegisterWithRandom(usize, u8),
     LoadSpriteRepresentationInMemory(u8),
     GetKey(usize),
     SkipIfKey(usize),
     SkipIfNotKey(usize),
+    LoadIndexLong, // LD I, long NNNN, XO-CHIP's F000 followed by the address
+}
+
+impl OpCode {
+    /// The word `decode_instruction` decodes into this instruction. Operands are
+    /// truncated to the bits the encoding has room for.
+    pub fn encode(self) -> u16 {
+        let xy = |x: u8, y: u8| (x as u16 & 0xF) << 8 | (y as u16 & 0xF) << 4;
+        let xnn = |x: usize, nn: u8| (x as u16 & 0xF) << 8 | nn as u16;
+        let x = |x: usize| (x as u16 & 0xF) << 8;
+        match self {
+            OpCode::ClearScreen => 0x00E0,
+            OpCode::RetFromSubroutine => 0x00EE,
+            OpCode::Jump(nnn) => 0x1000 | nnn & 0xFFF,
+            OpCode::CallSubroutine(nnn) => 0x2000 | nnn & 0xFFF,
+            OpCode::SkipIfRegisterEquals(vx, nn) => 0x3000 | xnn(vx as usize, nn),
+            OpCode::SkipIfRegisterNotEquals(vx, nn) => 0x4000 | xnn(vx as usize, nn),
+            OpCode::SkipIfBothRegistersEqual(vx, vy) => 0x5000 | xy(vx, vy),
+            OpCode::SetRegister { register, value } => 0x6000 | xnn(register, value),
+            OpCode::AddRegister { register, value } => 0x7000 | xnn(register, value),
+            OpCode::SetRegisterToRegisterValue(vx, vy) => 0x8000 | xy(vx, vy),
+            OpCode::SetRegisterToRegisterValueUsingOR(vx, vy) => 0x8001 | xy(vx, vy),
+            OpCode::SetRegisterToRegisterValueUsingAND(vx, vy) => 0x8002 | xy(vx, vy),
+            OpCode::SetRegisterToRegisterValueUsingXOR(vx, vy) => 0x8003 | xy(vx, vy),
+            OpCode::AddRegisterToRegister(vx, vy) => 0x8004 | xy(vx, vy),
+            OpCode::SubRegisterToRegister(vx, vy) => 0x8005 | xy(vx, vy),
+            OpCode::ShiftRightRegisterFromRegister(vx, vy) => 0x8006 | xy(vx, vy),
+            OpCode::SubRegisterToRegisterReverse(vx, vy) => 0x8007 | xy(vx, vy),
+            OpCode::ShiftLeftRegisterFromRegister(vx, vy) => 0x800E | xy(vx, vy),
+            OpCode::SkipIfBothRegistersNotEqual(vx, vy) => 0x9000 | xy(vx, vy),
+            OpCode::SetIndex(nnn) => 0xA000 | nnn & 0xFFF,
+            OpCode::JumpWithV0Offset(nnn) => 0xB000 | nnn & 0xFFF,
+            OpCode::SetRegisterWithRandom(vx, nn) => 0xC000 | xnn(vx, nn),
+            OpCode::Draw(vx, vy, n) => 0xD000 | xy(vx as u8, vy as u8) | n as u16 & 0xF,
+            OpCode::SkipIfKey(vx) => 0xE09E | x(vx),
+            OpCode::SkipIfNotKey(vx) => 0xE0A1 | x(vx),
+            OpCode::LoadIndexLong => 0xF000,
+            OpCode::SetRegisterFromDelayTimer(vx) => 0xF007 | x(vx as usize),
+            OpCode::GetKey(vx) => 0xF00A | x(vx),
+            OpCode::SetDelayTimerFromRegister(vx) => 0xF015 | x(vx as usize),
+            OpCode::SetSoundTimerFromRegister(vx) => 0xF018 | x(vx as usize),
+            OpCode::AddRegisterValueToIndex(vx) => 0xF01E | x(vx as usize),
+            OpCode::LoadSpriteRepresentationInMemory(vx) => 0xF029 | x(vx as usize),
+            OpCode::StoreBCDRepresentationOfRegister(vx) => 0xF033 | x(vx as usize),
+            OpCode::LoadFromRegistersToMemory(vx) => 0xF055 | x(vx as usize),
+            OpCode::LoadFromMemoryToRegisters(vx) => 0xF065 | x(vx as usize),
+            OpCode::Unknown(word) => word,
+        }
+    }
+
+    /// The words making up the instruction in memory, `address` being only
+    /// used by `LoadIndexLong`.
+    pub fn encode_with(self, address: u16) -> Vec<u16> {
+        match self {
+            OpCode::LoadIndexLong => vec![self.encode(), address],
+            _ => vec![self.encode()],
+        }
+    }
+
+    /// Size in memory, in bytes.
+    pub fn size(self) -> u16 {
+        match self {
+            OpCode::LoadIndexLong => 4,
+            _ => 2,
+        }
+    }
 }
 
 impl Display for OpCode {
+    /// `{}` gives the variant and its operands, `{:#}` the Cowgod mnemonic.
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        match self {
-            OpCode::ClearScreen => Display::fmt("ClearScreen", f),
-            OpCode::Jump(jump) => Display::fmt(&format!("Jump({jump})"), f),
-            OpCode::JumpWithV0Offset(jump) => Display::fmt(&format!("JumpWithV0Offset({jump})"), f),
-            OpCode::Unknown => Display::fmt("Unknown", f),
-            OpCode::SetRegister { register, value } => Display::fmt(
-                &format!("SetRegister(register={register}, value={value})"),
-                f,
-            ),
-            OpCode::SetRegisterWithRandom(register, value) => Display::fmt(
-                &format!("SetRegisterWithRandom(register={register}, value={value})"),
-                f,
-            ),
-            OpCode::AddRegister { register, value } => Display::fmt(
-                &format!("AddRegister(register={register}, value={value})"),
-                f,
-            ),
-            OpCode::SetIndex(index) => Display::fmt(&format!("SetIndex({index})",), f),
-            OpCode::Draw(x, y, n) => Display::fmt(&format!("Display(x={x}, y={y}, n={n})"), f),
-            OpCode::SkipIfRegisterEquals(x, nn) => {
-                Display::fmt(&format!("SkipIfEqual(x={x}, nn={nn})"), f)
-            }
-            OpCode::SkipIfRegisterNotEquals(x, nn) => {
-                Display::fmt(&format!("SkipIfNotEqual(x={x}, nn={nn})"), f)
-            }
-            OpCode::SkipIfBothRegistersEqual(x, y) => {
-                Display::fmt(&format!("SkipIfBothRegistersEquals(x={x}, y={y})"), f)
-            }
-            OpCode::SetRegisterToRegisterValue(x, y) => {
-                Display::fmt(&format!("SetRegisterToRegisterValue(x={x}, y={y})"), f)
-            }
-            OpCode::CallSubroutine(nnn) => Display::fmt(&format!("CallSubroutine(nnn={nnn})"), f),
-            OpCode::RetFromSubroutine => Display::fmt(&"RetFromSubroutine".to_string(), f),
-            OpCode::SetRegisterToRegisterValueUsingOR(x, y) => Display::fmt(
-                &format!("SetRegisterToRegisterValueUsingOR(x={x}, y={y})"),
-                f,
-            ),
-            OpCode::SetRegisterToRegisterValueUsingAND(x, y) => Display::fmt(
-                &format!("SetRegisterToRegisterValueUsingAND(x={x}, y={y})"),
-                f,
-            ),
-            OpCode::SetRegisterToRegisterValueUsingXOR(x, y) => Display::fmt(
-                &format!("SetRegisterToRegisterValueUsingXOR(x={x}, y={y})"),
-                f,
-            ),
-            OpCode::AddRegisterToRegister(x, y) => {
-                Display::fmt(&format!("AddRegisterToRegister(x={x}, y={y})"), f)
-            }
-            OpCode::SubRegisterToRegister(x, y) => {
-                Display::fmt(&format!("SubRegisterToRegister(x={x}, y={y})"), f)
-            }
-            OpCode::ShiftRightRegisterFromRegister(x, y) => {
-                Display::fmt(&format!("ShiftRightRegisterFromRegister(x={x}, y={y})"), f)
-            }
-            OpCode::ShiftLeftRegisterFromRegister(x, y) => {
-                Display::fmt(&format!("ShiftLeftRegisterFromRegister(x={x}, y={y})"), f)
-            }
-            OpCode::SubRegisterToRegisterReverse(x, y) => {
-                Display::fmt(&format!("SubRegisterToRegisterReverse(x={x}, y={y})"), f)
-            }
-            OpCode::LoadFromMemoryToRegisters(x) => {
-                Display::fmt(&format!("LoadFromMemoryToRegisters(x={x})"), f)
-            }
-            OpCode::LoadFromRegistersToMemory(x) => {
-                Display::fmt(&format!("LoadFromRegistersToMemory(x={x})"), f)
-            }
-            OpCode::StoreBCDRepresentationOfRegister(x) => {
-                Display::fmt(&format!("StoreBCDRepresentationOfRegister(x={x})"), f)
-            }
-            OpCode::AddRegisterValueToIndex(x) => {
-                Display::fmt(&format!("AddRegisterValueToIndex(x={x})"), f)
-            }
-            OpCode::SetRegisterFromDelayTimer(x) => {
-                Display::fmt(&format!("SetRegisterFromDelayTimer(x={x})"), f)
-            }
-            OpCode::SetDelayTimerFromRegister(x) => {
-                Display::fmt(&format!("SetDelayTimerFromRegister(x={x})"), f)
-            }
-            OpCode::SetSoundTimerFromRegister(x) => {
-                Display::fmt(&format!("SetSoundTimerFromRegister(x={x})"), f)
-            }
-            OpCode::SkipIfBothRegistersNotEqual(x, y) => {
-                Display::fmt(&format!("SkipIfBothRegistersNotEqual(x={x}, y={y})"), f)
-            }
-            OpCode::LoadSpriteRepresentationInMemory(x) => {
-                Display::fmt(&format!("LoadSpriteRepresentationInMemory(x={x})"), f)
-            }
-            OpCode::GetKey(x) => Display::fmt(&format!("GetKey(x={x})"), f),
-            OpCode::SkipIfKey(x) => Display::fmt(&format!("SkipIfKey(x={x})"), f),
-            OpCode::SkipIfNotKey(x) => Display::fmt(&format!("SkipIfNotKey(x={x})"), f),
+        if f.alternate() {
+            return f.pad(&self.mnemonic(Syntax::Cowgod).to_string());
         }
+        let name: &'static str = (*self).into();
+        let text = match *self {
+            OpCode::ClearScreen | OpCode::RetFromSubroutine | OpCode::LoadIndexLong => {
+                name.to_string()
+            }
+            OpCode::Jump(nnn)
+            | OpCode::JumpWithV0Offset(nnn)
+            | OpCode::CallSubroutine(nnn)
+            | OpCode::SetIndex(nnn) => format!("{name}(nnn={nnn:#05X})"),
+            OpCode::SetRegister { register, value } | OpCode::AddRegister { register, value } => {
+                format!("{name}(register={register}, value={value:#04X})")
+            }
+            OpCode::SetRegisterWithRandom(x, nn) => format!("{name}(x={x}, nn={nn:#04X})"),
+            OpCode::SkipIfRegisterEquals(x, nn) | OpCode::SkipIfRegisterNotEquals(x, nn) => {
+                format!("{name}(x={x}, nn={nn:#04X})")
+            }
+            OpCode::Draw(x, y, n) => format!("{name}(x={x}, y={y}, n={n})"),
+            OpCode::SkipIfBothRegistersEqual(x, y)
+            | OpCode::SkipIfBothRegistersNotEqual(x, y)
+            | OpCode::SetRegisterToRegisterValue(x, y)
+            | OpCode::SetRegisterToRegisterValueUsingOR(x, y)
+            | OpCode::SetRegisterToRegisterValueUsingAND(x, y)
+            | OpCode::SetRegisterToRegisterValueUsingXOR(x, y)
+            | OpCode::AddRegisterToRegister(x, y)
+            | OpCode::SubRegisterToRegister(x, y)
+            | OpCode::ShiftRightRegisterFromRegister(x, y)
+            | OpCode::ShiftLeftRegisterFromRegister(x, y)
+            | OpCode::SubRegisterToRegisterReverse(x, y) => format!("{name}(x={x}, y={y})"),
+            OpCode::LoadFromMemoryToRegisters(x)
+            | OpCode::LoadFromRegistersToMemory(x)
+            | OpCode::StoreBCDRepresentationOfRegister(x)
+            | OpCode::AddRegisterValueToIndex(x)
+            | OpCode::SetRegisterFromDelayTimer(x)
+            | OpCode::SetSoundTimerFromRegister(x)
+            | OpCode::SetDelayTimerFromRegister(x)
+            | OpCode::LoadSpriteRepresentationInMemory(x) => format!("{name}(x={x})"),
+            OpCode::GetKey(x) | OpCode::SkipIfKey(x) | OpCode::SkipIfNotKey(x) => {
+                format!("{name}(x={x})")
+            }
+            OpCode::Unknown(word) => format!("{name}({word:#06X})"),
+        };
+        f.pad(&text)
     }
 }
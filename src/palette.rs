@@ -0,0 +1,37 @@
+/// An RGB triplet, kept independent from SDL so that software paths
+/// (filters, exports) can use it without a window.
+pub type Rgb = (u8, u8, u8);
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Palette {
+    pub background: Rgb,
+    pub foreground: Rgb,
+}
+
+impl Palette {
+    pub fn color(&self, lit: bool) -> Rgb {
+        if lit {
+            self.foreground
+        } else {
+            self.background
+        }
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette {
+            background: (134, 84, 3),
+            foreground: (253, 195, 10),
+        }
+    }
+}
+
+/// `#RRGGBB`, as used by Octo and the CHIP-8 database.
+pub fn parse_color(color: &str) -> Option<Rgb> {
+    let hex = color.strip_prefix('#')?;
+    let value = u32::from_str_radix(hex, 16)
+        .ok()
+        .filter(|_| hex.len() == 6)?;
+    Some(((value >> 16) as u8, (value >> 8) as u8, value as u8))
+}
@@ -0,0 +1,86 @@
+use clap::ValueEnum;
+
+/// Where most interpreters load programs and start running them.
+pub const PROGRAM_START: u16 = 0x200;
+
+/// The machine a ROM was written for, which decides hardware limits.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum Platform {
+    /// Modern CHIP-8 interpreters
+    #[default]
+    #[value(name = "chip8")]
+    Chip8,
+    /// The original RCA COSMAC VIP interpreter
+    #[value(name = "vip")]
+    CosmacVip,
+    /// SUPER-CHIP 1.1 on the HP48 calculators
+    #[value(name = "schip")]
+    SuperChip,
+    /// Octo's XO-CHIP extension
+    #[value(name = "xochip")]
+    XoChip,
+    /// The ETI-660 learning computer, whose port of the VIP interpreter runs programs from 0x600
+    #[value(name = "eti660")]
+    Eti660,
+}
+
+impl Platform {
+    /// Number of nested subroutine calls the call stack can hold.
+    pub fn stack_depth(self) -> usize {
+        match self {
+            Platform::CosmacVip | Platform::Eti660 => 12,
+            Platform::Chip8 | Platform::SuperChip | Platform::XoChip => 16,
+        }
+    }
+
+    /// Where programs are loaded, and where they start unless told otherwise.
+    pub fn load_address(self) -> u16 {
+        match self {
+            Platform::Eti660 => 0x600,
+            Platform::Chip8 | Platform::CosmacVip | Platform::SuperChip | Platform::XoChip => {
+                PROGRAM_START
+            }
+        }
+    }
+
+    /// How the platform's interpreter implements the ambiguous instructions.
+    pub fn quirks(self) -> Quirks {
+        match self {
+            Platform::Chip8 => Quirks::default(),
+            Platform::CosmacVip | Platform::Eti660 => Quirks {
+                shift_uses_vy: true,
+                load_store_increments_index: true,
+                logic_resets_vf: true,
+                display_wait: true,
+                ..Quirks::default()
+            },
+            Platform::SuperChip => Quirks {
+                jump_uses_vx: true,
+                ..Quirks::default()
+            },
+            Platform::XoChip => Quirks {
+                shift_uses_vy: true,
+                load_store_increments_index: true,
+                sprite_wrapping: true,
+                ..Quirks::default()
+            },
+        }
+    }
+}
+
+/// Behaviours that differ between CHIP-8 interpreters, all off matches modern ones.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Quirks {
+    /// `8XY6` and `8XYE` shift VY into VX instead of shifting VX in place.
+    pub shift_uses_vy: bool,
+    /// `FX55` and `FX65` leave I pointing past the last register they accessed.
+    pub load_store_increments_index: bool,
+    /// `BNNN` adds VX, X being the first digit of NNN, instead of V0.
+    pub jump_uses_vx: bool,
+    /// `8XY1`, `8XY2` and `8XY3` reset VF.
+    pub logic_resets_vf: bool,
+    /// Sprites wrap around the screen edges instead of being clipped.
+    pub sprite_wrapping: bool,
+    /// `DXYN` waits for the next 60 Hz frame, as the VIP draws during vertical blank.
+    pub display_wait: bool,
+}
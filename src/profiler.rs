@@ -0,0 +1,239 @@
+use crate::decoder::decode_instruction;
+use crate::frontend::Overlay;
+use crate::memory::{Access, AccessKind};
+use crate::mnemonic::Syntax;
+use crate::opcode::OpCode;
+use std::collections::HashMap;
+use std::fmt::Write;
+
+/// Number of rows listed in each section of the report.
+const REPORT_ROWS: usize = 20;
+
+/// How often each byte of memory was fetched as code, read and written.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Heat {
+    pub executed: u64,
+    pub read: u64,
+    pub written: u64,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Subroutine {
+    pub calls: u64,
+    /// Instructions run between the calls and their returns, nested calls included.
+    pub cycles: u64,
+}
+
+/// Counts where the time goes while a ROM runs.
+pub struct Profiler {
+    cycles: u64,
+    /// Executions of the instruction starting at each address.
+    executions: Vec<u64>,
+    opcodes: HashMap<&'static str, u64>,
+    subroutines: HashMap<u16, Subroutine>,
+    /// Subroutines being run and the cycle they were called at.
+    calls: Vec<(u16, u64)>,
+    heat: Vec<Heat>,
+}
+
+impl Profiler {
+    pub fn new(memory_size: usize) -> Profiler {
+        Profiler {
+            cycles: 0,
+            executions: vec![0; memory_size],
+            opcodes: HashMap::new(),
+            subroutines: HashMap::new(),
+            calls: vec![],
+            heat: vec![Heat::default(); memory_size],
+        }
+    }
+
+    /// Called after each instruction with the memory accesses it performed.
+    pub fn record(&mut self, pc: u16, opcode: u16, accesses: &[Access]) {
+        self.cycles += 1;
+        if let Some(executions) = self.executions.get_mut(pc as usize) {
+            *executions += 1;
+        }
+
+        let decoded = decode_instruction(opcode);
+        *self.opcodes.entry(decoded.into()).or_default() += 1;
+        match decoded {
+            OpCode::CallSubroutine(address) => {
+                self.subroutines.entry(address).or_default().calls += 1;
+                self.calls.push((address, self.cycles));
+            }
+            OpCode::RetFromSubroutine => {
+                // returns from calls made before profiling started are not attributed
+                if let Some((address, called_at)) = self.calls.pop() {
+                    self.subroutines.entry(address).or_default().cycles += self.cycles - called_at;
+                }
+            }
+            _ => {}
+        }
+
+        for access in accesses {
+            let Some(heat) = self.heat.get_mut(access.address) else {
+                continue;
+            };
+            match access.kind {
+                AccessKind::Fetch => heat.executed += 1,
+                AccessKind::Read => heat.read += 1,
+                AccessKind::Write => heat.written += 1,
+            }
+        }
+    }
+
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    pub fn executions(&self, address: u16) -> u64 {
+        self.executions
+            .get(address as usize)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Counts per `OpCode` variant name, e.g. `Draw`.
+    pub fn opcodes(&self) -> &HashMap<&'static str, u64> {
+        &self.opcodes
+    }
+
+    pub fn subroutines(&self) -> &HashMap<u16, Subroutine> {
+        &self.subroutines
+    }
+
+    pub fn heat(&self) -> &[Heat] {
+        &self.heat
+    }
+
+    /// One pixel per byte of memory, in rows of 64 bytes for 4k and 256 bytes for 64k.
+    /// Writes show in red, executed code in green and reads in blue, on a log scale.
+    pub fn heatmap(&self) -> Overlay {
+        let width = (self.heat.len() as f64).sqrt().ceil() as u32;
+        let height = (self.heat.len() as u32).div_ceil(width);
+
+        let maximum = |count: fn(&Heat) -> u64| self.heat.iter().map(count).max().unwrap_or(0);
+        let maximums = [
+            maximum(|heat| heat.written),
+            maximum(|heat| heat.executed),
+            maximum(|heat| heat.read),
+        ];
+        let intensity = |count: u64, maximum: u64| match count {
+            0 => 0,
+            _ => (64.0 + 191.0 * (count as f64).ln_1p() / (maximum as f64).ln_1p()) as u8,
+        };
+
+        let mut pixels = Vec::with_capacity((width * height * 3) as usize);
+        for heat in &self.heat {
+            let counts = [heat.written, heat.executed, heat.read];
+            pixels.extend(
+                counts
+                    .iter()
+                    .zip(maximums)
+                    .map(|(&count, max)| intensity(count, max)),
+            );
+        }
+        pixels.resize((width * height * 3) as usize, 0);
+        Overlay {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    /// Plain-text summary of the hottest addresses, opcodes and subroutines.
+    pub fn report(&self, memory: &[u8], syntax: Syntax) -> String {
+        let percentage = |count: u64| 100.0 * count as f64 / self.cycles.max(1) as f64;
+        let mut report = format!("{} instructions\n", self.cycles);
+
+        let mut addresses: Vec<(usize, u64)> = self
+            .executions
+            .iter()
+            .copied()
+            .enumerate()
+            .filter(|(_, count)| *count > 0)
+            .collect();
+        addresses.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        report.push_str("\nhottest addresses\n");
+        for (address, count) in addresses.into_iter().take(REPORT_ROWS) {
+            let byte = |offset: usize| memory.get(address + offset).copied().unwrap_or(0);
+            let opcode = decode_instruction(u16::from_be_bytes([byte(0), byte(1)]));
+            let _ = writeln!(
+                report,
+                "  {address:#06X} {count:>10} {:>6.2}%  {}",
+                percentage(count),
+                opcode.mnemonic(syntax)
+            );
+        }
+
+        let mut opcodes: Vec<(&str, u64)> = self.opcodes.iter().map(|(k, v)| (*k, *v)).collect();
+        opcodes.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        report.push_str("\nopcodes\n");
+        for (name, count) in opcodes {
+            let _ = writeln!(
+                report,
+                "  {name:<36} {count:>10} {:>6.2}%",
+                percentage(count)
+            );
+        }
+
+        let mut subroutines: Vec<(u16, Subroutine)> =
+            self.subroutines.iter().map(|(k, v)| (*k, *v)).collect();
+        subroutines.sort_by(|a, b| b.1.cycles.cmp(&a.1.cycles).then(a.0.cmp(&b.0)));
+        report.push_str("\nsubroutines        calls     cycles  cycles/call\n");
+        for (address, subroutine) in subroutines.into_iter().take(REPORT_ROWS) {
+            let _ = writeln!(
+                report,
+                "  {address:#06X} {:>16} {:>10} {:>12.1}",
+                subroutine.calls,
+                subroutine.cycles,
+                subroutine.cycles as f64 / subroutine.calls.max(1) as f64
+            );
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cpu::new;
+    use crate::debugger::Debugger;
+    use crate::profiler::{Profiler, Subroutine};
+
+    #[test]
+    fn counts_instructions_subroutines_and_memory_heat() {
+        let mut cpu = new();
+        // 0x200: CALL 0x206, JP 0x200 (twice)
+        // 0x206: LD I, 0x300, LD [I], V0, RET
+        cpu.load_rom_bytes(&[
+            0x22, 0x06, 0x12, 0x00, 0x00, 0x00, 0xA3, 0x00, 0xF0, 0x55, 0x00, 0xEE,
+        ])
+        .unwrap();
+        let mut debugger = Debugger::default();
+        debugger.set_profiler(Profiler::new(cpu.memory().size()));
+
+        for _ in 0..10 {
+            debugger.step(&mut cpu).unwrap();
+        }
+
+        let profiler = debugger.profiler().unwrap();
+        assert_eq!(profiler.cycles(), 10);
+        assert_eq!(profiler.executions(0x200), 2);
+        assert_eq!(profiler.executions(0x206), 2);
+        assert_eq!(profiler.opcodes()["CallSubroutine"], 2);
+        assert_eq!(
+            profiler.subroutines()[&0x206],
+            Subroutine {
+                calls: 2,
+                cycles: 6
+            }
+        );
+        assert_eq!(profiler.heat()[0x300].written, 2);
+        assert_eq!(profiler.heat()[0x207].executed, 2);
+
+        let heatmap = profiler.heatmap();
+        assert_eq!((heatmap.width, heatmap.height), (64, 64));
+        assert_eq!(&heatmap.pixels[0x300 * 3..0x300 * 3 + 3], &[255, 0, 0]);
+    }
+}
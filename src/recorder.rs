@@ -0,0 +1,308 @@
+use crate::emulator::FRAME_RATE;
+use crate::palette::Palette;
+use crate::screen::{GRID_X_SIZE, GRID_Y_SIZE};
+use crate::screenshot::to_rgb;
+use clap::ValueEnum;
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+const SAMPLE_RATE: u32 = 44_100;
+const SAMPLES_PER_FRAME: u32 = SAMPLE_RATE / FRAME_RATE;
+const BEEP_FREQUENCY: u32 = 440;
+const BEEP_AMPLITUDE: i16 = 8_000;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum CaptureFormat {
+    /// Animated GIF, identical consecutive frames are merged
+    #[default]
+    Gif,
+    /// Concatenated RGB24 frames, e.g. for `ffmpeg -f rawvideo -pix_fmt rgb24 -r 60`
+    Frames,
+}
+
+enum Video {
+    Gif {
+        encoder: gif::Encoder<BufWriter<File>>,
+        pending: Option<Vec<u8>>,
+        pending_frames: u32,
+    },
+    Frames(BufWriter<File>),
+}
+
+/// Appends `.extension`, the base name's own dots being part of the ROM's name.
+fn append_extension(base_path: &Path, extension: &str) -> PathBuf {
+    let mut path = base_path.as_os_str().to_owned();
+    path.push(format!(".{extension}"));
+    PathBuf::from(path)
+}
+
+/// Records one picture per 60 Hz frame, and optionally the beeper as a WAV track.
+pub struct Recorder {
+    video: Video,
+    audio: Option<Wav>,
+    palette: Palette,
+    scale: u32,
+    frames: u32,
+    paths: Vec<PathBuf>,
+}
+
+impl Recorder {
+    /// Creates `<base_path>.gif` or `<base_path>.rgb`, plus `<base_path>.wav` when `with_audio` is set.
+    pub fn start(
+        format: CaptureFormat,
+        base_path: &Path,
+        palette: Palette,
+        scale: u32,
+        with_audio: bool,
+    ) -> Result<Recorder, String> {
+        let scale = scale.max(1);
+        let mut paths = vec![];
+
+        let video = match format {
+            CaptureFormat::Gif => {
+                let path = append_extension(base_path, "gif");
+                let (bg, fg) = (palette.background, palette.foreground);
+                let mut encoder = gif::Encoder::new(
+                    BufWriter::new(create(&path)?),
+                    (GRID_X_SIZE as u32 * scale) as u16,
+                    (GRID_Y_SIZE as u32 * scale) as u16,
+                    &[bg.0, bg.1, bg.2, fg.0, fg.1, fg.2],
+                )
+                .map_err(|e| e.to_string())?;
+                encoder
+                    .set_repeat(gif::Repeat::Infinite)
+                    .map_err(|e| e.to_string())?;
+                paths.push(path);
+                Video::Gif {
+                    encoder,
+                    pending: None,
+                    pending_frames: 0,
+                }
+            }
+            CaptureFormat::Frames => {
+                let path = append_extension(base_path, "rgb");
+                let writer = BufWriter::new(create(&path)?);
+                paths.push(path);
+                Video::Frames(writer)
+            }
+        };
+
+        let audio = if with_audio {
+            let path = append_extension(base_path, "wav");
+            let wav = Wav::create(&path)?;
+            paths.push(path);
+            Some(wav)
+        } else {
+            None
+        };
+
+        Ok(Recorder {
+            video,
+            audio,
+            palette,
+            scale,
+            frames: 0,
+            paths,
+        })
+    }
+
+    /// Must be called exactly once per emulated frame, after the frame has run.
+    pub fn capture(
+        &mut self,
+        screen: &[[bool; GRID_X_SIZE]; GRID_Y_SIZE],
+        is_sound_playing: bool,
+    ) -> Result<(), String> {
+        match &mut self.video {
+            Video::Gif {
+                encoder,
+                pending,
+                pending_frames,
+            } => {
+                let pixels = to_indexed(screen, self.scale);
+                if pending.as_ref() != Some(&pixels) {
+                    if let Some(previous) = pending.take() {
+                        write_gif_frame(
+                            encoder,
+                            previous,
+                            self.scale,
+                            self.frames,
+                            *pending_frames,
+                        )?;
+                    }
+                    *pending = Some(pixels);
+                    *pending_frames = 0;
+                }
+                *pending_frames += 1;
+            }
+            Video::Frames(writer) => writer
+                .write_all(&to_rgb(screen, &self.palette, self.scale))
+                .map_err(|e| e.to_string())?,
+        }
+
+        if let Some(audio) = &mut self.audio {
+            audio.write_frame(self.frames, is_sound_playing)?;
+        }
+        self.frames += 1;
+        Ok(())
+    }
+
+    /// Flushes everything to disk and returns the written files.
+    pub fn finish(mut self) -> Result<Vec<PathBuf>, String> {
+        match &mut self.video {
+            Video::Gif {
+                encoder,
+                pending,
+                pending_frames,
+            } => {
+                if let Some(previous) = pending.take() {
+                    write_gif_frame(encoder, previous, self.scale, self.frames, *pending_frames)?;
+                }
+            }
+            Video::Frames(writer) => writer.flush().map_err(|e| e.to_string())?,
+        }
+        if let Some(audio) = self.audio.take() {
+            audio.finish()?;
+        }
+        Ok(self.paths)
+    }
+}
+
+fn create(path: &Path) -> Result<File, String> {
+    File::create(path).map_err(|e| format!("can't create {}: {e}", path.display()))
+}
+
+fn to_indexed(screen: &[[bool; GRID_X_SIZE]; GRID_Y_SIZE], scale: u32) -> Vec<u8> {
+    let scale = scale as usize;
+    let mut pixels = Vec::with_capacity(GRID_X_SIZE * GRID_Y_SIZE * scale * scale);
+    for line in screen {
+        for _ in 0..scale {
+            for &lit in line {
+                pixels.extend(std::iter::repeat_n(lit as u8, scale));
+            }
+        }
+    }
+    pixels
+}
+
+/// Writes a picture shown from frame `end - frames` to frame `end`. GIF delays are
+/// in hundredths of a second, so they are computed from absolute times to avoid drift.
+fn write_gif_frame(
+    encoder: &mut gif::Encoder<BufWriter<File>>,
+    pixels: Vec<u8>,
+    scale: u32,
+    end: u32,
+    frames: u32,
+) -> Result<(), String> {
+    let to_centiseconds =
+        |frame: u32| (frame as u64 * 100 + FRAME_RATE as u64 / 2) / FRAME_RATE as u64;
+    let delay = to_centiseconds(end) - to_centiseconds(end - frames);
+
+    let mut frame = gif::Frame::from_indexed_pixels(
+        (GRID_X_SIZE as u32 * scale) as u16,
+        (GRID_Y_SIZE as u32 * scale) as u16,
+        pixels,
+        None,
+    );
+    frame.delay = delay.min(u16::MAX as u64) as u16;
+    encoder.write_frame(&frame).map_err(|e| e.to_string())
+}
+
+/// Mono 16-bit PCM writer, the header sizes are patched once the length is known.
+struct Wav {
+    writer: BufWriter<File>,
+    samples: u32,
+}
+
+impl Wav {
+    fn create(path: &Path) -> Result<Wav, String> {
+        let mut wav = Wav {
+            writer: BufWriter::new(create(path)?),
+            samples: 0,
+        };
+        wav.write_header().map_err(|e| e.to_string())?;
+        Ok(wav)
+    }
+
+    fn write_header(&mut self) -> std::io::Result<()> {
+        let data_size = self.samples * 2;
+        let w = &mut self.writer;
+        w.write_all(b"RIFF")?;
+        w.write_all(&(36 + data_size).to_le_bytes())?;
+        w.write_all(b"WAVEfmt ")?;
+        w.write_all(&16u32.to_le_bytes())?;
+        w.write_all(&1u16.to_le_bytes())?; // PCM
+        w.write_all(&1u16.to_le_bytes())?; // mono
+        w.write_all(&SAMPLE_RATE.to_le_bytes())?;
+        w.write_all(&(SAMPLE_RATE * 2).to_le_bytes())?;
+        w.write_all(&2u16.to_le_bytes())?;
+        w.write_all(&16u16.to_le_bytes())?;
+        w.write_all(b"data")?;
+        w.write_all(&data_size.to_le_bytes())
+    }
+
+    fn write_frame(&mut self, frame: u32, is_sound_playing: bool) -> Result<(), String> {
+        let half_period = SAMPLE_RATE / BEEP_FREQUENCY / 2;
+        let first_sample = frame * SAMPLES_PER_FRAME;
+
+        for sample in first_sample..first_sample + SAMPLES_PER_FRAME {
+            let value = match (is_sound_playing, (sample / half_period).is_multiple_of(2)) {
+                (false, _) => 0,
+                (true, true) => BEEP_AMPLITUDE,
+                (true, false) => -BEEP_AMPLITUDE,
+            };
+            self.writer
+                .write_all(&value.to_le_bytes())
+                .map_err(|e| e.to_string())?;
+        }
+        self.samples += SAMPLES_PER_FRAME;
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<(), String> {
+        self.writer
+            .seek(SeekFrom::Start(0))
+            .and_then(|_| self.write_header())
+            .and_then(|_| self.writer.flush())
+            .map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::palette::Palette;
+    use crate::recorder::{CaptureFormat, Recorder, SAMPLES_PER_FRAME};
+    use crate::screen::{GRID_X_SIZE, GRID_Y_SIZE};
+    use std::fs;
+
+    #[test]
+    fn records_raw_frames_and_audio() {
+        let stem = format!("chip8 recorder {} v1.1-20240101-000000", std::process::id());
+        let base_path = std::env::temp_dir().join(&stem);
+        let mut recorder = Recorder::start(
+            CaptureFormat::Frames,
+            &base_path,
+            Palette::default(),
+            1,
+            true,
+        )
+        .unwrap();
+
+        let screen = [[false; GRID_X_SIZE]; GRID_Y_SIZE];
+        recorder.capture(&screen, true).unwrap();
+        recorder.capture(&screen, false).unwrap();
+        let paths = recorder.finish().unwrap();
+
+        let names: Vec<_> = paths
+            .iter()
+            .map(|path| path.file_name().unwrap().to_str().unwrap())
+            .collect();
+        assert_eq!(names, [format!("{stem}.rgb"), format!("{stem}.wav")]);
+        let frames = fs::read(&paths[0]).unwrap();
+        let wav = fs::read(&paths[1]).unwrap();
+        assert_eq!(frames.len(), 2 * GRID_X_SIZE * GRID_Y_SIZE * 3);
+        assert_eq!(wav.len(), 44 + 2 * SAMPLES_PER_FRAME as usize * 2);
+        assert_eq!(&wav[40..44], &(2 * SAMPLES_PER_FRAME * 2).to_le_bytes());
+        paths.iter().for_each(|path| fs::remove_file(path).unwrap());
+    }
+}
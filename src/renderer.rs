@@ -1,27 +1,32 @@
+use crate::filters::Filter;
+use crate::frontend::{Display, Overlay};
+use crate::palette::{Palette, Rgb};
+use crate::screen::{DOT_SIZE_IN_PXS, GRID_X_SIZE, GRID_Y_SIZE};
 use sdl2::Sdl;
-use sdl2::pixels::Color;
+use sdl2::pixels::{Color, PixelFormatEnum};
 use sdl2::rect::Rect;
-use sdl2::render::WindowCanvas;
-
-pub const GRID_X_SIZE: usize = 64;
-pub const GRID_Y_SIZE: usize = 32;
-const DOT_SIZE_IN_PXS: u32 = 10;
-
-const BACKGROUND_COLOR: Color = Color::RGB(134, 84, 3);
-const FONT_COLOR: Color = Color::RGB(253, 195, 10);
+use sdl2::render::{BlendMode, WindowCanvas};
 
 
 pub struct Renderer {
     pub canvas: WindowCanvas,
+    pub palette: Palette,
+    pub filter: Filter,
+    pub overlay: Option<Overlay>,
 }
 
 impl Renderer {
 
     pub fn render(&mut self, screen: [[bool; GRID_X_SIZE]; GRID_Y_SIZE]) {
-        self.canvas.set_draw_color(BACKGROUND_COLOR);
+        if self.filter.is_enabled() {
+            self.render_filtered(&screen);
+            return;
+        }
+
+        self.canvas.set_draw_color(to_color(self.palette.background));
         self.canvas.clear();
 
-        self.canvas.set_draw_color(FONT_COLOR);
+        self.canvas.set_draw_color(to_color(self.palette.foreground));
         screen.iter().enumerate().for_each(|(index, line)| {
             line.iter().enumerate().for_each(|(sprite_index, sprite)| {
                 if *sprite {
@@ -34,11 +39,65 @@ impl Renderer {
                 }
             })
         });
+        self.draw_overlay();
         self.canvas.present()
     }
+
+    fn render_filtered(&mut self, screen: &[[bool; GRID_X_SIZE]; GRID_Y_SIZE]) {
+        let width = GRID_X_SIZE as u32 * DOT_SIZE_IN_PXS;
+        let height = GRID_Y_SIZE as u32 * DOT_SIZE_IN_PXS;
+        let pixels = self.filter.apply(screen, &self.palette, DOT_SIZE_IN_PXS);
+
+        let texture_creator = self.canvas.texture_creator();
+        let mut texture = texture_creator
+            .create_texture_static(PixelFormatEnum::RGB24, width, height)
+            .unwrap();
+        texture.update(None, &pixels, width as usize * 3).unwrap();
+
+        self.canvas.copy(&texture, None, None).unwrap();
+        self.draw_overlay();
+        self.canvas.present()
+    }
+
+    /// Blends the overlay over the right part of the window, scaled to its height.
+    fn draw_overlay(&mut self) {
+        let Some(overlay) = &self.overlay else { return };
+
+        let texture_creator = self.canvas.texture_creator();
+        let mut texture = texture_creator
+            .create_texture_static(PixelFormatEnum::RGB24, overlay.width, overlay.height)
+            .unwrap();
+        texture.update(None, &overlay.pixels, overlay.width as usize * 3).unwrap();
+        texture.set_blend_mode(BlendMode::Blend);
+        texture.set_alpha_mod(224);
+
+        let (window_width, window_height) = self.canvas.output_size().unwrap();
+        let width = window_height * overlay.width / overlay.height;
+        let target = Rect::new((window_width - width) as i32, 0, width, window_height);
+        self.canvas.copy(&texture, None, target).unwrap();
+    }
+}
+
+impl Display for Renderer {
+    fn render(&mut self, screen: &[[bool; GRID_X_SIZE]; GRID_Y_SIZE]) -> Result<(), String> {
+        Renderer::render(self, *screen);
+        Ok(())
+    }
+
+    fn palette(&self) -> Palette {
+        self.palette
+    }
+
+    fn set_overlay(&mut self, overlay: Option<Overlay>) {
+        self.overlay = overlay;
+    }
+}
+
+fn to_color((r, g, b): Rgb) -> Color {
+    Color::RGB(r, g, b)
 }
 
-pub fn new(sdl_context: &Sdl) -> Renderer {
+pub fn new(sdl_context: &Sdl, filter: Filter) -> Renderer {
     let video_subsystem = sdl_context.video().unwrap();
 
     let window = video_subsystem.window(
@@ -59,5 +118,8 @@ pub fn new(sdl_context: &Sdl) -> Renderer {
 
     Renderer {
         canvas,
+        palette: Palette::default(),
+        filter,
+        overlay: None,
     }
 }
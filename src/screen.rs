@@ -0,0 +1,4 @@
+pub const GRID_X_SIZE: usize = 64;
+pub const GRID_Y_SIZE: usize = 32;
+/// Upscaling factor used by the window and by image exports.
+pub const DOT_SIZE_IN_PXS: u32 = 10;
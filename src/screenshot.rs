@@ -0,0 +1,123 @@
+use crate::palette::Palette;
+use crate::screen::{GRID_X_SIZE, GRID_Y_SIZE};
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use time::OffsetDateTime;
+
+/// Converts the framebuffer into packed RGB24 pixels, `scale` pixels per dot.
+pub fn to_rgb(
+    screen: &[[bool; GRID_X_SIZE]; GRID_Y_SIZE],
+    palette: &Palette,
+    scale: u32,
+) -> Vec<u8> {
+    let scale = scale.max(1) as usize;
+    let mut pixels = Vec::with_capacity(GRID_X_SIZE * GRID_Y_SIZE * scale * scale * 3);
+
+    for line in screen {
+        for _ in 0..scale {
+            for &lit in line {
+                let (r, g, b) = palette.color(lit);
+                for _ in 0..scale {
+                    pixels.extend([r, g, b]);
+                }
+            }
+        }
+    }
+    pixels
+}
+
+pub fn save_png(
+    path: &Path,
+    screen: &[[bool; GRID_X_SIZE]; GRID_Y_SIZE],
+    palette: &Palette,
+    scale: u32,
+) -> Result<(), String> {
+    let scale = scale.max(1);
+    save_rgb_png(
+        path,
+        GRID_X_SIZE as u32 * scale,
+        GRID_Y_SIZE as u32 * scale,
+        &to_rgb(screen, palette, scale),
+    )
+}
+
+/// Writes packed RGB24 `pixels` as a `width` x `height` PNG.
+pub fn save_rgb_png(path: &Path, width: u32, height: u32, pixels: &[u8]) -> Result<(), String> {
+    let file = File::create(path).map_err(|e| format!("can't create {}: {e}", path.display()))?;
+
+    let mut encoder = png::Encoder::new(BufWriter::new(file), width, height);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    let mut writer = encoder.write_header().map_err(|e| e.to_string())?;
+    writer.write_image_data(pixels).map_err(|e| e.to_string())
+}
+
+/// Writes the framebuffer twice into `directory`, at native resolution and
+/// upscaled by `scale`, and returns the paths of the written files.
+pub fn capture(
+    directory: &Path,
+    rom_path: &Path,
+    screen: &[[bool; GRID_X_SIZE]; GRID_Y_SIZE],
+    palette: &Palette,
+    scale: u32,
+) -> Result<Vec<PathBuf>, String> {
+    let base_name = base_name(rom_path, OffsetDateTime::now_utc());
+    let native = directory.join(format!("{base_name}.png"));
+    let scaled = directory.join(format!("{base_name}-x{scale}.png"));
+
+    save_png(&native, screen, palette, 1)?;
+    save_png(&scaled, screen, palette, scale)?;
+    Ok(vec![native, scaled])
+}
+
+/// `<rom file stem>-<YYYYMMDD>-<HHMMSS>`, the timestamp being in UTC.
+pub fn base_name(rom_path: &Path, at: OffsetDateTime) -> String {
+    let rom = rom_path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "chip8".to_string());
+
+    format!(
+        "{rom}-{:04}{:02}{:02}-{:02}{:02}{:02}",
+        at.year(),
+        at.month() as u8,
+        at.day(),
+        at.hour(),
+        at.minute(),
+        at.second()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::palette::Palette;
+    use crate::screen::{GRID_X_SIZE, GRID_Y_SIZE};
+    use crate::screenshot::{base_name, to_rgb};
+    use std::path::Path;
+    use time::OffsetDateTime;
+
+    #[test]
+    fn names_the_file_after_the_rom_and_time() {
+        let name = base_name(
+            Path::new("./roms/pong.ch8"),
+            OffsetDateTime::from_unix_timestamp(1709622489).unwrap(),
+        );
+
+        assert_eq!(name, "pong-20240305-070809");
+    }
+
+    #[test]
+    fn scales_every_dot() {
+        let mut screen = [[false; GRID_X_SIZE]; GRID_Y_SIZE];
+        screen[0][1] = true;
+        let palette = Palette::default();
+        let pixels = to_rgb(&screen, &palette, 2);
+
+        assert_eq!(pixels.len(), GRID_X_SIZE * GRID_Y_SIZE * 4 * 3);
+        assert_eq!(pixels[0], palette.background.0);
+        assert_eq!(pixels[2 * 3], palette.foreground.0);
+        assert_eq!(pixels[(GRID_X_SIZE * 2 + 3) * 3], palette.foreground.0);
+    }
+}
@@ -0,0 +1,188 @@
+use crate::frontend::{Audio, Command, Input, Keymap};
+use log::warn;
+use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
+use sdl2::event::Event;
+use sdl2::keyboard::{Keycode, Scancode};
+use sdl2::{EventPump, Sdl};
+use std::collections::HashSet;
+
+const BEEP_FREQUENCY: f32 = 440.0;
+const BEEP_VOLUME: f32 = 0.1;
+
+pub struct SdlInput {
+    event_pump: EventPump,
+    keymap: Keymap,
+}
+
+impl SdlInput {
+    pub fn new(sdl_context: &Sdl) -> SdlInput {
+        SdlInput {
+            event_pump: sdl_context.event_pump().unwrap(),
+            keymap: Keymap::default(),
+        }
+    }
+}
+
+impl Input for SdlInput {
+    fn poll(&mut self) -> Vec<Command> {
+        self.event_pump
+            .poll_iter()
+            .filter_map(|event| match event {
+                Event::Quit { .. }
+                | Event::KeyDown {
+                    keycode: Some(Keycode::Escape),
+                    ..
+                } => Some(Command::Quit),
+                Event::KeyDown {
+                    keycode: Some(Keycode::F12),
+                    ..
+                } => Some(Command::Screenshot),
+                Event::KeyDown {
+                    keycode: Some(Keycode::F11),
+                    ..
+                } => Some(Command::ToggleRecording),
+                Event::KeyDown {
+                    keycode: Some(Keycode::F9),
+                    ..
+                } => Some(Command::ToggleHeatmap),
+                Event::KeyDown {
+                    keycode: Some(Keycode::F5),
+                    ..
+                } => Some(Command::Continue),
+                Event::KeyDown {
+                    keycode: Some(Keycode::F10),
+                    ..
+                } => Some(Command::Step),
+                Event::KeyDown {
+                    keycode: Some(keycode),
+                    ..
+                } => match keycode {
+                    Keycode::P | Keycode::Pause => Some(Command::TogglePause),
+                    Keycode::N => Some(Command::AdvanceFrame),
+                    Keycode::Tab => Some(Command::ToggleTurbo),
+                    Keycode::M => Some(Command::SlowMotion),
+                    Keycode::Equals | Keycode::KpPlus => Some(Command::Faster),
+                    Keycode::Minus | Keycode::KpMinus => Some(Command::Slower),
+                    _ => None,
+                },
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn pressed_keys(&mut self) -> HashSet<u8> {
+        self.event_pump
+            .keyboard_state()
+            .pressed_scancodes()
+            .filter_map(|scancode| map_scancode(scancode).or_else(|| self.map_control(scancode)))
+            .collect()
+    }
+
+    fn set_keymap(&mut self, keymap: Keymap) {
+        self.keymap = keymap;
+    }
+}
+
+impl SdlInput {
+    fn map_control(&self, scancode: Scancode) -> Option<u8> {
+        match scancode {
+            Scancode::Up => self.keymap.up,
+            Scancode::Down => self.keymap.down,
+            Scancode::Left => self.keymap.left,
+            Scancode::Right => self.keymap.right,
+            Scancode::Space => self.keymap.a,
+            Scancode::Return => self.keymap.b,
+            _ => None,
+        }
+    }
+}
+
+fn map_scancode(scancode: Scancode) -> Option<u8> {
+    match scancode {
+        Scancode::Num1 => Some(0x1),
+        Scancode::Num2 => Some(0x2),
+        Scancode::Num3 => Some(0x3),
+        Scancode::Num4 => Some(0xC),
+        Scancode::Q => Some(0x4),
+        Scancode::W => Some(0x5),
+        Scancode::E => Some(0x6),
+        Scancode::R => Some(0xD),
+        Scancode::A => Some(0x7),
+        Scancode::S => Some(0x8),
+        Scancode::D => Some(0x9),
+        Scancode::F => Some(0xE),
+        Scancode::Z => Some(0xA),
+        Scancode::X => Some(0x0),
+        Scancode::C => Some(0xB),
+        Scancode::V => Some(0xF),
+        _ => None,
+    }
+}
+
+struct SquareWave {
+    phase_increment: f32,
+    phase: f32,
+}
+
+impl AudioCallback for SquareWave {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        for sample in out.iter_mut() {
+            *sample = if self.phase < 0.5 {
+                BEEP_VOLUME
+            } else {
+                -BEEP_VOLUME
+            };
+            self.phase = (self.phase + self.phase_increment) % 1.0;
+        }
+    }
+}
+
+/// Plays a square wave while the sound timer runs. Sound is optional:
+/// without an audio device the emulator stays silent.
+pub struct SdlAudio {
+    device: Option<AudioDevice<SquareWave>>,
+    beeping: bool,
+}
+
+impl SdlAudio {
+    pub fn new(sdl_context: &Sdl) -> SdlAudio {
+        let desired = AudioSpecDesired {
+            freq: Some(44_100),
+            channels: Some(1),
+            samples: None,
+        };
+        let device = sdl_context
+            .audio()
+            .and_then(|audio| {
+                audio.open_playback(None, &desired, |spec| SquareWave {
+                    phase_increment: BEEP_FREQUENCY / spec.freq as f32,
+                    phase: 0.0,
+                })
+            })
+            .inspect_err(|e| warn!("no audio: {e}"))
+            .ok();
+
+        SdlAudio {
+            device,
+            beeping: false,
+        }
+    }
+}
+
+impl Audio for SdlAudio {
+    fn set_beeping(&mut self, beeping: bool) {
+        if beeping == self.beeping {
+            return;
+        }
+        self.beeping = beeping;
+        if let Some(device) = &self.device {
+            if beeping {
+                device.resume();
+            } else {
+                device.pause();
+            }
+        }
+    }
+}
@@ -0,0 +1,273 @@
+use crate::analysis::Analysis;
+use crate::cpu::Cpu;
+use crate::decoder::decode_instruction;
+use crate::fault::Fault;
+use crate::movie::Movie;
+use crate::opcode::OpCode;
+use crate::palette::Palette;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+/// Sprites per row of a sprite sheet.
+const SHEET_COLUMNS: usize = 8;
+/// Side of a sprite sheet cell in dots, enough for SCHIP's 16x16 sprites plus a gap.
+const CELL_SIZE: usize = 17;
+
+/// Sprite data a `DXYN` reads: `height` rows from `address`, SCHIP's 16x16
+/// sprites being drawn with a height of 0.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SpriteRef {
+    pub address: u16,
+    pub height: u8,
+}
+
+impl SpriteRef {
+    pub fn width(self) -> usize {
+        if self.height == 0 {
+            16
+        } else {
+            8
+        }
+    }
+
+    pub fn rows(self) -> usize {
+        if self.height == 0 {
+            16
+        } else {
+            self.height as usize
+        }
+    }
+
+    /// Bytes of sprite data.
+    pub fn size(self) -> usize {
+        self.rows() * self.width() / 8
+    }
+}
+
+/// Sprites found by looking for `ANNN` followed by `DXYN` in the same block of code.
+pub fn find_static(analysis: &Analysis) -> Vec<SpriteRef> {
+    let mut sprites = vec![];
+    for block in &analysis.blocks {
+        let mut index = None;
+        for &(_, op_code) in &block.instructions {
+            match op_code {
+                OpCode::SetIndex(address) => index = Some(address),
+                OpCode::Draw(_, _, height) => {
+                    sprites.extend(index.map(|address| SpriteRef { address, height }))
+                }
+                OpCode::AddRegisterValueToIndex(_)
+                | OpCode::LoadSpriteRepresentationInMemory(_)
+                | OpCode::LoadIndexLong
+                | OpCode::LoadFromRegistersToMemory(_)
+                | OpCode::LoadFromMemoryToRegisters(_) => index = None,
+                _ => {}
+            }
+        }
+    }
+    merge(sprites)
+}
+
+/// Runs the ROM for `frames` frames of `instructions_per_frame` instructions with
+/// the keys of `movie` and records every sprite drawn, including those whose
+/// address is computed. Their data is in the CPU's memory once tracing stops,
+/// at the first fault, which is returned with the sprites found until then.
+pub fn trace(
+    cpu: &mut Cpu,
+    movie: &Movie,
+    frames: u32,
+    instructions_per_frame: u32,
+) -> (Vec<SpriteRef>, Option<Fault>) {
+    let mut sprites = vec![];
+    for frame in 0..frames {
+        cpu.set_keys_pressed(movie.keys_at(frame));
+        for _ in 0..instructions_per_frame {
+            if cpu.is_waiting_for_vblank() {
+                break;
+            }
+            let byte = |offset: u16| {
+                let address = cpu.pc().wrapping_add(offset) as usize;
+                cpu.memory().peek(address).unwrap_or_default()
+            };
+            if let OpCode::Draw(_, _, height) =
+                decode_instruction(u16::from_be_bytes([byte(0), byte(1)]))
+            {
+                sprites.push(SpriteRef {
+                    address: cpu.index(),
+                    height,
+                });
+            }
+            if let Err(fault) = cpu.run(1) {
+                return (merge(sprites), Some(fault));
+            }
+        }
+        cpu.decrement_timers();
+    }
+    (merge(sprites), None)
+}
+
+/// Sorts by address, keeping the longest sprite drawn from each one.
+pub fn merge(sprites: Vec<SpriteRef>) -> Vec<SpriteRef> {
+    let mut longest: BTreeMap<u16, SpriteRef> = BTreeMap::new();
+    for sprite in sprites {
+        let kept = longest.entry(sprite.address).or_insert(sprite);
+        if sprite.size() > kept.size() {
+            *kept = sprite;
+        }
+    }
+    longest.into_values().collect()
+}
+
+fn bytes(memory: &[u8], sprite: SpriteRef) -> Vec<u8> {
+    (0..sprite.size())
+        .map(|offset| {
+            memory
+                .get(sprite.address as usize + offset)
+                .copied()
+                .unwrap_or_default()
+        })
+        .collect()
+}
+
+/// One labelled block of binary literals per sprite, a row per line.
+pub fn to_octo(memory: &[u8], sprites: &[SpriteRef]) -> String {
+    let mut source = String::new();
+    for &sprite in sprites {
+        let _ = writeln!(
+            source,
+            ": sprite-{:03X} # {}x{}",
+            sprite.address,
+            sprite.width(),
+            sprite.rows()
+        );
+        for row in bytes(memory, sprite).chunks(sprite.width() / 8) {
+            let literals: Vec<String> = row.iter().map(|byte| format!("0b{byte:08b}")).collect();
+            let _ = writeln!(source, "  {}", literals.join(" "));
+        }
+        source.push('\n');
+    }
+    source
+}
+
+/// Packed RGB24 picture of the sprites in a grid of 16x16 cells, `scale`
+/// pixels per dot, as `(width, height, pixels)`.
+pub fn to_sheet(
+    memory: &[u8],
+    sprites: &[SpriteRef],
+    palette: &Palette,
+    scale: u32,
+) -> (u32, u32, Vec<u8>) {
+    let scale = scale.max(1) as usize;
+    let columns = sprites.len().clamp(1, SHEET_COLUMNS);
+    let rows = sprites.len().div_ceil(SHEET_COLUMNS).max(1);
+    let (width, height) = (columns * CELL_SIZE * scale, rows * CELL_SIZE * scale);
+
+    let (r, g, b) = palette.background;
+    let mut pixels = [r, g, b].repeat(width * height);
+    for (position, &sprite) in sprites.iter().enumerate() {
+        let (left, top) = (position % SHEET_COLUMNS, position / SHEET_COLUMNS);
+        let data = bytes(memory, sprite);
+        for row in 0..sprite.rows() {
+            for column in 0..sprite.width() {
+                let byte = data[row * sprite.width() / 8 + column / 8];
+                if byte >> (7 - column % 8) & 1 == 0 {
+                    continue;
+                }
+                let (x, y) = (left * CELL_SIZE + column, top * CELL_SIZE + row);
+                for dy in 0..scale {
+                    let start = ((y * scale + dy) * width + x * scale) * 3;
+                    let (r, g, b) = palette.foreground;
+                    for pixel in pixels[start..start + scale * 3].chunks_exact_mut(3) {
+                        pixel.copy_from_slice(&[r, g, b]);
+                    }
+                }
+            }
+        }
+    }
+    (width as u32, height as u32, pixels)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::analysis::analyze;
+    use crate::movie::Movie;
+    use crate::octo;
+    use crate::sprites::{find_static, to_octo, trace, SpriteRef};
+
+    const ROM: [u8; 16] = [
+        0xA2, 0x0C, // 0x200: LD I, 0x20C
+        0xD0, 0x02, // 0x202: DRW V0, V0, 2
+        0xF0, 0x1E, // 0x204: ADD I, V0
+        0xD0, 0x01, // 0x206: DRW V0, V0, 1
+        0x12, 0x08, // 0x208: JP 0x208
+        0x00, 0x00, // 0x20A: padding
+        0x3C, 0x81, // 0x20C: sprite
+        0xFF, 0x00, // 0x20E: second sprite
+    ];
+
+    #[test]
+    fn finds_sprites_statically_and_at_runtime() {
+        let analysis = analyze(&ROM, 0x200);
+        assert_eq!(
+            find_static(&analysis),
+            [SpriteRef {
+                address: 0x20C,
+                height: 2
+            }]
+        );
+
+        let mut cpu = crate::cpu::new();
+        cpu.load_rom_bytes(&ROM).unwrap();
+        cpu.registers_mut()[0] = 2;
+        let (sprites, fault) = trace(&mut cpu, &Movie::default(), 1, 50);
+        assert_eq!(fault, None);
+        assert_eq!(
+            sprites,
+            [
+                SpriteRef {
+                    address: 0x20C,
+                    height: 2
+                },
+                SpriteRef {
+                    address: 0x20E,
+                    height: 1
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn exports_octo_literals_that_compile_back_to_the_sprite() {
+        let analysis = analyze(&ROM, 0x200);
+        let source = to_octo(&analysis.memory, &find_static(&analysis));
+        assert!(source.starts_with(": sprite-20C # 8x2\n  0b00111100\n  0b10000001\n"));
+
+        let rom = octo::compile(&format!(": main\n{source}")).unwrap();
+        assert!(rom.ends_with(&[0x3C, 0x81]));
+    }
+
+    #[test]
+    fn traces_sprites_built_at_runtime_at_the_given_rate() {
+        let rom = [
+            0x60, 0xFF, // LD V0, 0xFF
+            0x61, 0x81, // LD V1, 0x81
+            0xA3, 0x00, // LD I, 0x300
+            0xF1, 0x55, // LD [I], V1
+            0xA3, 0x00, // LD I, 0x300
+            0xD2, 0x22, // DRW V2, V2, 2
+            0x12, 0x0C, // JP 0x20C
+        ];
+        let mut cpu = crate::cpu::new();
+        cpu.load_rom_bytes(&rom).unwrap();
+        assert_eq!(trace(&mut cpu, &Movie::default(), 1, 5).0, []);
+
+        let (sprites, _) = trace(&mut cpu, &Movie::default(), 1, 5);
+        assert_eq!(
+            sprites,
+            [SpriteRef {
+                address: 0x300,
+                height: 2
+            }]
+        );
+        assert!(to_octo(cpu.memory().as_slice(), &sprites).contains("0b11111111\n  0b10000001"));
+    }
+}
@@ -0,0 +1,132 @@
+use crate::fault::Fault;
+use crate::memory::Memory;
+
+/// Where the VIP interpreter keeps return addresses, in the reserved top of memory.
+pub const VIP_STACK_ADDRESS: usize = 0xEA0;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum StackStorage {
+    /// Return addresses are kept outside of the emulated memory.
+    #[default]
+    Internal,
+    /// Return addresses are stored big-endian in emulated memory from this
+    /// address upwards, where a ROM can read or overwrite them.
+    Memory(usize),
+}
+
+/// Fixed-depth call stack, overflowing or underflowing it is a fault.
+#[derive(Clone, Debug)]
+pub struct Stack {
+    depth: usize,
+    storage: StackStorage,
+    entries: Vec<u16>,
+    pointer: usize,
+}
+
+impl Default for Stack {
+    fn default() -> Self {
+        Stack::new(16, StackStorage::Internal)
+    }
+}
+
+impl Stack {
+    pub fn new(depth: usize, storage: StackStorage) -> Stack {
+        Stack {
+            depth,
+            storage,
+            entries: Vec::with_capacity(depth),
+            pointer: 0,
+        }
+    }
+
+    /// Number of return addresses currently on the stack.
+    pub fn len(&self) -> usize {
+        self.pointer
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pointer == 0
+    }
+
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    pub fn push(&mut self, memory: &mut Memory, address: u16) -> Result<(), Fault> {
+        if self.pointer == self.depth {
+            return Err(Fault::StackOverflow { depth: self.depth });
+        }
+        match self.storage {
+            StackStorage::Internal => self.entries.push(address),
+            StackStorage::Memory(base) => memory.write_word(base + self.pointer * 2, address)?,
+        }
+        self.pointer += 1;
+        Ok(())
+    }
+
+    pub fn pop(&mut self, memory: &mut Memory) -> Result<u16, Fault> {
+        if self.pointer == 0 {
+            return Err(Fault::StackUnderflow);
+        }
+        let address = match self.storage {
+            StackStorage::Internal => self.entries.pop().unwrap_or_default(),
+            StackStorage::Memory(base) => memory.read_word(base + (self.pointer - 1) * 2)?,
+        };
+        self.pointer -= 1;
+        Ok(address)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fault::Fault;
+    use crate::memory::{Memory, MemorySize, OutOfBounds};
+    use crate::stack::{Stack, StackStorage, VIP_STACK_ADDRESS};
+
+    #[test]
+    fn faults_past_its_depth() {
+        let mut memory = Memory::default();
+        let mut stack = Stack::new(2, StackStorage::Internal);
+        stack.push(&mut memory, 0x202).unwrap();
+        stack.push(&mut memory, 0x204).unwrap();
+
+        assert_eq!(
+            stack.push(&mut memory, 0x206),
+            Err(Fault::StackOverflow { depth: 2 })
+        );
+        assert_eq!(stack.pop(&mut memory), Ok(0x204));
+        assert_eq!(stack.pop(&mut memory), Ok(0x202));
+        assert_eq!(stack.pop(&mut memory), Err(Fault::StackUnderflow));
+    }
+
+    #[test]
+    fn can_live_in_emulated_memory() {
+        let mut memory = Memory::default();
+        let mut stack = Stack::new(12, StackStorage::Memory(VIP_STACK_ADDRESS));
+        stack.push(&mut memory, 0x2AB).unwrap();
+
+        assert_eq!(memory.peek(VIP_STACK_ADDRESS), Some(0x02));
+        assert_eq!(memory.peek(VIP_STACK_ADDRESS + 1), Some(0xAB));
+
+        memory.write(VIP_STACK_ADDRESS + 1, 0xCD).unwrap();
+        assert_eq!(stack.pop(&mut memory), Ok(0x2CD));
+    }
+
+    #[test]
+    fn faulting_accesses_leave_the_stack_and_memory_unchanged() {
+        let mut memory = Memory::new(MemorySize::Standard, OutOfBounds::Fault);
+        memory.write(0xFFF, 0x02).unwrap();
+        // the entry straddles the end of memory
+        let mut stack = Stack::new(2, StackStorage::Memory(0xFFF));
+        assert!(stack.push(&mut memory, 0x2CD).is_err());
+        assert_eq!((stack.len(), memory.peek(0xFFF)), (0, Some(0x02)));
+
+        // pushed to a larger memory, then popped from one too small to hold it
+        let mut extended = Memory::new(MemorySize::Extended, OutOfBounds::Fault);
+        let mut stack = Stack::new(2, StackStorage::Memory(0x1000));
+        stack.push(&mut extended, 0x2AB).unwrap();
+        assert!(stack.pop(&mut memory).is_err());
+        assert_eq!(stack.len(), 1);
+        assert_eq!(stack.pop(&mut extended), Ok(0x2AB));
+    }
+}
@@ -0,0 +1,259 @@
+use crate::frontend::{Audio, Command, Display, Input, Keymap};
+use crate::palette::{Palette, Rgb};
+use crate::screen::{GRID_X_SIZE, GRID_Y_SIZE};
+use crossterm::event::{
+    self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, KeyboardEnhancementFlags,
+    PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+};
+use crossterm::style::{Color, Colors, Print, ResetColor, SetColors};
+use crossterm::{cursor, execute, queue, terminal};
+use std::collections::HashSet;
+use std::io::{self, Stdout, Write};
+use std::time::{Duration, Instant};
+
+/// Most terminals only report key presses and auto-repeats, so a key is
+/// considered released when no event refreshed it for this long.
+const FIRST_PRESS_HOLD: Duration = Duration::from_millis(200);
+const REPEAT_HOLD: Duration = Duration::from_millis(100);
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum KeyState {
+    Up,
+    /// Held until a release event is received.
+    Down,
+    /// Held until the deadline unless an auto-repeat extends it.
+    DownUntil(Instant),
+}
+
+/// Owns the TTY for the duration of the run: raw mode and the alternate
+/// screen are restored when it is dropped.
+pub struct Terminal {
+    pub display: TerminalDisplay,
+    pub audio: TerminalAudio,
+    pub input: TerminalInput,
+}
+
+/// Renders the screen with half-block characters, two CHIP-8 rows per terminal line.
+pub struct TerminalDisplay {
+    stdout: Stdout,
+    palette: Palette,
+}
+
+/// Rings the terminal bell when a beep starts.
+pub struct TerminalAudio {
+    beeping: bool,
+}
+
+pub struct TerminalInput {
+    keys: [KeyState; 16],
+    reports_key_release: bool,
+    keymap: Keymap,
+    commands: Vec<Command>,
+}
+
+impl Terminal {
+    pub fn enter(palette: Palette) -> io::Result<Terminal> {
+        let mut stdout = io::stdout();
+        terminal::enable_raw_mode()?;
+        execute!(
+            stdout,
+            terminal::EnterAlternateScreen,
+            cursor::Hide,
+            terminal::Clear(terminal::ClearType::All)
+        )?;
+
+        let reports_key_release = terminal::supports_keyboard_enhancement().unwrap_or(false);
+        if reports_key_release {
+            execute!(
+                stdout,
+                PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::REPORT_EVENT_TYPES)
+            )?;
+        }
+
+        Ok(Terminal {
+            display: TerminalDisplay { stdout, palette },
+            audio: TerminalAudio { beeping: false },
+            input: TerminalInput {
+                keys: [KeyState::Up; 16],
+                reports_key_release,
+                keymap: Keymap::default(),
+                commands: vec![],
+            },
+        })
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        let mut stdout = io::stdout();
+        if self.input.reports_key_release {
+            let _ = execute!(stdout, PopKeyboardEnhancementFlags);
+        }
+        let _ = execute!(
+            stdout,
+            ResetColor,
+            cursor::Show,
+            terminal::LeaveAlternateScreen
+        );
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+impl TerminalInput {
+    fn handle_key(&mut self, key_event: KeyEvent) {
+        if key_event.kind != KeyEventKind::Release {
+            match key_event.code {
+                KeyCode::Esc => self.commands.push(Command::Quit),
+                KeyCode::Char('c') if key_event.modifiers.contains(KeyModifiers::CONTROL) => {
+                    self.commands.push(Command::Quit)
+                }
+                KeyCode::F(12) => self.commands.push(Command::Screenshot),
+                KeyCode::F(11) => self.commands.push(Command::ToggleRecording),
+                KeyCode::F(5) => self.commands.push(Command::Continue),
+                KeyCode::F(10) => self.commands.push(Command::Step),
+                KeyCode::Char('p') => self.commands.push(Command::TogglePause),
+                KeyCode::Char('n') => self.commands.push(Command::AdvanceFrame),
+                KeyCode::Tab => self.commands.push(Command::ToggleTurbo),
+                KeyCode::Char('m') => self.commands.push(Command::SlowMotion),
+                KeyCode::Char('=' | '+') => self.commands.push(Command::Faster),
+                KeyCode::Char('-') => self.commands.push(Command::Slower),
+                _ => {}
+            }
+        }
+
+        let key = match key_event.code {
+            KeyCode::Char(' ') => self.keymap.a,
+            KeyCode::Char(character) => map_key(character),
+            KeyCode::Up => self.keymap.up,
+            KeyCode::Down => self.keymap.down,
+            KeyCode::Left => self.keymap.left,
+            KeyCode::Right => self.keymap.right,
+            KeyCode::Enter => self.keymap.b,
+            _ => None,
+        };
+        let Some(key) = key else {
+            return;
+        };
+
+        let now = Instant::now();
+        let state = &mut self.keys[key as usize];
+        *state = match key_event.kind {
+            KeyEventKind::Release => KeyState::Up,
+            // with real release events there is no need to guess
+            _ if self.reports_key_release => KeyState::Down,
+            KeyEventKind::Press if *state == KeyState::Up => {
+                KeyState::DownUntil(now + FIRST_PRESS_HOLD)
+            }
+            KeyEventKind::Press | KeyEventKind::Repeat => KeyState::DownUntil(now + REPEAT_HOLD),
+        };
+    }
+}
+
+impl Input for TerminalInput {
+    fn poll(&mut self) -> Vec<Command> {
+        loop {
+            match event::poll(Duration::ZERO) {
+                Ok(true) => {
+                    if let Ok(Event::Key(key_event)) = event::read() {
+                        self.handle_key(key_event);
+                    }
+                }
+                Ok(false) => break,
+                Err(_) => {
+                    self.commands.push(Command::Quit);
+                    break;
+                }
+            }
+        }
+        std::mem::take(&mut self.commands)
+    }
+
+    fn pressed_keys(&mut self) -> HashSet<u8> {
+        let now = Instant::now();
+        self.keys
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(key, state)| match *state {
+                KeyState::Down => Some(key as u8),
+                KeyState::DownUntil(deadline) if deadline > now => Some(key as u8),
+                _ => {
+                    *state = KeyState::Up;
+                    None
+                }
+            })
+            .collect()
+    }
+
+    fn set_keymap(&mut self, keymap: Keymap) {
+        self.keymap = keymap;
+    }
+}
+
+impl Display for TerminalDisplay {
+    fn render(&mut self, screen: &[[bool; GRID_X_SIZE]; GRID_Y_SIZE]) -> Result<(), String> {
+        self.draw(screen).map_err(|e| e.to_string())
+    }
+
+    fn palette(&self) -> Palette {
+        self.palette
+    }
+}
+
+impl TerminalDisplay {
+    fn draw(&mut self, screen: &[[bool; GRID_X_SIZE]; GRID_Y_SIZE]) -> io::Result<()> {
+        let mut current: Option<Colors> = None;
+
+        for (line, rows) in screen.chunks(2).enumerate() {
+            queue!(self.stdout, cursor::MoveTo(0, line as u16))?;
+            for x in 0..GRID_X_SIZE {
+                let top = self.palette.color(rows[0][x]);
+                let bottom = self.palette.color(rows.get(1).is_some_and(|row| row[x]));
+                let colors = Colors::new(to_color(top), to_color(bottom));
+                if current != Some(colors) {
+                    queue!(self.stdout, SetColors(colors))?;
+                    current = Some(colors);
+                }
+                queue!(self.stdout, Print('▀'))?;
+            }
+        }
+        queue!(self.stdout, ResetColor)?;
+        self.stdout.flush()
+    }
+}
+
+impl Audio for TerminalAudio {
+    fn set_beeping(&mut self, beeping: bool) {
+        if beeping && !self.beeping {
+            let mut stdout = io::stdout();
+            let _ = stdout.write_all(b"\x07").and_then(|_| stdout.flush());
+        }
+        self.beeping = beeping;
+    }
+}
+
+/// Same physical layout as the SDL frontend, on a QWERTY keyboard.
+fn map_key(character: char) -> Option<u8> {
+    match character.to_ascii_lowercase() {
+        '1' => Some(0x1),
+        '2' => Some(0x2),
+        '3' => Some(0x3),
+        '4' => Some(0xC),
+        'q' => Some(0x4),
+        'w' => Some(0x5),
+        'e' => Some(0x6),
+        'r' => Some(0xD),
+        'a' => Some(0x7),
+        's' => Some(0x8),
+        'd' => Some(0x9),
+        'f' => Some(0xE),
+        'z' => Some(0xA),
+        'x' => Some(0x0),
+        'c' => Some(0xB),
+        'v' => Some(0xF),
+        _ => None,
+    }
+}
+
+fn to_color((r, g, b): Rgb) -> Color {
+    Color::Rgb { r, g, b }
+}
@@ -0,0 +1,316 @@
+use crate::cpu::Cpu;
+use crate::decoder::decode_instruction;
+use crate::fault::Fault;
+use crate::memory::{Access, AccessKind};
+use clap::ValueEnum;
+use log::{info, warn};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+
+/// Starts binary trace files, followed by a format version byte.
+pub const BINARY_MAGIC: &[u8; 4] = b"C8TR";
+const BINARY_VERSION: u8 = 1;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum TraceFormat {
+    /// One JSON object per instruction and per line
+    #[default]
+    JsonLines,
+    /// Fixed-size little-endian records, see `Record::write_binary`
+    Binary,
+}
+
+/// The part of the machine state an instruction can change, besides memory and the screen.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Registers {
+    pub v: [u8; 16],
+    pub i: u16,
+    pub sp: u8,
+    pub dt: u8,
+    pub st: u8,
+}
+
+impl Registers {
+    pub fn of(cpu: &Cpu) -> Registers {
+        Registers {
+            v: *cpu.registers(),
+            i: cpu.index(),
+            sp: cpu.stack().len() as u8,
+            dt: cpu.delay_timer(),
+            st: cpu.sound_timer(),
+        }
+    }
+
+    fn write_binary(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.v);
+        out.extend_from_slice(&self.i.to_le_bytes());
+        out.extend_from_slice(&[self.sp, self.dt, self.st]);
+    }
+
+    fn to_json(self) -> String {
+        format!(
+            r#"{{"v":{:?},"i":{},"sp":{},"dt":{},"st":{}}}"#,
+            self.v, self.i, self.sp, self.dt, self.st
+        )
+    }
+}
+
+/// One executed instruction.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Record {
+    /// Number of instructions executed before this one.
+    pub cycle: u64,
+    pub pc: u16,
+    pub opcode: u16,
+    pub before: Registers,
+    pub after: Registers,
+    /// Address and new value of every byte written, including stack pushes.
+    pub writes: Vec<(u16, u8)>,
+    pub fault: Option<Fault>,
+}
+
+impl Record {
+    /// `cycle: u64, pc: u16, opcode: u16, before, after, faulted: u8, write count: u16`
+    /// then `address: u16, value: u8` per write, where registers are `V0-VF, I: u16, SP, DT, ST`.
+    pub fn write_binary(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.cycle.to_le_bytes());
+        out.extend_from_slice(&self.pc.to_le_bytes());
+        out.extend_from_slice(&self.opcode.to_le_bytes());
+        self.before.write_binary(out);
+        self.after.write_binary(out);
+        out.push(self.fault.is_some() as u8);
+        out.extend_from_slice(&(self.writes.len() as u16).to_le_bytes());
+        for (address, value) in &self.writes {
+            out.extend_from_slice(&address.to_le_bytes());
+            out.push(*value);
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        let writes: Vec<String> = self
+            .writes
+            .iter()
+            .map(|(address, value)| format!(r#"{{"address":{address},"value":{value}}}"#))
+            .collect();
+        let fault = match &self.fault {
+            Some(fault) => format!(r#","fault":"{fault}""#),
+            None => String::new(),
+        };
+        // mnemonics and fault messages are plain ASCII without quotes
+        format!(
+            r#"{{"cycle":{},"pc":{},"opcode":{},"mnemonic":"{:#}","before":{},"after":{},"writes":[{}]{fault}}}"#,
+            self.cycle,
+            self.pc,
+            self.opcode,
+            decode_instruction(self.opcode),
+            self.before.to_json(),
+            self.after.to_json(),
+            writes.join(",")
+        )
+    }
+}
+
+enum Sink {
+    Stream(BufWriter<File>),
+    /// Keeps the last records in memory, written to `path` only on a fault.
+    Ring {
+        records: VecDeque<Record>,
+        capacity: usize,
+        path: PathBuf,
+    },
+}
+
+/// Records executed instructions to a file, as they run or only the last ones
+/// before a fault.
+pub struct Tracer {
+    sink: Sink,
+    format: TraceFormat,
+    /// Only instructions at these addresses are recorded.
+    addresses: Option<RangeInclusive<usize>>,
+    cycle: u64,
+}
+
+impl Tracer {
+    /// Writes every instruction to `path` as it runs.
+    pub fn stream(path: &Path, format: TraceFormat) -> Result<Tracer, String> {
+        let mut writer = BufWriter::new(create(path)?);
+        if format == TraceFormat::Binary {
+            write_header(&mut writer).map_err(|e| e.to_string())?;
+        }
+        Ok(Tracer {
+            sink: Sink::Stream(writer),
+            format,
+            addresses: None,
+            cycle: 0,
+        })
+    }
+
+    /// Keeps the last `capacity` instructions and writes them to `path` when a fault occurs.
+    pub fn ring(path: &Path, format: TraceFormat, capacity: usize) -> Tracer {
+        Tracer {
+            sink: Sink::Ring {
+                records: VecDeque::with_capacity(capacity),
+                capacity: capacity.max(1),
+                path: path.to_path_buf(),
+            },
+            format,
+            addresses: None,
+            cycle: 0,
+        }
+    }
+
+    pub fn with_addresses(mut self, addresses: RangeInclusive<usize>) -> Tracer {
+        self.addresses = Some(addresses);
+        self
+    }
+
+    /// Called after each instruction with the state from before it ran and
+    /// the memory accesses it performed.
+    pub fn record(
+        &mut self,
+        pc: u16,
+        opcode: u16,
+        before: Registers,
+        cpu: &Cpu,
+        accesses: &[Access],
+        fault: Option<Fault>,
+    ) -> Result<(), String> {
+        let cycle = self.cycle;
+        self.cycle += 1;
+        if let Some(addresses) = &self.addresses {
+            if !addresses.contains(&(pc as usize)) {
+                return Ok(());
+            }
+        }
+
+        let record = Record {
+            cycle,
+            pc,
+            opcode,
+            before,
+            after: Registers::of(cpu),
+            writes: accesses
+                .iter()
+                .filter(|access| access.kind == AccessKind::Write)
+                .map(|access| (access.address as u16, access.value))
+                .collect(),
+            fault,
+        };
+
+        match &mut self.sink {
+            Sink::Stream(writer) => {
+                write_record(writer, self.format, &record).map_err(|e| e.to_string())?;
+                if fault.is_some() {
+                    writer.flush().map_err(|e| e.to_string())?;
+                }
+            }
+            Sink::Ring {
+                records,
+                capacity,
+                path,
+            } => {
+                if records.len() == *capacity {
+                    records.pop_front();
+                }
+                records.push_back(record);
+                if fault.is_some() {
+                    let mut writer = BufWriter::new(create(path)?);
+                    dump(&mut writer, self.format, records.iter()).map_err(|e| e.to_string())?;
+                    info!(
+                        "wrote the last {} instructions to {}",
+                        records.len(),
+                        path.display()
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Tracer {
+    fn drop(&mut self) {
+        if let Sink::Stream(writer) = &mut self.sink {
+            if let Err(e) = writer.flush() {
+                warn!("can't write the trace: {e}");
+            }
+        }
+    }
+}
+
+fn create(path: &Path) -> Result<File, String> {
+    File::create(path).map_err(|e| format!("can't create {}: {e}", path.display()))
+}
+
+fn write_header(writer: &mut impl Write) -> std::io::Result<()> {
+    writer.write_all(BINARY_MAGIC)?;
+    writer.write_all(&[BINARY_VERSION])
+}
+
+fn write_record(
+    writer: &mut impl Write,
+    format: TraceFormat,
+    record: &Record,
+) -> std::io::Result<()> {
+    match format {
+        TraceFormat::JsonLines => writeln!(writer, "{}", record.to_json()),
+        TraceFormat::Binary => {
+            let mut bytes = vec![];
+            record.write_binary(&mut bytes);
+            writer.write_all(&bytes)
+        }
+    }
+}
+
+fn dump<'a>(
+    writer: &mut impl Write,
+    format: TraceFormat,
+    records: impl IntoIterator<Item = &'a Record>,
+) -> std::io::Result<()> {
+    if format == TraceFormat::Binary {
+        write_header(writer)?;
+    }
+    for record in records {
+        write_record(writer, format, record)?;
+    }
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cpu::new;
+    use crate::debugger::Debugger;
+    use crate::fault::Fault;
+    use crate::trace::{TraceFormat, Tracer};
+    use std::fs;
+
+    #[test]
+    fn dumps_the_last_instructions_on_a_fault() {
+        let path =
+            std::env::temp_dir().join(format!("chip8-trace-test-{}.jsonl", std::process::id()));
+        let mut cpu = new();
+        // LD V0, 1 then LD V1, 2 then LD I, 0x300 then LD [I], V1 then RET
+        cpu.load_rom_bytes(&[0x60, 0x01, 0x61, 0x02, 0xA3, 0x00, 0xF1, 0x55, 0x00, 0xEE])
+            .unwrap();
+        let mut debugger = Debugger::default();
+        debugger.set_tracer(
+            Tracer::ring(&path, TraceFormat::JsonLines, 2).with_addresses(0x202..=0x20F),
+        );
+
+        let fault = (0..5).find_map(|_| debugger.step(&mut cpu).err());
+        assert_eq!(fault, Some(Fault::StackUnderflow));
+
+        let trace = fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = trace.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with(r#"{"cycle":3,"pc":518,"opcode":61781,"#));
+        assert!(
+            lines[0].contains(r#""writes":[{"address":768,"value":1},{"address":769,"value":2}]"#)
+        );
+        assert!(lines[1].ends_with(r#""fault":"return from subroutine with an empty call stack"}"#));
+        fs::remove_file(&path).unwrap();
+    }
+}